@@ -0,0 +1,673 @@
+use clap::{Args, Subcommand};
+use regex::Regex;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::privacy::PrivacyArgs;
+use crate::snowflake::Snowflake;
+use crate::{db, duplicates, emoji, spam, SimpleResult};
+
+#[derive(Debug, Subcommand)]
+pub enum AnalyzeCommand {
+    /// List the most used words and phrases
+    Words(WordsArgs),
+    /// Count messages per day, or per hour of each day of the week, as CSV
+    Activity(ActivityArgs),
+    /// Count the emoji used in messages and reactions, per channel and per user
+    Emoji(EmojiArgs),
+    /// Flag users who look like spammers or bots in the flagged_user table
+    Spam(spam::SpamArgs),
+    /// Group copy-pasted and near-identical messages across channels, as CSV
+    Duplicates(duplicates::DuplicatesArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WordsArgs {
+    /// Only count messages in this channel
+    #[clap(long)]
+    channel: Option<Snowflake>,
+
+    /// Only count messages by this user
+    #[clap(long)]
+    author: Option<Snowflake>,
+
+    /// How many words and phrases to list
+    #[clap(long, default_value_t = 25, value_name = "N")]
+    top: usize,
+
+    /// Also list phrases of two up to this many words, 1 for words only
+    #[clap(long, default_value_t = 3, value_name = "N")]
+    ngrams: usize,
+
+    /// Count common words like "the" and "and" too
+    #[clap(long)]
+    keep_stopwords: bool,
+
+    /// Output file, defaults to stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    #[clap(flatten)]
+    privacy: PrivacyArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct EmojiArgs {
+    /// Only count messages in this channel
+    #[clap(long)]
+    channel: Option<Snowflake>,
+
+    /// Only count messages by this user, and the reactions to them
+    #[clap(long)]
+    author: Option<Snowflake>,
+
+    /// How many emoji, channels and users to list
+    #[clap(long, default_value_t = 25, value_name = "N")]
+    top: usize,
+
+    /// Output file, defaults to stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    #[clap(flatten)]
+    privacy: PrivacyArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ActivityArgs {
+    /// Only count messages in this channel
+    #[clap(long)]
+    channel: Option<Snowflake>,
+
+    /// Only count messages by this user
+    #[clap(long)]
+    author: Option<Snowflake>,
+
+    /// Count messages per hour of day and day of week instead of per day
+    #[clap(long)]
+    heatmap: bool,
+
+    /// Also draw a chart into this .png or .svg file (needs the `charts` feature)
+    #[clap(long, value_name = "PATH")]
+    chart: Option<String>,
+
+    /// Output file, defaults to stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    #[clap(flatten)]
+    privacy: PrivacyArgs,
+}
+
+pub fn run(db_path: &str, cmd: AnalyzeCommand) -> SimpleResult<()> {
+    let (report, output) = match cmd {
+        AnalyzeCommand::Words(args) => {
+            let conn = db::open_read_only(db_path)?;
+            (words_report(&conn, &args)?, args.output)
+        }
+        AnalyzeCommand::Activity(args) => {
+            let conn = db::open_read_only(db_path)?;
+            (activity_report(&conn, &args)?, args.output)
+        }
+        AnalyzeCommand::Emoji(args) => {
+            let conn = db::open_read_only(db_path)?;
+            (emoji_report(&conn, &args)?, args.output)
+        }
+        AnalyzeCommand::Duplicates(args) => {
+            let conn = db::open_read_only(db_path)?;
+            (duplicates::report(&conn, &args)?, args.output)
+        }
+        // Flags are written to the database rather than reported.
+        AnalyzeCommand::Spam(args) => return spam::run(&mut db::open_db(db_path)?, args),
+    };
+    match output {
+        Some(path) => std::fs::write(path, report)?,
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+/// Words too common to say anything about a conversation.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "but", "by", "can", "could", "did", "do", "does",
+    "doing", "don't", "for", "from", "get", "got", "had", "has", "have", "having", "he", "her",
+    "here", "him", "his", "how", "i", "i'm", "i've", "if", "in", "into", "is", "it", "it's", "its",
+    "just", "like", "me", "more", "my", "no", "not", "now", "of", "on", "one", "only", "or",
+    "other", "our", "out", "over", "own", "really", "same", "she", "should", "so", "some", "such",
+    "than", "that", "that's", "the", "their", "them", "then", "there", "these", "they", "this",
+    "those", "through", "to", "too", "up", "us", "very", "was", "we", "were", "what", "when",
+    "where", "which", "while", "who", "why", "will", "with", "would", "you", "your",
+];
+
+/// Counts words and phrases of the selected messages.
+struct Counts {
+    messages: i64,
+    words: i64,
+    /// Index 0 holds single words, 1 pairs of words and so on.
+    ngrams: Vec<HashMap<String, i64>>,
+}
+
+fn count_words(conn: &rusqlite::Connection, args: &WordsArgs) -> SimpleResult<Counts> {
+    // Mentions, custom emoji, timestamps and links aren't words.
+    let markup = Regex::new(r"<(a?:\w+:|[@#][!&]?|t:)\d+(:\w)?>|https?://\S+").unwrap();
+    let mut counts = Counts {
+        messages: 0,
+        words: 0,
+        ngrams: vec![HashMap::new(); args.ngrams.max(1)],
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT content FROM message
+         WHERE (?1 IS NULL OR channel_id = ?1) AND (?2 IS NULL OR author_id = ?2)",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![args.channel, args.author])?;
+    while let Some(row) = rows.next()? {
+        let content: String = row.get(0)?;
+        let content = markup
+            .replace_all(&content, " ")
+            .to_lowercase()
+            .replace('’', "'");
+        let tokens = tokenize(&content);
+        counts.messages += 1;
+        counts.words += tokens.len() as i64;
+
+        for (n, ngrams) in counts.ngrams.iter_mut().enumerate() {
+            for window in tokens.windows(n + 1) {
+                // Stopwords may sit inside a phrase ("point of view") but not at its edges.
+                let edges = [window[0], window[n]];
+                if !args.keep_stopwords && edges.iter().any(|w| STOPWORDS.contains(w)) {
+                    continue;
+                }
+                *ngrams.entry(window.join(" ")).or_default() += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Splits lowercased text into words, keeping apostrophes inside them and dropping numbers.
+fn tokenize(text: &str) -> Vec<&str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .map(|word| word.trim_matches('\''))
+        .filter(|word| !word.is_empty() && !word.chars().all(|c| c.is_numeric()))
+        .collect()
+}
+
+fn words_report(conn: &rusqlite::Connection, args: &WordsArgs) -> SimpleResult<String> {
+    let privacy = &args.privacy;
+    privacy.check()?;
+    let counts = count_words(conn, args)?;
+
+    let mut report = String::new();
+    writeln!(report, "# Word Frequency")?;
+    writeln!(report)?;
+    if let Some(channel) = args.channel {
+        writeln!(report, "- Channel: {}", channel)?;
+    }
+    if let Some(author) = args.author {
+        writeln!(report, "- Author: {}", author)?;
+    }
+    writeln!(report, "- Messages: {}", privacy.count(counts.messages))?;
+    writeln!(report, "- Words: {}", privacy.count(counts.words))?;
+    if !args.keep_stopwords {
+        writeln!(report, "- Common words left out")?;
+    }
+    if privacy.is_enabled() {
+        writeln!(report, "- Published counts: {}", privacy.describe())?;
+    }
+
+    for (n, ngrams) in counts.ngrams.into_iter().enumerate() {
+        // A phrase used once is no phrase.
+        let mut top: Vec<(String, i64)> = ngrams
+            .into_iter()
+            .filter(|(_, count)| n == 0 || *count > 1)
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(args.top);
+
+        writeln!(report)?;
+        match n {
+            0 => writeln!(report, "## Words")?,
+            _ => writeln!(report, "## Phrases of {} Words", n + 1)?,
+        }
+        writeln!(report)?;
+        if top.is_empty() {
+            writeln!(report, "None.")?;
+            continue;
+        }
+        writeln!(report, "| Rank | Term | Count |")?;
+        writeln!(report, "|---|---|---|")?;
+        for (rank, (term, count)) in top.iter().enumerate() {
+            writeln!(
+                report,
+                "| {} | {} | {} |",
+                rank + 1,
+                term,
+                privacy.count(*count)
+            )?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// How often each emoji was used in the selected messages and reactions.
+#[derive(Default)]
+struct EmojiCounts {
+    messages: i64,
+    /// Per emoji, how often it was written in messages and how often reacted with.
+    emoji: HashMap<String, (i64, i64)>,
+    channels: HashMap<Snowflake, Usage>,
+    users: HashMap<Snowflake, Usage>,
+}
+
+/// The emoji a channel or user used.
+#[derive(Default)]
+struct Usage {
+    name: String,
+    total: i64,
+    emoji: HashMap<String, i64>,
+}
+
+impl Usage {
+    fn add(&mut self, emoji: &str, count: i64) {
+        self.total += count;
+        *self.emoji.entry(emoji.to_string()).or_default() += count;
+    }
+
+    fn most_used(&self) -> &str {
+        self.emoji
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map_or("", |(emoji, _)| emoji.as_str())
+    }
+}
+
+fn count_emoji(conn: &rusqlite::Connection, args: &EmojiArgs) -> SimpleResult<EmojiCounts> {
+    let mut counts = EmojiCounts::default();
+
+    let mut stmt = conn.prepare(
+        "SELECT m.channel_id, c.name, m.author_id, u.username, m.content
+         FROM message m
+         JOIN channel c ON c.id = m.channel_id
+         JOIN user u ON u.id = m.author_id
+         WHERE (?1 IS NULL OR m.channel_id = ?1) AND (?2 IS NULL OR m.author_id = ?2)",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![args.channel, args.author])?;
+    while let Some(row) = rows.next()? {
+        counts.messages += 1;
+        let found = emoji::find(&row.get::<_, String>(4)?);
+        if found.is_empty() {
+            continue;
+        }
+        let channel = counts.channels.entry(row.get(0)?).or_default();
+        channel.name = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+        let user = counts.users.entry(row.get(2)?).or_default();
+        user.name = row.get(3)?;
+        for emoji in found {
+            channel.add(&emoji, 1);
+            user.add(&emoji, 1);
+            counts.emoji.entry(emoji).or_default().0 += 1;
+        }
+    }
+
+    // Reactions only have counts, not who reacted, so they count towards channels only.
+    let mut stmt = conn.prepare(
+        "SELECT m.channel_id, c.name, r.emoji, r.count
+         FROM message_reaction r
+         JOIN message m ON m.id = r.message_id
+         JOIN channel c ON c.id = m.channel_id
+         WHERE (?1 IS NULL OR m.channel_id = ?1) AND (?2 IS NULL OR m.author_id = ?2)",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![args.channel, args.author])?;
+    while let Some(row) = rows.next()? {
+        let emoji = emoji::key(&row.get::<_, String>(2)?);
+        let count: i64 = row.get(3)?;
+        let channel = counts.channels.entry(row.get(0)?).or_default();
+        channel.name = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+        channel.add(&emoji, count);
+        counts.emoji.entry(emoji).or_default().1 += count;
+    }
+    Ok(counts)
+}
+
+/// Custom emoji are counted as `name:id` and shown as `:name:`.
+fn emoji_name(emoji: &str) -> String {
+    match emoji.split_once(':') {
+        Some((name, _)) if !name.is_empty() => format!(":{}:", name),
+        Some((_, id)) => format!("deleted emoji {}", id),
+        None => emoji.to_string(),
+    }
+}
+
+fn emoji_report(conn: &rusqlite::Connection, args: &EmojiArgs) -> SimpleResult<String> {
+    let privacy = &args.privacy;
+    privacy.check()?;
+    let counts = count_emoji(conn, args)?;
+
+    let mut report = String::new();
+    writeln!(report, "# Emoji Usage")?;
+    writeln!(report)?;
+    if let Some(channel) = args.channel {
+        writeln!(report, "- Channel: {}", channel)?;
+    }
+    if let Some(author) = args.author {
+        writeln!(report, "- Author: {}", author)?;
+    }
+    let (written, reacted) = counts
+        .emoji
+        .values()
+        .fold((0, 0), |(w, r), (messages, reactions)| {
+            (w + messages, r + reactions)
+        });
+    writeln!(report, "- Messages: {}", privacy.count(counts.messages))?;
+    writeln!(report, "- Emoji in messages: {}", privacy.count(written))?;
+    writeln!(report, "- Reactions: {}", privacy.count(reacted))?;
+    if privacy.is_enabled() {
+        writeln!(report, "- Published counts: {}", privacy.describe())?;
+    }
+
+    let mut emoji: Vec<(&String, &(i64, i64))> = counts.emoji.iter().collect();
+    emoji.sort_by(|a, b| {
+        let total = |(messages, reactions): &(i64, i64)| messages + reactions;
+        total(b.1).cmp(&total(a.1)).then_with(|| a.0.cmp(b.0))
+    });
+    emoji.truncate(args.top);
+    writeln!(report)?;
+    writeln!(report, "## Emoji")?;
+    writeln!(report)?;
+    if emoji.is_empty() {
+        writeln!(report, "None.")?;
+    } else {
+        writeln!(
+            report,
+            "| Rank | Emoji | In Messages | As Reactions | Total |"
+        )?;
+        writeln!(report, "|---|---|---|---|---|")?;
+        for (rank, (emoji, (messages, reactions))) in emoji.iter().enumerate() {
+            // The total is published with its parts, so it's made up of their noised values.
+            let (messages, reactions) = (privacy.noise(*messages), privacy.noise(*reactions));
+            writeln!(
+                report,
+                "| {} | {} | {} | {} | {} |",
+                rank + 1,
+                emoji_name(emoji),
+                privacy.show(messages),
+                privacy.show(reactions),
+                privacy.show(messages + reactions)
+            )?;
+        }
+    }
+
+    for (title, usage) in [("Channels", &counts.channels), ("Users", &counts.users)] {
+        let mut top: Vec<&Usage> = usage.values().collect();
+        top.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        top.truncate(args.top);
+        writeln!(report)?;
+        writeln!(report, "## {}", title)?;
+        writeln!(report)?;
+        if top.is_empty() {
+            writeln!(report, "None.")?;
+            continue;
+        }
+        writeln!(report, "| Rank | Name | Emoji | Most Used |")?;
+        writeln!(report, "|---|---|---|---|")?;
+        for (rank, usage) in top.iter().enumerate() {
+            writeln!(
+                report,
+                "| {} | {} | {} | {} |",
+                rank + 1,
+                usage.name,
+                privacy.count(usage.total),
+                emoji_name(usage.most_used())
+            )?;
+        }
+    }
+
+    Ok(report)
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Messages per hour of day, by day of week starting on Monday, in the --timezone.
+pub type Heatmap = [[i64; 24]; 7];
+
+fn activity_report(conn: &rusqlite::Connection, args: &ActivityArgs) -> SimpleResult<String> {
+    if args.chart.is_some() && !cfg!(feature = "charts") {
+        return Err("Charts need a build with the `charts` feature".into());
+    }
+    let privacy = &args.privacy;
+    privacy.check()?;
+    // Counts are noised once, so the chart shows the same ones as the CSV.
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if args.heatmap {
+        let heatmap = count_heatmap(conn, args)?.map(|hours| hours.map(|n| privacy.noise(n)));
+        let mut header = vec!["day".to_string()];
+        header.extend((0..24).map(|hour| hour.to_string()));
+        writer.write_record(&header)?;
+        for (day, hours) in WEEKDAYS.iter().zip(&heatmap) {
+            let mut record = vec![day.to_string()];
+            record.extend(hours.iter().map(|count| privacy.show(*count)));
+            writer.write_record(&record)?;
+        }
+        if let Some(path) = &args.chart {
+            chart::heatmap(path, &heatmap.map(|hours| hours.map(|n| privacy.plot(n))))?;
+        }
+    } else {
+        let days: Vec<(String, i64)> = count_days(conn, args)?
+            .into_iter()
+            .map(|(date, n)| (date, privacy.noise(n)))
+            .collect();
+        writer.write_record(["date", "messages"])?;
+        for (date, count) in &days {
+            writer.write_record([date, &privacy.show(*count)])?;
+        }
+        if let Some(path) = &args.chart {
+            let plotted: Vec<(String, i64)> = days
+                .iter()
+                .map(|(date, n)| (date.clone(), privacy.plot(*n)))
+                .collect();
+            chart::daily(path, &plotted)?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Messages on every day from the first selected message to the last, quiet days included.
+fn count_days(
+    conn: &rusqlite::Connection,
+    args: &ActivityArgs,
+) -> SimpleResult<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE
+            selected AS (
+                SELECT date(local(created_at_unix), 'unixepoch') AS day FROM message
+                WHERE (?1 IS NULL OR channel_id = ?1) AND (?2 IS NULL OR author_id = ?2)),
+            days(day) AS (
+                SELECT MIN(day) FROM selected
+                UNION ALL
+                SELECT date(day, '+1 day') FROM days WHERE day < (SELECT MAX(day) FROM selected))
+         SELECT days.day, COUNT(selected.day) FROM days
+         LEFT JOIN selected ON selected.day = days.day
+         WHERE days.day IS NOT NULL
+         GROUP BY days.day
+         ORDER BY days.day",
+    )?;
+    let days = stmt
+        .query_map(rusqlite::params![args.channel, args.author], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(days)
+}
+
+fn count_heatmap(conn: &rusqlite::Connection, args: &ActivityArgs) -> SimpleResult<Heatmap> {
+    let mut stmt = conn.prepare(
+        "SELECT CAST(strftime('%w', local(created_at_unix), 'unixepoch') AS INTEGER),
+                CAST(strftime('%H', local(created_at_unix), 'unixepoch') AS INTEGER),
+                COUNT(*)
+         FROM message
+         WHERE (?1 IS NULL OR channel_id = ?1) AND (?2 IS NULL OR author_id = ?2)
+         GROUP BY 1, 2",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![args.channel, args.author])?;
+    let mut heatmap = [[0; 24]; 7];
+    while let Some(row) = rows.next()? {
+        let (weekday, hour): (usize, usize) = (row.get(0)?, row.get(1)?);
+        // SQLite counts days of the week from Sunday.
+        heatmap[(weekday + 6) % 7][hour] = row.get(2)?;
+    }
+    Ok(heatmap)
+}
+
+#[cfg(feature = "charts")]
+mod chart {
+    use plotters::coord::Shift;
+    use plotters::prelude::*;
+
+    use super::{Heatmap, WEEKDAYS};
+    use crate::SimpleResult;
+
+    const SIZE: (u32, u32) = (1200, 600);
+    const COLOR: RGBColor = RGBColor(31, 119, 180);
+
+    enum Format {
+        Png,
+        Svg,
+    }
+
+    fn format(path: &str) -> SimpleResult<Format> {
+        match path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+            Some(ext) if ext == "png" => Ok(Format::Png),
+            Some(ext) if ext == "svg" => Ok(Format::Svg),
+            _ => {
+                let err_msg = format!("Charts are drawn as .png or .svg files, not {}", path);
+                Err(err_msg.into())
+            }
+        }
+    }
+
+    pub fn daily(path: &str, days: &[(String, i64)]) -> SimpleResult<()> {
+        match format(path)? {
+            Format::Png => draw_daily(&BitMapBackend::new(path, SIZE).into_drawing_area(), days)?,
+            Format::Svg => draw_daily(&SVGBackend::new(path, SIZE).into_drawing_area(), days)?,
+        }
+        Ok(())
+    }
+
+    pub fn heatmap(path: &str, heatmap: &Heatmap) -> SimpleResult<()> {
+        match format(path)? {
+            Format::Png => {
+                draw_heatmap(&BitMapBackend::new(path, SIZE).into_drawing_area(), heatmap)?
+            }
+            Format::Svg => draw_heatmap(&SVGBackend::new(path, SIZE).into_drawing_area(), heatmap)?,
+        }
+        Ok(())
+    }
+
+    fn draw_daily<DB: DrawingBackend>(
+        root: &DrawingArea<DB, Shift>,
+        days: &[(String, i64)],
+    ) -> SimpleResult<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+        let most = days.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Messages per Day", ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(0..days.len().max(1), 0..most + most / 10 + 1)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(8)
+            .x_label_formatter(&|i| days.get(*i).map(|d| d.0.clone()).unwrap_or_default())
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            days.iter().enumerate().map(|(i, (_, count))| (i, *count)),
+            &COLOR,
+        ))?;
+        root.present()?;
+        Ok(())
+    }
+
+    fn draw_heatmap<DB: DrawingBackend>(
+        root: &DrawingArea<DB, Shift>,
+        heatmap: &Heatmap,
+    ) -> SimpleResult<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+        let most = heatmap.iter().flatten().copied().max().unwrap_or(0).max(1);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Messages per Hour (UTC)", ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(100)
+            .build_cartesian_2d((0..23).into_segmented(), (0..6).into_segmented())?;
+        // Labels go in the middle of each cell, and Monday is drawn at the top.
+        let hour_label = |hour: &SegmentValue<i32>| match hour {
+            SegmentValue::CenterOf(hour) => format!("{:02}", hour),
+            _ => String::new(),
+        };
+        let day_label = |row: &SegmentValue<i32>| match row {
+            SegmentValue::CenterOf(row @ 0..=6) => WEEKDAYS[6 - *row as usize].to_string(),
+            _ => String::new(),
+        };
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_labels(24)
+            .y_labels(7)
+            .x_label_formatter(&hour_label)
+            .y_label_formatter(&day_label)
+            .draw()?;
+        chart.draw_series(heatmap.iter().enumerate().flat_map(|(day, hours)| {
+            let row = 6 - day as i32;
+            hours.iter().enumerate().map(move |(hour, count)| {
+                let share = *count as f64 / most as f64;
+                // From white for silence to the full color for the busiest hour.
+                let shade = |c: u8| (255.0 - (255.0 - c as f64) * share) as u8;
+                let color = RGBColor(shade(COLOR.0), shade(COLOR.1), shade(COLOR.2));
+                let hour = hour as i32;
+                Rectangle::new(
+                    [
+                        (SegmentValue::Exact(hour), SegmentValue::Exact(row)),
+                        (SegmentValue::Exact(hour + 1), SegmentValue::Exact(row + 1)),
+                    ],
+                    color.filled(),
+                )
+            })
+        }))?;
+        root.present()?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "charts"))]
+mod chart {
+    use super::Heatmap;
+    use crate::SimpleResult;
+
+    pub fn daily(_path: &str, _days: &[(String, i64)]) -> SimpleResult<()> {
+        unreachable!("charts aren't drawn without the `charts` feature")
+    }
+
+    pub fn heatmap(_path: &str, _heatmap: &Heatmap) -> SimpleResult<()> {
+        unreachable!("charts aren't drawn without the `charts` feature")
+    }
+}
@@ -0,0 +1,111 @@
+use regex::{NoExpand, Regex};
+use sha2::{Digest, Sha256};
+
+use std::path::Path;
+
+use crate::snowflake::Snowflake;
+use crate::{Channel, Message, SimpleResult, User};
+
+/// What user mentions in anonymized content are replaced with.
+pub const MENTION: &str = "@user";
+
+/// Replaces user identities with salted hashes before they are stored. The salt lives
+/// in its own file so the database can be shared without it; the same salt always
+/// maps a user to the same pseudonym, keeping conversations followable across runs.
+/// Mentions are stripped rather than pseudonymized, so who mentioned whom can't be linked.
+pub struct Anonymizer {
+    salt: Vec<u8>,
+    mention: Regex,
+}
+
+impl Anonymizer {
+    /// Reads the salt from `salt_path`, creating a new random one if the file doesn't exist.
+    pub fn load(salt_path: &str) -> SimpleResult<Self> {
+        let salt = if Path::new(salt_path).exists() {
+            let hex = std::fs::read_to_string(salt_path)?;
+            let hex = hex.trim();
+            if hex.is_empty() || hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                let err_msg = format!("{} does not contain a valid salt", salt_path);
+                return Err(err_msg.into());
+            }
+            hex.as_bytes()
+                .chunks(2)
+                .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16))
+                .collect::<Result<Vec<u8>, _>>()?
+        } else {
+            let salt = rand::random::<[u8; 32]>().to_vec();
+            if let Some(parent) = Path::new(salt_path).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(salt_path, to_hex(&salt))?;
+            info!(
+                "Created anonymization salt {}, keep it apart from the database",
+                salt_path
+            );
+            salt
+        };
+
+        Ok(Anonymizer {
+            salt,
+            mention: Regex::new(r"<@!?\d+>").unwrap(),
+        })
+    }
+
+    fn hash(&self, id: Snowflake) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.salt);
+        hasher.update(id.to_string());
+        hasher.finalize().into()
+    }
+
+    /// A stable pseudonymous ID, kept positive like real snowflakes.
+    fn user_id(&self, id: Snowflake) -> Snowflake {
+        let hash = self.hash(id);
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        Snowflake(i64::from_be_bytes(bytes) & i64::MAX)
+    }
+
+    fn username(&self, id: Snowflake) -> String {
+        format!("user-{}", to_hex(&self.hash(id)[..6]))
+    }
+
+    pub fn user(&self, user: User) -> User {
+        User {
+            id: self.user_id(user.id),
+            username: self.username(user.id),
+            discriminator: None,
+            global_name: None,
+        }
+    }
+
+    /// Anonymizes the author and strips the user mentions in the content.
+    pub fn message(&self, message: Message) -> Message {
+        Message {
+            author: self.user(message.author),
+            content: self.content(&message.content),
+            ..message
+        }
+    }
+
+    /// Replaces user mentions with the same placeholder whoever they mention.
+    pub fn content(&self, content: &str) -> String {
+        self.mention
+            .replace_all(content, NoExpand(MENTION))
+            .into_owned()
+    }
+
+    /// Anonymizes the members targeted by permission overwrites; roles are left as they are.
+    pub fn channel(&self, mut channel: Channel) -> Channel {
+        for overwrite in &mut channel.permission_overwrites {
+            if overwrite.kind == 1 {
+                overwrite.id = self.user_id(overwrite.id);
+            }
+        }
+        channel
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
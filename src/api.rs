@@ -0,0 +1,127 @@
+use reqwest::header::AUTHORIZATION;
+use serde::Deserialize;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::{SimpleResult, BASE_URL};
+
+/// What requests to Discord go through, so they can be answered by something other than
+/// Discord, such as canned responses in tests.
+pub trait ApiClient: Sync {
+    /// Sends a GET request to `url` with the given `Authorization` header.
+    fn get(&self, url: &str, authorization: &str) -> SimpleResult<ApiResponse>;
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiResponse {
+    pub status: u16,
+    /// Keyed by lowercase name.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl ApiResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+impl ApiClient for reqwest::blocking::Client {
+    fn get(&self, url: &str, authorization: &str) -> SimpleResult<ApiResponse> {
+        let res = reqwest::blocking::Client::get(self, url)
+            .header(AUTHORIZATION, authorization)
+            .send()?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Ok(ApiResponse {
+            status,
+            headers,
+            body: res.text()?,
+        })
+    }
+}
+
+/// Answers requests with canned responses, in the order they were added for each path.
+/// Paths are relative to the API, e.g. `/channels/1/messages?limit=100`.
+#[derive(Default)]
+pub struct ReplayClient {
+    responses: Mutex<Vec<(String, VecDeque<ApiResponse>)>>,
+    requests: Mutex<Vec<String>>,
+}
+
+/// A response to replay, as stored in a replay file.
+#[derive(Deserialize)]
+struct Recorded {
+    path: String,
+    #[serde(flatten)]
+    response: ApiResponse,
+}
+
+impl ReplayClient {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads responses from a JSON file holding a list of objects with the `path` they
+    /// answer and their `status`, `headers` and `body`.
+    pub fn from_file(path: &str) -> SimpleResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let recorded: Vec<Recorded> = serde_json::from_str(&text)?;
+        let client = Self::new();
+        for r in recorded {
+            client.push(&r.path, r.response);
+        }
+        Ok(client)
+    }
+
+    /// Queues `response` as the answer to the next request to `path`.
+    pub fn push(&self, path: &str, response: ApiResponse) {
+        let mut responses = self.responses.lock().unwrap();
+        match responses.iter_mut().find(|(p, _)| p == path) {
+            Some((_, queue)) => queue.push_back(response),
+            None => responses.push((path.to_string(), VecDeque::from([response]))),
+        }
+    }
+
+    /// Queues a response with `status` and a JSON `body`.
+    pub fn push_json(&self, path: &str, status: u16, body: serde_json::Value) {
+        self.push(
+            path,
+            ApiResponse {
+                status,
+                headers: HashMap::new(),
+                body: body.to_string(),
+            },
+        );
+    }
+
+    /// The paths requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl ApiClient for ReplayClient {
+    fn get(&self, url: &str, _authorization: &str) -> SimpleResult<ApiResponse> {
+        let path = url.strip_prefix(BASE_URL).unwrap_or(url);
+        self.requests.lock().unwrap().push(path.to_string());
+        let mut responses = self.responses.lock().unwrap();
+        match responses
+            .iter_mut()
+            .find(|(p, _)| p == path)
+            .and_then(|(_, queue)| queue.pop_front())
+        {
+            Some(response) => Ok(response),
+            None => {
+                let err_msg = format!("No response left to replay for {}", path);
+                Err(err_msg.into())
+            }
+        }
+    }
+}
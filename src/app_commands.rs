@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiClient;
+use crate::snowflake::Snowflake;
+use crate::{send_request, SimpleResult, BASE_URL};
+
+/// Every application command registered in a guild, across all of its bots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApplicationCommandIndex {
+    applications: Vec<Application>,
+    application_commands: Vec<ApplicationCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Application {
+    id: Snowflake,
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApplicationCommand {
+    id: Snowflake,
+    application_id: Snowflake,
+    name: String,
+    description: String,
+    #[serde(rename = "type")]
+    kind: Option<i64>,
+    options: Option<serde_json::Value>,
+}
+
+/// Fetches and stores the application commands registered in a guild, so interaction
+/// messages can still be interpreted after the bots that handled them are gone.
+pub fn archive_app_commands(
+    conn: &mut rusqlite::Connection,
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+) -> SimpleResult<()> {
+    let index = get_app_command_index(client, guild_id)?;
+    info!(
+        "Inserting {} Application Commands from {} Applications",
+        index.application_commands.len(),
+        index.applications.len()
+    );
+
+    let tx = conn.transaction()?;
+    for app in &index.applications {
+        tx.execute(
+            "INSERT OR REPLACE INTO application (id, name, description) VALUES (?,?,?)",
+            rusqlite::params![app.id, app.name, app.description],
+        )?;
+    }
+    for cmd in &index.application_commands {
+        tx.execute(
+            "INSERT OR REPLACE INTO application_command
+                (id, application_id, guild_id, name, description, type, options)
+             VALUES (?,?,?,?,?,?,?)",
+            rusqlite::params![
+                cmd.id,
+                cmd.application_id,
+                guild_id,
+                cmd.name,
+                cmd.description,
+                cmd.kind,
+                cmd.options.as_ref().map(|o| o.to_string()),
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn get_app_command_index(
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+) -> SimpleResult<ApplicationCommandIndex> {
+    let req_url = format!("{}/guilds/{}/application-command-index", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let index: ApplicationCommandIndex = serde_json::from_str(&body)?;
+    Ok(index)
+}
@@ -0,0 +1,104 @@
+use clap::Args;
+
+use std::path::Path;
+
+use crate::cutoff::Cutoff;
+use crate::db;
+use crate::SimpleResult;
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    /// Move messages older than this age (e.g. 365d) or date (e.g. 2021-01-01)
+    #[clap(long)]
+    older_than: Cutoff,
+
+    /// Directory holding the monthly archive databases (`YYYY-MM.db`)
+    #[clap(long)]
+    out_dir: String,
+}
+
+/// Moves old messages into one database per month. Messages that belong to a case
+/// stay in the working database so cases remain complete.
+pub fn run(db_path: &str, args: ArchiveArgs) -> SimpleResult<()> {
+    let mut conn = db::open_db(db_path)?;
+    conn.execute(
+        "CREATE TEMP TABLE archive_message AS
+         SELECT id, strftime('%Y-%m', created_at_unix, 'unixepoch') AS month
+         FROM message
+         WHERE created_at_unix < ? AND id NOT IN (SELECT message_id FROM case_message)",
+        [args.older_than.0],
+    )?;
+
+    let months: Vec<String> = conn
+        .prepare("SELECT DISTINCT month FROM archive_message ORDER BY month")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    std::fs::create_dir_all(&args.out_dir)?;
+    for month in months {
+        let archive_path = Path::new(&args.out_dir).join(format!("{}.db", month));
+        drop(db::connect_db(&archive_path)?);
+        db::attach(&conn, &archive_path.to_string_lossy(), "archive")?;
+
+        // Attached WAL databases don't commit atomically together, so the copy is
+        // committed first and only messages confirmed in the archive are deleted.
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TEMP TABLE archive_month AS SELECT id FROM archive_message WHERE month = ?",
+            [&month],
+        )?;
+        tx.execute_batch(
+            "INSERT OR IGNORE INTO archive.guild SELECT * FROM guild WHERE id IN (
+                SELECT guild_id FROM channel WHERE id IN (
+                    SELECT channel_id FROM message WHERE id IN (SELECT id FROM archive_month)));
+            INSERT OR IGNORE INTO archive.channel SELECT * FROM channel WHERE id IN (
+                SELECT channel_id FROM message WHERE id IN (SELECT id FROM archive_month));
+            INSERT OR IGNORE INTO archive.user SELECT * FROM user WHERE id IN (
+                SELECT author_id FROM message WHERE id IN (SELECT id FROM archive_month));
+            INSERT OR IGNORE INTO archive.message SELECT * FROM message
+                WHERE id IN (SELECT id FROM archive_month);
+            INSERT OR REPLACE INTO archive.message_field SELECT * FROM message_field
+                WHERE message_id IN (SELECT id FROM archive_month);
+            INSERT OR IGNORE INTO archive.message_url SELECT * FROM message_url
+                WHERE message_id IN (SELECT id FROM archive_month);
+            INSERT OR REPLACE INTO archive.message_language SELECT * FROM message_language
+                WHERE message_id IN (SELECT id FROM archive_month);
+            INSERT OR REPLACE INTO archive.message_reaction SELECT * FROM message_reaction
+                WHERE message_id IN (SELECT id FROM archive_month);
+            INSERT OR IGNORE INTO archive.message_revision SELECT * FROM message_revision
+                WHERE message_id IN (SELECT id FROM archive_month);",
+        )?;
+        tx.commit()?;
+
+        let tx = conn.transaction()?;
+        for table in [
+            "message_field",
+            "message_url",
+            "message_language",
+            "message_reaction",
+            "message_revision",
+        ] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM main.{} WHERE message_id IN (
+                        SELECT id FROM archive_month WHERE id IN (SELECT id FROM archive.message))",
+                    table
+                ),
+                [],
+            )?;
+        }
+        let moved = tx.execute(
+            "DELETE FROM main.message WHERE id IN (
+                SELECT id FROM archive_month WHERE id IN (SELECT id FROM archive.message))",
+            [],
+        )?;
+        tx.execute("DROP TABLE archive_month", [])?;
+        tx.commit()?;
+
+        conn.execute("DETACH DATABASE archive", [])?;
+        info!("Archived {} Messages to {}", moved, archive_path.display());
+    }
+
+    conn.execute("DROP TABLE archive_message", [])?;
+    Ok(())
+}
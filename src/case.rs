@@ -0,0 +1,191 @@
+use clap::Subcommand;
+
+use std::fmt::Write as _;
+
+use crate::snowflake::Snowflake;
+use crate::{timezone, SimpleResult, User};
+
+#[derive(Debug, Subcommand)]
+pub enum CaseCommand {
+    /// Open a new case
+    Create {
+        name: String,
+
+        /// Short description of what the case is about
+        #[clap(long)]
+        description: Option<String>,
+    },
+    /// Attach archived messages (and their authors) to a case
+    AddMessage {
+        case_id: i64,
+
+        #[clap(required = true)]
+        message_ids: Vec<Snowflake>,
+
+        /// Moderator note recorded alongside the messages
+        #[clap(short, long)]
+        note: Option<String>,
+    },
+    /// Write a case report containing its users, messages and notes
+    Export {
+        case_id: i64,
+
+        /// Output file, defaults to stdout
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+}
+
+pub fn run(conn: &mut rusqlite::Connection, cmd: CaseCommand) -> SimpleResult<()> {
+    match cmd {
+        CaseCommand::Create { name, description } => create_case(conn, &name, description),
+        CaseCommand::AddMessage {
+            case_id,
+            message_ids,
+            note,
+        } => add_messages(conn, case_id, message_ids, note),
+        CaseCommand::Export { case_id, output } => {
+            let report = case_report(conn, case_id)?;
+            match output {
+                Some(path) => std::fs::write(path, report)?,
+                None => print!("{}", report),
+            }
+            Ok(())
+        }
+    }
+}
+
+fn create_case(
+    conn: &mut rusqlite::Connection,
+    name: &str,
+    description: Option<String>,
+) -> SimpleResult<()> {
+    conn.execute(
+        "INSERT INTO case_record (name, description) VALUES (?,?)",
+        rusqlite::params![name, description],
+    )?;
+    info!("Created case {}: {}", conn.last_insert_rowid(), name);
+
+    Ok(())
+}
+
+fn add_messages(
+    conn: &mut rusqlite::Connection,
+    case_id: i64,
+    message_ids: Vec<Snowflake>,
+    note: Option<String>,
+) -> SimpleResult<()> {
+    let tx = conn.transaction()?;
+    let case_exists: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM case_record WHERE id = ?)",
+        [case_id],
+        |row| row.get(0),
+    )?;
+    if !case_exists {
+        return Err(format!("No case with id {}", case_id).into());
+    }
+
+    for message_id in &message_ids {
+        let message_exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM message WHERE id = ?)",
+            [message_id],
+            |row| row.get(0),
+        )?;
+        if !message_exists {
+            return Err(format!("Message {} is not in the archive", message_id).into());
+        }
+
+        tx.execute(
+            "INSERT INTO case_message (case_id, message_id, note) VALUES (?,?,?)
+             ON CONFLICT (case_id, message_id) DO UPDATE SET note = coalesce(excluded.note, note)",
+            rusqlite::params![case_id, message_id, note],
+        )?;
+    }
+    tx.commit()?;
+
+    info!("Added {} Messages to case {}", message_ids.len(), case_id);
+
+    Ok(())
+}
+
+fn case_report(conn: &rusqlite::Connection, case_id: i64) -> SimpleResult<String> {
+    let (name, description, created_at): (String, Option<String>, String) = conn
+        .query_row(
+            "SELECT name, description, created_at FROM case_record WHERE id = ?",
+            [case_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|_| format!("No case with id {}", case_id))?;
+
+    let mut report = String::new();
+    writeln!(report, "# Case {}: {}", case_id, name)?;
+    writeln!(report)?;
+    writeln!(report, "Opened: {}", timezone::local(&created_at))?;
+    if let Some(description) = description {
+        writeln!(report)?;
+        writeln!(report, "{}", description)?;
+    }
+
+    writeln!(report)?;
+    writeln!(report, "## Users")?;
+    writeln!(report)?;
+    let mut stmt = conn.prepare(
+        "SELECT u.id, u.username, u.discriminator, u.global_name, COUNT(*)
+         FROM case_message cm
+         JOIN message m ON m.id = cm.message_id
+         JOIN user u ON u.id = m.author_id
+         WHERE cm.case_id = ?
+         GROUP BY u.id
+         ORDER BY u.username",
+    )?;
+    let mut rows = stmt.query([case_id])?;
+    while let Some(row) = rows.next()? {
+        let user = User {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            discriminator: row.get(2)?,
+            global_name: row.get(3)?,
+        };
+        writeln!(
+            report,
+            "- {} ({}): {} messages",
+            user.tag(),
+            user.id,
+            row.get::<_, i64>(4)?
+        )?;
+    }
+
+    writeln!(report)?;
+    writeln!(report, "## Messages")?;
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.timestamp, c.name, u.username, m.content, cm.note
+         FROM case_message cm
+         JOIN message m ON m.id = cm.message_id
+         LEFT JOIN channel c ON c.id = m.channel_id
+         LEFT JOIN user u ON u.id = m.author_id
+         WHERE cm.case_id = ?
+         ORDER BY m.created_at_unix, m.id",
+    )?;
+    let mut rows = stmt.query([case_id])?;
+    while let Some(row) = rows.next()? {
+        writeln!(report)?;
+        writeln!(
+            report,
+            "### {} in #{} by {} ({})",
+            timezone::local(&row.get::<_, String>(1)?),
+            row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            row.get::<_, Snowflake>(0)?
+        )?;
+        writeln!(report)?;
+        for line in row.get::<_, String>(4)?.lines() {
+            writeln!(report, "> {}", line)?;
+        }
+        if let Some(note) = row.get::<_, Option<String>>(5)? {
+            writeln!(report)?;
+            writeln!(report, "Note: {}", note)?;
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,251 @@
+use clap::{ArgEnum, Args};
+
+use std::env;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::api::ApiClient;
+use crate::exit::{self, Failure};
+use crate::{credentials, SimpleResult, BASE_URL};
+
+/// The tokens requests are sent with, set once the client is built.
+static TOKENS: OnceLock<Vec<Token>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum TokenType {
+    Auto,
+    /// Sent as `Bot <token>`
+    Bot,
+    User,
+}
+
+struct Token {
+    /// The value of the `Authorization` header.
+    authorization: String,
+    token_type: TokenType,
+    /// Set once Discord rejects the token, after which it isn't used again.
+    revoked: AtomicBool,
+}
+
+/// How many tokens requests are spread over.
+pub fn token_count() -> usize {
+    TOKENS.get().map_or(0, Vec::len)
+}
+
+/// The `Authorization` header of token `index`.
+pub fn authorization(index: usize) -> &'static str {
+    &TOKENS.get().unwrap()[index].authorization
+}
+
+/// Whether token `index` may still be used.
+pub fn is_usable(index: usize) -> bool {
+    !TOKENS.get().unwrap()[index].revoked.load(Ordering::Relaxed)
+}
+
+/// Stops using token `index` after Discord rejected it, returning whether any are left.
+pub fn revoke(index: usize) -> bool {
+    let tokens = TOKENS.get().unwrap();
+    if !tokens[index].revoked.swap(true, Ordering::Relaxed) && tokens.len() > 1 {
+        warn!(
+            "Discord rejected token {}, continuing without it",
+            index + 1
+        );
+    }
+    (0..tokens.len()).any(is_usable)
+}
+
+/// Sends requests with these `Authorization` headers, all for accounts of `token_type`.
+/// Clients built from the command line set them, others have to before any request.
+pub fn set_tokens(token_type: TokenType, authorizations: Vec<String>) -> SimpleResult<()> {
+    let pool = authorizations
+        .into_iter()
+        .map(|authorization| Token {
+            authorization,
+            token_type,
+            revoked: AtomicBool::new(false),
+        })
+        .collect();
+    TOKENS
+        .set(pool)
+        .map_err(|_| "Tokens were already set".into())
+}
+
+/// Whether requests are sent as a bot, some endpoints being only for users.
+pub fn is_bot() -> bool {
+    TOKENS
+        .get()
+        .and_then(|tokens| tokens.first())
+        .is_some_and(|token| token.token_type == TokenType::Bot)
+}
+
+#[derive(Debug, Args)]
+pub struct ClientArgs {
+    /// Discord authorization token, or - to read them from standard input, one per line.
+    /// Given several times, requests are spread over the tokens
+    #[clap(short, long, global = true)]
+    auth: Vec<String>,
+
+    /// Read authorization tokens from this file, one per line, keeping them out of the
+    /// process list
+    #[clap(long, global = true, value_name = "PATH", conflicts_with = "auth")]
+    auth_file: Option<String>,
+
+    /// Whether the tokens belong to bot or user accounts, by default found out by trying
+    /// them
+    #[clap(long, arg_enum, global = true, default_value = "auto")]
+    token_type: TokenType,
+
+    /// Send requests through this proxy, e.g. http://proxy:3128 or socks5h://127.0.0.1:9050,
+    /// defaults to DISCORD_PROXY, then the usual HTTPS_PROXY and ALL_PROXY
+    #[clap(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Also trust the certificates in this PEM file, e.g. the CA of a TLS-inspecting proxy,
+    /// can be given several times
+    #[clap(long = "ca-cert", global = true, value_name = "PEM")]
+    ca_certs: Vec<String>,
+
+    /// Seconds to wait for a connection to Discord
+    #[clap(long, global = true, default_value_t = 10.0, value_name = "SECS")]
+    connect_timeout: f64,
+
+    /// Seconds to wait for a whole response before giving up on a request
+    #[clap(long, global = true, default_value_t = 30.0, value_name = "SECS")]
+    timeout: f64,
+}
+
+impl ClientArgs {
+    /// A client whose requests are spread over the authorization tokens, taken from
+    /// --auth or --auth-file, DISCORD_AUTH_TOKEN or the keyring, in that order.
+    pub fn build(&self) -> SimpleResult<reqwest::blocking::Client> {
+        let tokens = self.tokens()?;
+        if !tokens.is_empty() {
+            return self.build_with_tokens(&tokens);
+        }
+        match credentials::stored_token() {
+            Ok(Some(token)) => self.build_with_tokens(&[token]),
+            Ok(None) => Err(Failure::new(exit::AUTH, "No authorization token found!").into()),
+            Err(e) => {
+                let err_msg = format!("No authorization token found! {}", e);
+                Err(Failure::new(exit::AUTH, err_msg).into())
+            }
+        }
+    }
+
+    /// The tokens given on the command line or in the environment, where several are
+    /// separated by commas.
+    fn tokens(&self) -> SimpleResult<Vec<String>> {
+        let text = match (self.auth.as_slice(), &self.auth_file) {
+            ([auth], _) if auth == "-" => {
+                let mut text = String::new();
+                std::io::stdin().lock().read_to_string(&mut text)?;
+                text
+            }
+            ([], Some(path)) => std::fs::read_to_string(path)
+                .map_err(|e| format!("Reading tokens from {}: {}", path, e))?,
+            ([], None) => match env::var("DISCORD_AUTH_TOKEN") {
+                Ok(tokens) => tokens.replace(',', "\n"),
+                Err(_) => return Ok(Vec::new()),
+            },
+            (auth, _) => return Ok(auth.to_vec()),
+        };
+        // Files and pipes usually end in a newline.
+        let tokens: Vec<String> = text
+            .lines()
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .map(String::from)
+            .collect();
+        if tokens.is_empty() {
+            return Err("No tokens were given".into());
+        }
+        Ok(tokens)
+    }
+
+    /// A client for `tokens`, sent as bot or user tokens as --token-type says.
+    pub fn build_with_tokens(&self, tokens: &[String]) -> SimpleResult<reqwest::blocking::Client> {
+        let client = self.client()?;
+        let mut types = Vec::new();
+        let mut authorizations = Vec::new();
+        for token in tokens.iter().map(String::as_str) {
+            // Tokens already carrying the prefix are bot tokens whatever --token-type says.
+            let (token, token_type) = match (token.strip_prefix("Bot "), self.token_type) {
+                (Some(token), _) => (token, TokenType::Bot),
+                (None, TokenType::Auto) => (token, detect_token_type(&client, token)?),
+                (None, token_type) => (token, token_type),
+            };
+            authorizations.push(match token_type {
+                TokenType::Bot => format!("Bot {}", token),
+                _ => token.to_string(),
+            });
+            types.push(token_type);
+        }
+        // Some endpoints are only for one of the two, and any token may send any request.
+        if types.iter().any(|t| *t != types[0]) {
+            return Err("Tokens must all be bot tokens or all be user tokens".into());
+        }
+        set_tokens(types[0], authorizations)?;
+        Ok(client)
+    }
+
+    /// A client without a token, requests are given one from the pool as they're sent.
+    fn client(&self) -> SimpleResult<reqwest::blocking::Client> {
+        let seconds = |flag: &str, value: f64| match Duration::try_from_secs_f64(value) {
+            Ok(duration) if !duration.is_zero() => Ok(duration),
+            _ => Err(format!(
+                "{} must be a positive number of seconds, got {}",
+                flag, value
+            )),
+        };
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent("MessageScraperBot (1.0.0)")
+            .connect_timeout(seconds("--connect-timeout", self.connect_timeout)?)
+            .timeout(seconds("--timeout", self.timeout)?);
+        // Without one reqwest picks up the proxy environment variables by itself.
+        if let Some(proxy) = self
+            .proxy
+            .clone()
+            .or_else(|| env::var("DISCORD_PROXY").ok())
+        {
+            let proxy = reqwest::Proxy::all(&proxy)
+                .map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+        // The system's trust store is used as well, through the platform's TLS library.
+        for path in &self.ca_certs {
+            let pem = std::fs::read(path).map_err(|e| format!("Reading {}: {}", path, e))?;
+            let certs = reqwest::Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+            if certs.is_empty() {
+                let err_msg = format!("No certificates found in {}", path);
+                return Err(err_msg.into());
+            }
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Tries `token` as a user token, then as a bot token.
+fn detect_token_type(client: &dyn ApiClient, token: &str) -> SimpleResult<TokenType> {
+    let url = format!("{}/users/@me", BASE_URL);
+    for (token_type, authorization) in [
+        (TokenType::User, token.to_string()),
+        (TokenType::Bot, format!("Bot {}", token)),
+    ] {
+        let status = reqwest::StatusCode::from_u16(client.get(&url, &authorization)?.status)?;
+        if status.is_success() {
+            return Ok(token_type);
+        }
+        if status != reqwest::StatusCode::UNAUTHORIZED {
+            let err_msg = format!("Checking the token failed: {}", status);
+            return Err(err_msg.into());
+        }
+    }
+    let err_msg = "Discord rejected the token, both as a user and as a bot token";
+    Err(Failure::new(exit::AUTH, err_msg).into())
+}
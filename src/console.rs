@@ -0,0 +1,67 @@
+use std::env;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    /// Yellow, for something skipped or worked around while the run carries on.
+    Warn,
+    /// Red and on standard error, for what ends the run.
+    Error,
+}
+
+/// Colors lines only on a terminal, and not with `--no-color` or `NO_COLOR` set
+/// (<https://no-color.org>). `--quiet` hides everything but errors and the run summary.
+pub fn configure(no_color: bool, quiet: bool) {
+    let no_color = no_color || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    set_color(!no_color && std::io::stdout().is_terminal());
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn set_color(color: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Prints a line tagged with its level, unless `--quiet` hides it.
+pub fn print(level: Level, args: fmt::Arguments) {
+    if level == Level::Error || !QUIET.load(Ordering::Relaxed) {
+        print_always(level, args);
+    }
+}
+
+/// Prints a line tagged with its level even with `--quiet`, for the run summary.
+pub fn print_always(level: Level, args: fmt::Arguments) {
+    let (tag, color) = match level {
+        Level::Info => ("[INFO]", None),
+        Level::Warn => ("[WARN]", Some(33)),
+        Level::Error => ("[ERROR]", Some(31)),
+    };
+    let line = match color {
+        Some(color) if COLOR.load(Ordering::Relaxed) => {
+            format!("\x1b[{}m{} {}\x1b[0m", color, tag, args)
+        }
+        _ => format!("{} {}", tag, args),
+    };
+    if level == Level::Error {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::console::print($crate::console::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::console::print($crate::console::Level::Warn, format_args!($($arg)*))
+    };
+}
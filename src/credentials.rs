@@ -0,0 +1,77 @@
+use clap::Subcommand;
+
+use std::io::{BufRead, IsTerminal};
+
+use crate::client::ClientArgs;
+use crate::{send_request, SimpleResult, User, BASE_URL};
+
+/// Where the token is kept in the platform keyring.
+const SERVICE: &str = "discord-scraper";
+const ACCOUNT: &str = "default";
+
+#[derive(Debug, Subcommand)]
+pub enum AuthCommand {
+    /// Check a token and save it in the platform keyring, where scrapes find it when
+    /// neither --auth nor DISCORD_AUTH_TOKEN is given. Prompts for the token, or reads it
+    /// from standard input when that's not a terminal
+    Login,
+    /// Remove the token saved by `auth login`
+    Logout,
+}
+
+pub fn run(cmd: AuthCommand, client_args: &ClientArgs) -> SimpleResult<()> {
+    match cmd {
+        AuthCommand::Login => login(client_args),
+        AuthCommand::Logout => match entry()?.delete_password() {
+            Ok(()) => {
+                info!("Removed the token from the keyring");
+                Ok(())
+            }
+            Err(keyring::Error::NoEntry) => {
+                info!("No token was saved in the keyring");
+                Ok(())
+            }
+            Err(e) => Err(keyring_error("Removing the token from", e)),
+        },
+    }
+}
+
+/// The token saved by `auth login`, if any.
+pub fn stored_token() -> SimpleResult<Option<String>> {
+    match entry()?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(keyring_error("Reading the token from", e)),
+    }
+}
+
+fn entry() -> SimpleResult<keyring::Entry> {
+    keyring::Entry::new(SERVICE, ACCOUNT).map_err(|e| keyring_error("Opening", e))
+}
+
+fn keyring_error(action: &str, e: keyring::Error) -> Box<dyn std::error::Error> {
+    format!("{} the platform keyring: {}", action, e).into()
+}
+
+fn login(client_args: &ClientArgs) -> SimpleResult<()> {
+    let token = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password("Discord authorization token: ")?
+    } else {
+        let mut token = String::new();
+        std::io::stdin().lock().read_line(&mut token)?;
+        token
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        return Err("No token given".into());
+    }
+
+    let client = client_args.build_with_tokens(&[token.to_string()])?;
+    let body = send_request(&client, &format!("{}/users/@me", BASE_URL))?;
+    let user: User = serde_json::from_str(&body)?;
+    entry()?
+        .set_password(token)
+        .map_err(|e| keyring_error("Saving the token in", e))?;
+    info!("Saved the token of {} in the keyring", user.username);
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A point in time given on the command line either as an age relative to now
+/// (`90d`, `12w`, `36h`) or as a UTC date (`2021-06-30`). Holds unix seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Cutoff(pub i64);
+
+impl FromStr for Cutoff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((year, rest)) = s.split_once('-') {
+            let (month, day) = rest
+                .split_once('-')
+                .ok_or_else(|| format!("invalid date: {}", s))?;
+            let parse = |v: &str| v.parse::<i64>().map_err(|_| format!("invalid date: {}", s));
+            let (year, month, day) = (parse(year)?, parse(month)?, parse(day)?);
+            if !(1..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day)
+            {
+                return Err(format!("invalid date: {}", s));
+            }
+            return Ok(Cutoff(days_from_civil(year, month, day) * 86400));
+        }
+
+        let unit = match s.chars().last() {
+            Some('h') => 3600,
+            Some('d') => 86400,
+            Some('w') => 7 * 86400,
+            _ => {
+                return Err(format!(
+                    "expected an age like 90d, 12w or 36h, or a date: {}",
+                    s
+                ))
+            }
+        };
+        let amount: i64 = match s[..s.len() - 1].parse() {
+            Ok(amount) if amount >= 0 => amount,
+            _ => return Err(format!("invalid age: {}", s)),
+        };
+
+        amount
+            .checked_mul(unit)
+            .and_then(|age| now_unix().checked_sub(age))
+            .map(Cutoff)
+            .ok_or_else(|| format!("age too large: {}", s))
+    }
+}
+
+pub fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The UTC date and time of unix seconds, as year, month, day, hour, minute and second.
+pub fn utc_from_unix(unix: i64) -> [i64; 6] {
+    let (days, seconds) = (unix.div_euclid(86400), unix.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    [
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    ]
+}
+
+/// The proleptic Gregorian date of days since 1970-01-01, the inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,580 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::{enrich, timezone, SimpleResult};
+
+/// Ordered schema migrations. Entry `i` upgrades a database from version `i` to
+/// version `i + 1`. Migrations that have shipped must never be edited; schema
+/// changes are made by appending a new entry.
+const MIGRATIONS: &[&str] = &[
+    // 1: The original schema.
+    "CREATE TABLE channel (
+              id              TEXT PRIMARY KEY,
+              guild_id        TEXT,
+              name            TEXT
+              ) STRICT;
+    CREATE TABLE message (
+              id              TEXT PRIMARY KEY,
+              channel_id      TEXT REFERENCES channel(id),
+              author_id       TEXT REFERENCES user(id),
+              content         TEXT NOT NULL,
+              timestamp       TEXT NOT NULL
+              ) STRICT;
+    CREATE TABLE user (
+              id              TEXT PRIMARY KEY,
+              username        TEXT NOT NULL,
+              discriminator   TEXT NOT NULL
+              ) STRICT;",
+    // 2: Unix creation time derived from the message snowflake.
+    "ALTER TABLE message ADD COLUMN created_at_unix INTEGER NOT NULL DEFAULT 0;
+    UPDATE message SET created_at_unix = ((CAST(id AS INTEGER) >> 22) + 1420070400000) / 1000;",
+    // 3: Snowflake IDs stored as INTEGER.
+    "CREATE TABLE channel_new (
+              id              INTEGER PRIMARY KEY,
+              guild_id        INTEGER,
+              name            TEXT
+              ) STRICT;
+    INSERT INTO channel_new (id, guild_id, name)
+        SELECT CAST(id AS INTEGER), CAST(NULLIF(guild_id, '') AS INTEGER), name FROM channel;
+    DROP TABLE channel;
+    ALTER TABLE channel_new RENAME TO channel;
+    CREATE TABLE user_new (
+              id              INTEGER PRIMARY KEY,
+              username        TEXT NOT NULL,
+              discriminator   TEXT NOT NULL
+              ) STRICT;
+    INSERT INTO user_new (id, username, discriminator)
+        SELECT CAST(id AS INTEGER), username, discriminator FROM user;
+    DROP TABLE user;
+    ALTER TABLE user_new RENAME TO user;
+    CREATE TABLE message_new (
+              id              INTEGER PRIMARY KEY,
+              channel_id      INTEGER REFERENCES channel(id),
+              author_id       INTEGER REFERENCES user(id),
+              content         TEXT NOT NULL,
+              timestamp       TEXT NOT NULL,
+              created_at_unix INTEGER NOT NULL
+              ) STRICT;
+    INSERT INTO message_new (id, channel_id, author_id, content, timestamp, created_at_unix)
+        SELECT CAST(id AS INTEGER), CAST(channel_id AS INTEGER), CAST(author_id AS INTEGER),
+               content, timestamp, created_at_unix
+        FROM message;
+    DROP TABLE message;
+    ALTER TABLE message_new RENAME TO message;",
+    // 4: Moderation cases, which `case` created itself before versioning.
+    "CREATE TABLE IF NOT EXISTS case_record (
+              id              INTEGER PRIMARY KEY,
+              name            TEXT NOT NULL,
+              description     TEXT,
+              created_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+              ) STRICT;
+    CREATE TABLE IF NOT EXISTS case_message (
+              case_id         INTEGER NOT NULL REFERENCES case_record(id),
+              message_id      INTEGER NOT NULL REFERENCES message(id),
+              note            TEXT,
+              added_at        TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              PRIMARY KEY (case_id, message_id)
+              ) STRICT;",
+    // 5: Indexes on the columns queries filter and join on.
+    "CREATE INDEX message_channel_id_timestamp ON message(channel_id, timestamp);
+    CREATE INDEX message_author_id ON message(author_id);
+    CREATE INDEX channel_guild_id ON channel(guild_id);
+    CREATE INDEX case_message_message_id ON case_message(message_id);",
+    // 6: Bot applications and the commands they registered in each guild.
+    "CREATE TABLE application (
+              id              INTEGER PRIMARY KEY,
+              name            TEXT NOT NULL,
+              description     TEXT
+              ) STRICT;
+    CREATE TABLE application_command (
+              id              INTEGER PRIMARY KEY,
+              application_id  INTEGER NOT NULL REFERENCES application(id),
+              guild_id        INTEGER NOT NULL,
+              name            TEXT NOT NULL,
+              description     TEXT NOT NULL,
+              type            INTEGER,
+              options         TEXT
+              ) STRICT;
+    CREATE INDEX application_command_application_id ON application_command(application_id);
+    CREATE INDEX application_command_guild_id ON application_command(guild_id);",
+    // 7: Guilds and the history of their boost tier and feature flags.
+    "CREATE TABLE guild (
+              id              INTEGER PRIMARY KEY,
+              name            TEXT NOT NULL
+              ) STRICT;
+    CREATE TABLE guild_history (
+              id              INTEGER PRIMARY KEY,
+              guild_id        INTEGER NOT NULL REFERENCES guild(id),
+              captured_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              premium_tier    INTEGER NOT NULL,
+              premium_subscription_count INTEGER,
+              features        TEXT NOT NULL
+              ) STRICT;
+    CREATE INDEX guild_history_guild_id ON guild_history(guild_id);",
+    // 8: Guild roles and the permission overwrites set on channels.
+    "CREATE TABLE role (
+              id              INTEGER PRIMARY KEY,
+              guild_id        INTEGER NOT NULL REFERENCES guild(id),
+              name            TEXT NOT NULL,
+              permissions     INTEGER NOT NULL,
+              position        INTEGER NOT NULL
+              ) STRICT;
+    CREATE TABLE permission_overwrite (
+              channel_id      INTEGER NOT NULL REFERENCES channel(id),
+              target_id       INTEGER NOT NULL,
+              type            INTEGER NOT NULL,
+              allow           INTEGER NOT NULL,
+              deny            INTEGER NOT NULL,
+              PRIMARY KEY (channel_id, target_id)
+              ) STRICT;
+    CREATE INDEX role_guild_id ON role(guild_id);",
+    // 9: The newest message each named delta export has covered in every channel.
+    "CREATE TABLE export_watermark (
+              name            TEXT NOT NULL,
+              channel_id      INTEGER NOT NULL REFERENCES channel(id),
+              last_message_id INTEGER NOT NULL,
+              exported_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              PRIMARY KEY (name, channel_id)
+              ) STRICT;
+    CREATE INDEX export_watermark_channel_id ON export_watermark(channel_id);",
+    // 10: The message a reply responds to. Not a foreign key, the original may never
+    // have been scraped or may have been deleted.
+    "ALTER TABLE message ADD COLUMN reply_to INTEGER;
+    CREATE INDEX message_reply_to ON message(reply_to);",
+    // 11: Where an interrupted scrape of each channel stopped, to resume from there.
+    "CREATE TABLE scrape_state (
+              channel_id      INTEGER PRIMARY KEY REFERENCES channel(id),
+              newest_id       INTEGER NOT NULL,
+              oldest_id       INTEGER NOT NULL,
+              updated_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+              ) STRICT;",
+    // 12: Scrape state is kept after every page, and once a channel has been scraped to
+    // its first message later runs only fetch what's newer.
+    "ALTER TABLE scrape_state ADD COLUMN complete INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE scrape_state RENAME COLUMN updated_at TO last_run_at;",
+    // 13: Fields a --script computed for messages, kept as whatever type it returned.
+    "CREATE TABLE message_field (
+              message_id      INTEGER NOT NULL REFERENCES message(id),
+              name            TEXT NOT NULL,
+              value           ANY,
+              PRIMARY KEY (message_id, name)
+              ) STRICT;
+    CREATE INDEX message_field_name ON message_field(name);",
+    // 14: The language detected in a message's content, as an ISO 639-3 code.
+    "ALTER TABLE message ADD COLUMN lang TEXT;
+    CREATE INDEX message_lang ON message(lang);",
+    // 15: Links found in messages by --extract-urls.
+    "CREATE TABLE message_url (
+              message_id      INTEGER NOT NULL REFERENCES message(id),
+              url             TEXT NOT NULL,
+              domain          TEXT NOT NULL,
+              PRIMARY KEY (message_id, url)
+              ) STRICT;
+    CREATE INDEX message_url_domain ON message_url(domain);",
+    // 16: Users that look like spammers or bots, as of the last `analyze spam`.
+    "CREATE TABLE flagged_user (
+              user_id         INTEGER NOT NULL REFERENCES user(id),
+              reason          TEXT NOT NULL,
+              detail          TEXT NOT NULL,
+              flagged_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              PRIMARY KEY (user_id, reason)
+              ) STRICT;",
+    // 17: Edits, deletions and reactions, kept current for recent messages by --refresh.
+    "ALTER TABLE message ADD COLUMN edited_timestamp TEXT;
+    ALTER TABLE message ADD COLUMN deleted INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE message_reaction (
+              message_id      INTEGER NOT NULL REFERENCES message(id),
+              emoji           TEXT NOT NULL,
+              count           INTEGER NOT NULL,
+              PRIMARY KEY (message_id, emoji)
+              ) STRICT;",
+    // 18: Names users went by before the one in `user`, kept as they're replaced.
+    "CREATE TABLE user_history (
+              id              INTEGER PRIMARY KEY,
+              user_id         INTEGER NOT NULL REFERENCES user(id),
+              replaced_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              username        TEXT NOT NULL,
+              discriminator   TEXT NOT NULL
+              ) STRICT;
+    CREATE INDEX user_history_user_id ON user_history(user_id);",
+    // 19: Discriminators are optional now that most users have none, and users can have
+    // a display name. `0` is what the API sends for no discriminator, `0000` what
+    // anonymized users were stored with.
+    "CREATE TABLE user_new (
+              id              INTEGER PRIMARY KEY,
+              username        TEXT NOT NULL,
+              discriminator   TEXT,
+              global_name     TEXT
+              ) STRICT;
+    INSERT INTO user_new (id, username, discriminator)
+        SELECT id, username, NULLIF(NULLIF(discriminator, '0'), '0000') FROM user;
+    DROP TABLE user;
+    ALTER TABLE user_new RENAME TO user;
+    CREATE TABLE user_history_new (
+              id              INTEGER PRIMARY KEY,
+              user_id         INTEGER NOT NULL REFERENCES user(id),
+              replaced_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              username        TEXT NOT NULL,
+              discriminator   TEXT,
+              global_name     TEXT
+              ) STRICT;
+    INSERT INTO user_history_new (id, user_id, replaced_at, username, discriminator)
+        SELECT id, user_id, replaced_at, username, NULLIF(NULLIF(discriminator, '0'), '0000')
+        FROM user_history;
+    DROP TABLE user_history;
+    ALTER TABLE user_history_new RENAME TO user_history;
+    CREATE INDEX user_history_user_id ON user_history(user_id);",
+    // 20: Earlier versions of edited messages, kept as they're replaced.
+    "CREATE TABLE message_revision (
+              id              INTEGER PRIMARY KEY,
+              message_id      INTEGER NOT NULL REFERENCES message(id),
+              replaced_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              content         TEXT NOT NULL,
+              edited_timestamp TEXT
+              ) STRICT;
+    CREATE INDEX message_revision_message_id ON message_revision(message_id);",
+    // 21: Channel topics, and the names and topics channels had before, kept as they're
+    // replaced.
+    "ALTER TABLE channel ADD COLUMN topic TEXT;
+    CREATE TABLE channel_history (
+              id              INTEGER PRIMARY KEY,
+              channel_id      INTEGER NOT NULL REFERENCES channel(id),
+              replaced_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              name            TEXT,
+              topic           TEXT
+              ) STRICT;
+    CREATE INDEX channel_history_channel_id ON channel_history(channel_id);",
+    // 22: Message flags, and where crossposted announcements were published. Not foreign
+    // keys, the origin is usually in a guild that isn't scraped.
+    "ALTER TABLE message ADD COLUMN flags INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE message ADD COLUMN origin_guild_id INTEGER;
+    ALTER TABLE message ADD COLUMN origin_channel_id INTEGER;
+    ALTER TABLE message ADD COLUMN origin_message_id INTEGER;
+    CREATE INDEX message_origin_channel_id ON message(origin_channel_id);",
+    // 23: Super reactions, counted in `count` along with normal ones, and the colors of
+    // their animation as a JSON array.
+    "ALTER TABLE message_reaction ADD COLUMN burst_count INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE message_reaction ADD COLUMN burst_colors TEXT NOT NULL DEFAULT '[]';",
+    // 24: Channel types, the bitrate and user limit of voice and stage channels, and the
+    // events scheduled in guilds. An event's channel isn't a foreign key, it may not be
+    // stored.
+    "ALTER TABLE channel ADD COLUMN type INTEGER;
+    ALTER TABLE channel ADD COLUMN bitrate INTEGER;
+    ALTER TABLE channel ADD COLUMN user_limit INTEGER;
+    CREATE TABLE scheduled_event (
+              id              INTEGER PRIMARY KEY,
+              guild_id        INTEGER NOT NULL REFERENCES guild(id),
+              channel_id      INTEGER,
+              name            TEXT NOT NULL,
+              description     TEXT,
+              scheduled_start_time TEXT NOT NULL,
+              scheduled_end_time TEXT,
+              status          INTEGER NOT NULL,
+              entity_type     INTEGER NOT NULL
+              ) STRICT;
+    CREATE INDEX scheduled_event_guild_id ON scheduled_event(guild_id);",
+    // 25: Users erased with `forget-user`, whose messages later scrapes leave out. Not a
+    // foreign key, the user row is gone.
+    "CREATE TABLE forgotten_user (
+              user_id         INTEGER PRIMARY KEY,
+              forgotten_at    TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+              messages        INTEGER NOT NULL,
+              anonymized      INTEGER NOT NULL
+              ) STRICT;",
+    // 26: How long voice messages are and their waveform, base64 encoded as Discord sends it.
+    "ALTER TABLE message ADD COLUMN voice_duration_secs REAL;
+    ALTER TABLE message ADD COLUMN voice_waveform TEXT;",
+    // 27: Soundboard sounds of guilds. Who uploaded one isn't a foreign key, it's only known
+    // to those allowed to manage expressions and may be forgotten.
+    "CREATE TABLE soundboard_sound (
+              id              INTEGER PRIMARY KEY,
+              guild_id        INTEGER NOT NULL REFERENCES guild(id),
+              name            TEXT NOT NULL,
+              volume          REAL NOT NULL,
+              emoji_id        INTEGER,
+              emoji_name      TEXT,
+              user_id         INTEGER,
+              available       INTEGER NOT NULL
+              ) STRICT;
+    CREATE INDEX soundboard_sound_guild_id ON soundboard_sound(guild_id);",
+    // 28: Detected languages move out of the message table into one of their own, like the
+    // output of other enrichers.
+    "CREATE TABLE message_language (
+              message_id      INTEGER PRIMARY KEY REFERENCES message(id),
+              lang            TEXT NOT NULL
+              ) STRICT;
+    CREATE INDEX message_language_lang ON message_language(lang);
+    INSERT INTO message_language (message_id, lang) SELECT id, lang FROM message WHERE lang IS NOT NULL;
+    DROP INDEX message_lang;
+    ALTER TABLE message DROP COLUMN lang;",
+];
+
+pub fn open_db(db_path: &str) -> SimpleResult<rusqlite::Connection> {
+    let db_path = std::path::Path::new(db_path);
+    let prefix = db_path.parent().unwrap();
+    std::fs::create_dir_all(prefix).unwrap();
+    connect_db(db_path)
+}
+
+/// Opens an existing database without migrating it, refusing any statement that writes.
+/// Fails unless the database is already at the schema version of this build.
+pub fn open_read_only(db_path: &str) -> SimpleResult<rusqlite::Connection> {
+    if !Path::new(db_path).exists() {
+        let err_msg = format!("{} does not exist", db_path);
+        return Err(err_msg.into());
+    }
+    let conn = open_keyed(
+        db_path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let version = schema_version(&conn)?;
+    if version != MIGRATIONS.len() {
+        let err_msg = if version < MIGRATIONS.len() {
+            format!(
+                "{} is at schema version {}, run a scrape or `verify` on it to migrate it to {}",
+                db_path,
+                version,
+                MIGRATIONS.len()
+            )
+        } else {
+            format!(
+                "Database schema version {} is newer than this build supports ({})",
+                version,
+                MIGRATIONS.len()
+            )
+        };
+        return Err(err_msg.into());
+    }
+    Ok(conn)
+}
+
+/// Starts a read transaction, so every query until it ends sees the database as of its first
+/// read. In WAL mode a scrape keeps writing meanwhile, and what it commits shows up in the
+/// next snapshot.
+pub fn snapshot(conn: &rusqlite::Connection) -> rusqlite::Result<rusqlite::Transaction<'_>> {
+    conn.unchecked_transaction()
+}
+
+/// Passphrase every database is opened with, set once from the command line.
+static DB_KEY: OnceLock<String> = OnceLock::new();
+
+pub fn set_key(key: String) -> SimpleResult<()> {
+    if !cfg!(feature = "sqlcipher") {
+        return Err("Encrypted databases need a build with the `sqlcipher` feature".into());
+    }
+    DB_KEY
+        .set(key)
+        .map_err(|_| "Database key was already set".into())
+}
+
+/// Opens a connection and, for encrypted builds, unlocks it with the database key.
+fn open_keyed<P: AsRef<Path>>(
+    path: P,
+    flags: rusqlite::OpenFlags,
+) -> SimpleResult<rusqlite::Connection> {
+    let conn = rusqlite::Connection::open_with_flags(&path, flags)?;
+    timezone::register(&conn)?;
+    if let Some(key) = DB_KEY.get() {
+        conn.pragma_update(None, "key", key)?;
+        // The key is only checked once the first page is read.
+        if conn
+            .query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))
+            .is_err()
+        {
+            let err_msg = format!(
+                "Could not decrypt {}, check the database key",
+                path.as_ref().display()
+            );
+            return Err(err_msg.into());
+        }
+    }
+    Ok(conn)
+}
+
+/// Attaches another database under `alias`, unlocking it with the same key as the main one.
+pub fn attach(conn: &rusqlite::Connection, path: &str, alias: &str) -> SimpleResult<()> {
+    match DB_KEY.get() {
+        Some(key) => conn.execute(
+            &format!("ATTACH DATABASE ? AS {} KEY ?", alias),
+            [path, key],
+        )?,
+        None => conn.execute(&format!("ATTACH DATABASE ? AS {}", alias), [path])?,
+    };
+    Ok(())
+}
+
+pub fn connect_db<P: AsRef<Path>>(path: P) -> SimpleResult<rusqlite::Connection> {
+    let mut conn = open_keyed(path, rusqlite::OpenFlags::default())?;
+    // WAL with NORMAL sync stays crash safe while only syncing at checkpoints,
+    // and a 64 MiB page cache keeps the message indexes in memory during big scrapes.
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+        PRAGMA synchronous = NORMAL;
+        PRAGMA cache_size = -65536;
+        PRAGMA foreign_keys = ON;",
+    )?;
+    migrate(&mut conn)?;
+    enrich::setup(&conn)?;
+
+    Ok(conn)
+}
+
+/// Brings the schema up to date, applying each pending migration in its own transaction.
+fn migrate(conn: &mut rusqlite::Connection) -> SimpleResult<()> {
+    let current = schema_version(conn)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+                  version         INTEGER PRIMARY KEY,
+                  applied_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+                  ) STRICT;",
+        [],
+    )?;
+    if current > MIGRATIONS.len() {
+        let err_msg = format!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            MIGRATIONS.len()
+        );
+        return Err(err_msg.into());
+    }
+
+    // Rebuilding a table means dropping one that other tables reference, so enforcement
+    // is paused and references are checked before each migration commits instead.
+    let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = version + 1;
+        if current > 0 {
+            info!("Migrating database to schema version {}", version);
+        }
+
+        let tx = conn.transaction()?;
+        let violations_before = foreign_key_violations(&tx)?;
+        tx.execute_batch(migration)?;
+        let violations = foreign_key_violations(&tx)? - violations_before;
+        if violations > 0 {
+            let err_msg = format!(
+                "Migration to schema version {} left {} broken references",
+                version, violations
+            );
+            return Err(err_msg.into());
+        }
+        tx.execute("INSERT INTO schema_version (version) VALUES (?)", [version])?;
+        tx.commit()?;
+    }
+
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+
+    Ok(())
+}
+
+fn foreign_key_violations(conn: &rusqlite::Connection) -> SimpleResult<i64> {
+    Ok(
+        conn.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })?,
+    )
+}
+
+/// The schema version the database is at, read without writing to it.
+fn schema_version(conn: &rusqlite::Connection) -> SimpleResult<usize> {
+    let versioned: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if versioned {
+        let version: Option<usize> =
+            conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+                row.get(0)
+            })?;
+        if let Some(version) = version {
+            return Ok(version);
+        }
+    }
+
+    // Databases created before versioning hold the schema of the build that last opened
+    // them: the original one, with creation times added, or with INTEGER IDs as well.
+    let has_tables: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'message')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_tables {
+        return Ok(0);
+    }
+    let has_created_at: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('message') WHERE name = 'created_at_unix')",
+        [],
+        |row| row.get(0),
+    )?;
+    let integer_ids: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('message') WHERE name = 'id' AND type = 'INTEGER')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(match (has_created_at, integer_ids) {
+        (_, true) => 3,
+        (true, false) => 2,
+        (false, false) => 1,
+    })
+}
+
+/// Rebuilds the database file without dead space and refreshes the query planner statistics.
+pub fn vacuum(conn: &rusqlite::Connection, db_path: &str) -> SimpleResult<()> {
+    // Fold the write-ahead log into the main file so the sizes reported are the real ones.
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    let before = std::fs::metadata(db_path)?.len();
+
+    info!("Running VACUUM");
+    conn.execute_batch("VACUUM;")?;
+    info!("Running ANALYZE");
+    conn.execute_batch("ANALYZE; PRAGMA optimize;")?;
+
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    let after = std::fs::metadata(db_path)?.len();
+    info!(
+        "Database size: {} bytes -> {} bytes ({} bytes reclaimed)",
+        before,
+        after,
+        before.saturating_sub(after)
+    );
+
+    Ok(())
+}
+
+/// Copies the database a few pages at a time with SQLite's online backup, so writers
+/// are only briefly blocked and the copy is always consistent.
+pub fn backup(conn: &rusqlite::Connection, destination: &str) -> SimpleResult<()> {
+    if Path::new(destination).exists() {
+        let err_msg = format!("{} already exists", destination);
+        return Err(err_msg.into());
+    }
+
+    if let Some(parent) = Path::new(destination).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    info!("Backing up to {}", destination);
+    // The copy is keyed like the source, SQLCipher can't back up between different keys.
+    let mut dest = open_keyed(destination, rusqlite::OpenFlags::default())?;
+    rusqlite::backup::Backup::new(conn, &mut dest)?.run_to_completion(
+        100,
+        std::time::Duration::from_millis(250),
+        Some(|progress: rusqlite::backup::Progress| {
+            let done = progress.pagecount - progress.remaining;
+            if done % 1000 == 0 {
+                info!("Copied {}/{} pages", done, progress.pagecount);
+            }
+        }),
+    )?;
+    drop(dest);
+
+    let size = std::fs::metadata(destination)?.len();
+    info!("Backup complete ({} bytes)", size);
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+use crate::export::ExportMessage;
+use crate::snowflake::Snowflake;
+use crate::SimpleResult;
+
+/// Avatars aren't stored, authors get the default avatar Discord shows for them.
+const DEFAULT_AVATAR_URL: &str = "https://cdn.discordapp.com/embed/avatars";
+
+/// What DiscordChatExporter files need beyond the exported messages: the guild of every
+/// channel, authors' discriminators and display names, and the date range of the export.
+pub struct Context {
+    guilds: HashMap<Snowflake, Guild>,
+    users: HashMap<Snowflake, (Option<String>, Option<String>)>,
+    date_range: DateRange,
+}
+
+impl Context {
+    /// `after` and `before` are unix seconds, as given to the export's filters.
+    pub fn load(
+        conn: &rusqlite::Connection,
+        after: Option<i64>,
+        before: Option<i64>,
+    ) -> SimpleResult<Self> {
+        let guilds = conn
+            .prepare("SELECT c.id, g.id, g.name FROM channel c JOIN guild g ON g.id = c.guild_id")?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    Guild {
+                        id: row.get(1)?,
+                        name: row.get(2)?,
+                        icon_url: format!("{}/0.png", DEFAULT_AVATAR_URL),
+                    },
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+        let users = conn
+            .prepare("SELECT id, discriminator, global_name FROM user")?
+            .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+            .collect::<Result<_, _>>()?;
+        let timestamp = |unix: Option<i64>| -> SimpleResult<Option<String>> {
+            Ok(match unix {
+                Some(unix) => Some(conn.query_row(
+                    "SELECT strftime('%Y-%m-%dT%H:%M:%S+00:00', ?, 'unixepoch')",
+                    [unix],
+                    |row| row.get(0),
+                )?),
+                None => None,
+            })
+        };
+
+        Ok(Context {
+            guilds,
+            users,
+            date_range: DateRange {
+                after: timestamp(after)?,
+                before: timestamp(before)?,
+            },
+        })
+    }
+
+    fn guild(&self, channel_id: Snowflake) -> Guild {
+        self.guilds.get(&channel_id).cloned().unwrap_or(Guild {
+            id: Snowflake(0),
+            name: "Direct Messages".to_string(),
+            icon_url: format!("{}/0.png", DEFAULT_AVATAR_URL),
+        })
+    }
+
+    /// Starts the export of the channel `message` was sent in.
+    pub fn export(&self, message: &ExportMessage, exported_at: &str) -> Export {
+        let guild = self.guild(message.channel_id);
+        Export {
+            channel: Channel {
+                id: message.channel_id,
+                kind: if guild.id == Snowflake(0) {
+                    "DirectTextChat"
+                } else {
+                    "GuildTextChat"
+                },
+                category_id: None,
+                category: None,
+                name: message.channel_name.clone(),
+                topic: None,
+            },
+            guild,
+            date_range: self.date_range.clone(),
+            exported_at: exported_at.to_string(),
+            messages: Vec::new(),
+            message_count: 0,
+        }
+    }
+
+    pub fn message(&self, message: ExportMessage, guild_id: Snowflake) -> Message {
+        // Anonymized authors aren't in the user table and have neither anyway.
+        let (discriminator, global_name) = self
+            .users
+            .get(&message.author_id)
+            .cloned()
+            .unwrap_or_default();
+        let discriminator = discriminator.unwrap_or_else(|| "0000".to_string());
+        let avatar = match discriminator.parse::<u64>() {
+            Ok(n) if n > 0 => n % 5,
+            // Users without a discriminator get a default avatar picked from their ID.
+            _ => (message.author_id.0 as u64 >> 22) % 6,
+        };
+        Message {
+            id: message.id,
+            kind: if message.reply_to.is_some() {
+                "Reply"
+            } else {
+                "Default"
+            },
+            timestamp: message.timestamp,
+            timestamp_edited: None,
+            call_ended_timestamp: None,
+            is_pinned: false,
+            content: message.content,
+            author: Author {
+                id: message.author_id,
+                nickname: global_name.unwrap_or_else(|| message.author.clone()),
+                name: message.author,
+                discriminator,
+                color: None,
+                is_bot: false,
+                roles: Vec::new(),
+                avatar_url: format!("{}/{}.png", DEFAULT_AVATAR_URL, avatar),
+            },
+            attachments: Vec::new(),
+            embeds: Vec::new(),
+            stickers: Vec::new(),
+            reactions: Vec::new(),
+            mentions: Vec::new(),
+            reference: match (message.origin_message_id, message.origin_channel_id) {
+                // Crossposts and forwards point at the message they copy, as Discord's own
+                // references do.
+                (Some(message_id), Some(channel_id)) => Some(Reference {
+                    message_id,
+                    channel_id,
+                    guild_id: message.origin_guild_id.unwrap_or(guild_id),
+                }),
+                _ => message.reply_to.map(|message_id| Reference {
+                    message_id,
+                    channel_id: message.channel_id,
+                    guild_id,
+                }),
+            },
+        }
+    }
+}
+
+/// One channel in DiscordChatExporter's JSON schema.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Export {
+    pub guild: Guild,
+    pub channel: Channel,
+    date_range: DateRange,
+    exported_at: String,
+    pub messages: Vec<Message>,
+    pub message_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Guild {
+    pub id: Snowflake,
+    name: String,
+    icon_url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Channel {
+    pub id: Snowflake,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    category_id: Option<Snowflake>,
+    category: Option<String>,
+    name: String,
+    topic: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DateRange {
+    after: Option<String>,
+    before: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    id: Snowflake,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    timestamp: String,
+    timestamp_edited: Option<String>,
+    call_ended_timestamp: Option<String>,
+    is_pinned: bool,
+    content: String,
+    author: Author,
+    attachments: Vec<()>,
+    embeds: Vec<()>,
+    stickers: Vec<()>,
+    reactions: Vec<()>,
+    mentions: Vec<()>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<Reference>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Author {
+    id: Snowflake,
+    name: String,
+    discriminator: String,
+    nickname: String,
+    color: Option<String>,
+    is_bot: bool,
+    roles: Vec<()>,
+    avatar_url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Reference {
+    message_id: Snowflake,
+    channel_id: Snowflake,
+    guild_id: Snowflake,
+}
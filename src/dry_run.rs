@@ -0,0 +1,145 @@
+use crate::api::ApiClient;
+use crate::client;
+use crate::snowflake::Snowflake;
+use crate::{send_request, Args, ChannelArg, GuildCache, Message, SimpleResult};
+use crate::{BASE_URL, PAGE_SIZE};
+
+/// What scraping one channel would involve.
+struct Estimate {
+    name: String,
+    first: Option<Message>,
+    last: Option<Message>,
+    messages: u64,
+    /// Whether `messages` was counted rather than extrapolated.
+    exact: bool,
+}
+
+/// Prints what a scrape with `args` would fetch, and roughly how many requests it would
+/// take, from a few requests per channel. Nothing is written to the database.
+pub fn run(args: &Args, client: &dyn ApiClient) -> SimpleResult<()> {
+    let mut guilds = GuildCache::default();
+    let mut requests = 0;
+    let mut total_messages = 0;
+
+    println!(
+        "{:<20} {:<24} {:<10} {:<10} {:>10} {:>9}",
+        "CHANNEL", "NAME", "FIRST", "LAST", "MESSAGES", "REQUESTS"
+    );
+    for channel_arg in &args.channel_ids {
+        // Channels of guilds already listed are looked up without a request.
+        let listed = match channel_arg {
+            ChannelArg::Id(channel_id) => guilds.listed(*channel_id).is_some(),
+            ChannelArg::Name { guild_id, .. } => guilds.channels.contains_key(guild_id),
+        };
+        let channel = guilds.channel(client, channel_arg)?;
+        if !listed {
+            requests += 1;
+        }
+        if let Some(guild_id) = channel.guild_id {
+            if guilds.stored.insert(guild_id) {
+                // The guild, its roles, scheduled events and soundboard sounds, and the
+                // command index with --app-commands.
+                requests += if args.app_commands && !client::is_bot() {
+                    5
+                } else {
+                    4
+                };
+                // Its channels, listed here too so the guild's other channels are found.
+                if !guilds.channels.contains_key(&guild_id) {
+                    guilds.guild_channels(client, guild_id)?;
+                    requests += 1;
+                }
+            }
+        }
+
+        let estimate = estimate(client, channel.id, channel.name.unwrap_or_default())?;
+        // Full pages until the last, partial or empty, one.
+        let pages = estimate.messages / PAGE_SIZE + 1;
+        requests += pages;
+        total_messages += estimate.messages;
+
+        let date = |m: &Option<Message>| match m {
+            Some(m) => m.timestamp.get(..10).unwrap_or_default().to_string(),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<20} {:<24} {:<10} {:<10} {:>10} {:>9}",
+            channel.id.to_string(),
+            estimate.name,
+            date(&estimate.first),
+            date(&estimate.last),
+            if estimate.exact {
+                estimate.messages.to_string()
+            } else {
+                format!("~{}", estimate.messages)
+            },
+            pages
+        );
+    }
+
+    info!(
+        "Would scrape ~{} Messages from {} channels in ~{} requests",
+        total_messages,
+        args.channel_ids.len(),
+        requests
+    );
+    if let Some(rps) = args.rps {
+        info!(
+            "At --rps {} that takes at least {}",
+            rps,
+            duration((requests as f64 / rps) as u64)
+        );
+    }
+    Ok(())
+}
+
+fn duration(seconds: u64) -> String {
+    match seconds {
+        0..=119 => format!("{}s", seconds),
+        120..=7199 => format!("{}m", seconds / 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+/// Counts the messages of a channel with a single page when it has few, otherwise
+/// extrapolates the rate of its newest page over its whole lifetime.
+fn estimate(client: &dyn ApiClient, channel_id: Snowflake, name: String) -> SimpleResult<Estimate> {
+    let newest = get_page(client, channel_id, &format!("limit={}", PAGE_SIZE))?;
+    if (newest.len() as u64) < PAGE_SIZE {
+        return Ok(Estimate {
+            name,
+            messages: newest.len() as u64,
+            last: newest.first().cloned(),
+            first: newest.last().cloned(),
+            exact: true,
+        });
+    }
+    // Messages right after the channel's first ID start from the oldest one.
+    let oldest = get_page(client, channel_id, "limit=1&after=0")?;
+
+    // IDs are shifted milliseconds, so their differences measure time.
+    let ms = |m: &Message| (m.id.0 >> 22) as f64;
+    let page_span = ms(&newest[0]) - ms(&newest[newest.len() - 1]);
+    let lifetime = match oldest.first() {
+        Some(oldest) => ms(&newest[0]) - ms(oldest),
+        None => page_span,
+    };
+    let rate = (newest.len() - 1) as f64 / page_span.max(1.0);
+    Ok(Estimate {
+        name,
+        messages: ((lifetime * rate) as u64).max(newest.len() as u64),
+        last: newest.first().cloned(),
+        first: oldest.into_iter().next(),
+        exact: false,
+    })
+}
+
+fn get_page(
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    query: &str,
+) -> SimpleResult<Vec<Message>> {
+    let req_url = format!("{}/channels/{}/messages?{}", BASE_URL, channel_id, query);
+    let body = send_request(client, &req_url)?;
+    Ok(serde_json::from_str(&body)?)
+}
@@ -0,0 +1,195 @@
+use clap::Args;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::snowflake::Snowflake;
+use crate::SimpleResult;
+
+#[derive(Debug, Args)]
+pub struct DuplicatesArgs {
+    /// Only compare messages in this channel, can be given several times
+    #[clap(long = "channel", value_name = "CHANNEL_ID")]
+    channels: Vec<Snowflake>,
+
+    /// How alike two messages must be to count as duplicates, from 0 to 1 (Jaccard similarity
+    /// of their character shingles)
+    #[clap(long, default_value_t = 0.8, value_name = "SIMILARITY")]
+    threshold: f64,
+
+    /// Leave out messages shorter than this many characters, which are alike by chance
+    #[clap(long, default_value_t = 30, value_name = "CHARS")]
+    min_length: usize,
+
+    /// Output file, defaults to stdout
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+/// Characters per shingle.
+const SHINGLE: usize = 5;
+/// Hashes per signature, split into bands for locality-sensitive hashing. Messages
+/// agreeing on every hash of any one band are compared, which with 16 bands of 4 finds
+/// most pairs more than half alike.
+const HASHES: usize = 64;
+const BANDS: usize = 16;
+const ROWS: usize = HASHES / BANDS;
+
+struct Candidate {
+    id: Snowflake,
+    channel_id: Snowflake,
+    author_id: Snowflake,
+    signature: [u32; HASHES],
+}
+
+/// Groups of near-identical messages as CSV, largest first, with how alike each message is
+/// to the first of its group.
+pub fn report(conn: &rusqlite::Connection, args: &DuplicatesArgs) -> SimpleResult<String> {
+    if !(0.0..=1.0).contains(&args.threshold) {
+        let err_msg = format!(
+            "--threshold must be between 0 and 1, got {}",
+            args.threshold
+        );
+        return Err(err_msg.into());
+    }
+    let candidates = load(conn, args)?;
+
+    // Messages sharing a band are compared with the first message seen in it.
+    let mut groups = UnionFind::new(candidates.len());
+    let mut buckets: HashMap<(usize, u64), usize> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        for (band, rows) in candidate.signature.chunks(ROWS).enumerate() {
+            let mut hasher = DefaultHasher::new();
+            rows.hash(&mut hasher);
+            let first = *buckets.entry((band, hasher.finish())).or_insert(i);
+            if first != i && similarity(&candidates[first], candidate) >= args.threshold {
+                groups.union(first, i);
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..candidates.len() {
+        members.entry(groups.find(i)).or_default().push(i);
+    }
+    let mut members: Vec<Vec<usize>> = members.into_values().filter(|m| m.len() > 1).collect();
+    members.sort_by_key(|m| (std::cmp::Reverse(m.len()), candidates[m[0]].id));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "group",
+        "message_id",
+        "channel_id",
+        "author_id",
+        "similarity",
+    ])?;
+    for (group, members) in members.iter().enumerate() {
+        let first = &candidates[members[0]];
+        for candidate in members.iter().map(|&i| &candidates[i]) {
+            writer.write_record([
+                (group + 1).to_string(),
+                candidate.id.to_string(),
+                candidate.channel_id.to_string(),
+                candidate.author_id.to_string(),
+                format!("{:.2}", similarity(first, candidate)),
+            ])?;
+        }
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn load(conn: &rusqlite::Connection, args: &DuplicatesArgs) -> SimpleResult<Vec<Candidate>> {
+    let mut sql = String::from(
+        "SELECT id, channel_id, author_id, content FROM message WHERE length(content) >= ?",
+    );
+    if !args.channels.is_empty() {
+        sql += &format!(
+            " AND channel_id IN {}",
+            crate::values_placeholders(1, args.channels.len())
+        );
+    }
+    sql += " ORDER BY id";
+    let mut params: Vec<&dyn rusqlite::ToSql> = vec![&args.min_length];
+    params.extend(args.channels.iter().map(|c| c as &dyn rusqlite::ToSql));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params.as_slice())?;
+    let mut candidates = Vec::new();
+    while let Some(row) = rows.next()? {
+        let content: String = row.get(3)?;
+        candidates.push(Candidate {
+            id: row.get(0)?,
+            channel_id: row.get(1)?,
+            author_id: row.get(2)?,
+            signature: signature(&content),
+        });
+    }
+    Ok(candidates)
+}
+
+/// The MinHash signature of the shingles of `content`, ignoring case and spacing.
+fn signature(content: &str) -> [u32; HASHES] {
+    let normalized: Vec<char> = content
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let mut signature = [u32::MAX; HASHES];
+    for shingle in normalized.windows(SHINGLE.min(normalized.len()).max(1)) {
+        let mut hasher = DefaultHasher::new();
+        shingle.hash(&mut hasher);
+        let hash = hasher.finish();
+        for (seed, min) in signature.iter_mut().enumerate() {
+            *min = (*min).min(mix(hash ^ seed as u64) as u32);
+        }
+    }
+    signature
+}
+
+/// SplitMix64's finalizer, turning one hash into as many independent ones as there are seeds.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Estimated Jaccard similarity: the share of hashes both signatures agree on.
+fn similarity(a: &Candidate, b: &Candidate) -> f64 {
+    let same = a
+        .signature
+        .iter()
+        .zip(&b.signature)
+        .filter(|(x, y)| x == y)
+        .count();
+    same as f64 / HASHES as f64
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        UnionFind {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        // The older message stays the root, so groups are led by their first message.
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
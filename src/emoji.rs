@@ -0,0 +1,191 @@
+use handlebars::html_escape;
+use regex::Regex;
+
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::OnceLock;
+
+const TWEMOJI_URL: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/svg";
+const CUSTOM_EMOJI_URL: &str = "https://cdn.discordapp.com/emojis";
+
+const ZWJ: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+
+/// Custom emoji as written in content, `<:name:id>` or `<a:name:id>` when animated.
+fn custom_emoji() -> &'static Regex {
+    static CUSTOM: OnceLock<Regex> = OnceLock::new();
+    CUSTOM.get_or_init(|| Regex::new(r"<(a?):(\w+):(\d+)>").unwrap())
+}
+
+/// HTML-escapes message content, replacing unicode emoji with Twemoji images and
+/// custom `<:name:id>` emoji with the images Discord serves for them.
+pub fn render_html(content: &str) -> String {
+    let mut html = String::new();
+    let mut last = 0;
+    for caps in custom_emoji().captures_iter(content) {
+        let token = caps.get(0).unwrap();
+        html += &render_unicode(&content[last..token.start()]);
+        let extension = if &caps[1] == "a" { "gif" } else { "png" };
+        html += &format!(
+            r#"<img class="emoji" alt=":{0}:" title=":{0}:" src="{1}/{2}.{3}">"#,
+            &caps[2], CUSTOM_EMOJI_URL, &caps[3], extension
+        );
+        last = token.end();
+    }
+    html += &render_unicode(&content[last..]);
+    html
+}
+
+/// The emoji used in message content, custom ones as `name:id` the way reactions are
+/// stored, unicode ones as returned by `key`.
+pub fn find(content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut unicode = |text: &str| {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if starts_emoji(c, chars.peek().copied()) {
+                let sequence: String = sequence(c, &mut chars).iter().collect();
+                found.push(key(&sequence));
+            }
+        }
+    };
+    let mut last = 0;
+    for caps in custom_emoji().captures_iter(content) {
+        let token = caps.get(0).unwrap();
+        unicode(&content[last..token.start()]);
+        last = token.end();
+    }
+    unicode(&content[last..]);
+    found.extend(
+        custom_emoji()
+            .captures_iter(content)
+            .map(|caps| format!("{}:{}", &caps[2], &caps[3])),
+    );
+    found
+}
+
+/// An emoji as it's counted: with the U+FE0F that asks for emoji presentation only
+/// where it's needed, since some keyboards add it everywhere and others leave it out.
+/// ZWJ sequences and custom `name:id` emoji are returned as they are.
+pub fn key(emoji: &str) -> String {
+    if emoji.contains(ZWJ) || emoji.contains(':') {
+        return emoji.to_string();
+    }
+    let mut chars: Vec<char> = emoji.chars().filter(|&c| c != VARIATION_SELECTOR).collect();
+    if let Some(&first) = chars.first() {
+        if !starts_emoji(first, chars.get(1).copied()) {
+            chars.insert(1, VARIATION_SELECTOR);
+        }
+    }
+    chars.into_iter().collect()
+}
+
+fn render_unicode(text: &str) -> String {
+    let mut html = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !starts_emoji(c, chars.peek().copied()) {
+            html += &html_escape(&c.to_string());
+            continue;
+        }
+
+        let sequence = sequence(c, &mut chars);
+        let alt: String = sequence.iter().collect();
+        html += &format!(
+            r#"<img class="emoji" alt="{}" src="{}/{}.svg">"#,
+            html_escape(&alt),
+            TWEMOJI_URL,
+            twemoji_code(&sequence)
+        );
+    }
+    html
+}
+
+/// The whole emoji starting with `c`: its modifiers, variation selectors and ZWJ-joined
+/// parts, taken from `chars`.
+fn sequence(c: char, chars: &mut Peekable<Chars>) -> Vec<char> {
+    let mut sequence = vec![c];
+    let regional_indicator = is_regional_indicator(c);
+    while let Some(&next) = chars.peek() {
+        let joined = sequence.last() == Some(&ZWJ) && !next.is_ascii();
+        let flag_pair = regional_indicator && sequence.len() == 1 && is_regional_indicator(next);
+        if is_modifier(next) || next == ZWJ || joined || flag_pair {
+            sequence.push(next);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    sequence
+}
+
+/// Twemoji names files by code points, dropping U+FE0F unless the sequence has a ZWJ.
+fn twemoji_code(sequence: &[char]) -> String {
+    let keep_selectors = sequence.contains(&ZWJ);
+    sequence
+        .iter()
+        .filter(|&&c| keep_selectors || c != VARIATION_SELECTOR)
+        .map(|&c| format!("{:x}", c as u32))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Whether `c` begins an emoji: either a character shown as emoji by default, or a symbol
+/// asking for emoji presentation with a following U+FE0F (e.g. ❤️ or 1️⃣). Of ASCII, only
+/// the keycap bases can.
+fn starts_emoji(c: char, next: Option<char>) -> bool {
+    if next == Some(VARIATION_SELECTOR) {
+        return !c.is_ascii() || c.is_ascii_digit() || c == '#' || c == '*';
+    }
+    let code = c as u32;
+    if (0x1F000..=0x1FAFF).contains(&code) {
+        // Besides two tiles, the game symbols at the start of the block aren't emoji.
+        return !is_modifier(c) && (code >= 0x1F100 || code == 0x1F004 || code == 0x1F0CF);
+    }
+    matches!(
+        code,
+        0x231A..=0x231B
+            | 0x23E9..=0x23EC
+            | 0x23F0
+            | 0x23F3
+            | 0x25FD..=0x25FE
+            | 0x2614..=0x2615
+            | 0x2648..=0x2653
+            | 0x267F
+            | 0x2693
+            | 0x26A1
+            | 0x26AA..=0x26AB
+            | 0x26BD..=0x26BE
+            | 0x26C4..=0x26C5
+            | 0x26CE
+            | 0x26D4
+            | 0x26EA
+            | 0x26F2..=0x26F3
+            | 0x26F5
+            | 0x26FA
+            | 0x26FD
+            | 0x2705
+            | 0x270A..=0x270B
+            | 0x2728
+            | 0x274C
+            | 0x274E
+            | 0x2753..=0x2755
+            | 0x2757
+            | 0x2795..=0x2797
+            | 0x27B0
+            | 0x27BF
+            | 0x2B1B..=0x2B1C
+            | 0x2B50
+            | 0x2B55
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+/// Characters that only change the emoji before them: skin tones, variation selectors,
+/// the keycap mark and tag characters.
+fn is_modifier(c: char) -> bool {
+    matches!(c as u32, 0x1F3FB..=0x1F3FF | 0xFE0F | 0x20E3 | 0xE0020..=0xE007F)
+}
@@ -0,0 +1,59 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{Message, SimpleResult};
+
+/// Computes something about each message as it's stored, e.g. its language or the links in
+/// it, and writes it to tables of its own. Enrichers are registered before scraping and run
+/// on every page of messages right after it's inserted, as the rows they write reference
+/// the stored messages.
+pub trait Enricher: Send + Sync {
+    /// Named in errors.
+    fn name(&self) -> &str;
+
+    /// Creates the tables the enricher writes to, run whenever a database is opened.
+    /// Built-in enrichers write to tables of the schema and have nothing to create.
+    fn setup(&self, _conn: &rusqlite::Connection) -> SimpleResult<()> {
+        Ok(())
+    }
+
+    /// Stores what it computes for `messages`, all of which are in the database already.
+    /// Messages may be given again when a channel is scraped again.
+    fn enrich(&self, tx: &rusqlite::Transaction, messages: &[Message]) -> SimpleResult<()>;
+}
+
+static ENRICHERS: RwLock<Vec<Arc<dyn Enricher>>> = RwLock::new(Vec::new());
+
+/// Runs `enricher` on every page stored from now on, after those registered before it.
+pub fn register(enricher: impl Enricher + 'static) {
+    ENRICHERS.write().unwrap().push(Arc::new(enricher));
+}
+
+fn enrichers() -> Vec<Arc<dyn Enricher>> {
+    ENRICHERS.read().unwrap().clone()
+}
+
+pub fn setup(conn: &rusqlite::Connection) -> SimpleResult<()> {
+    for enricher in enrichers() {
+        enricher
+            .setup(conn)
+            .map_err(|e| format!("Setting up {}: {}", enricher.name(), e))?;
+    }
+    Ok(())
+}
+
+/// Runs every enricher on a page of stored messages, in one transaction so a page is
+/// enriched by all of them or none.
+pub fn apply(conn: &mut rusqlite::Connection, messages: &[Message]) -> SimpleResult<()> {
+    let enrichers = enrichers();
+    if enrichers.is_empty() || messages.is_empty() {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    for enricher in enrichers {
+        enricher
+            .enrich(&tx, messages)
+            .map_err(|e| format!("{} failed: {}", enricher.name(), e))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::error::Error;
+use std::fmt;
+
+// Exit codes of the process, kept stable so wrapper scripts can tell failures apart.
+// Usage errors exit with 2, as clap does.
+
+/// Anything not covered by a more specific code.
+pub const FAILURE: i32 = 1;
+/// No token was found, or Discord rejected every token.
+pub const AUTH: i32 = 3;
+/// A channel doesn't exist or the token may not read it.
+pub const CHANNEL: i32 = 4;
+/// Discord asked to wait longer than `--max-rate-limit-wait`.
+pub const RATE_LIMITED: i32 = 5;
+/// The database couldn't be opened or written, or `verify` found problems in it.
+pub const DATABASE: i32 = 6;
+/// Some channels were scraped, others failed.
+pub const PARTIAL: i32 = 7;
+/// Stopped by Ctrl-C.
+pub const INTERRUPTED: i32 = 130;
+
+/// An error that ends the process with `code`.
+#[derive(Debug)]
+pub struct Failure {
+    pub code: i32,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Failure {}
+
+/// The code a run that failed with `err` exits with.
+pub fn code(err: &(dyn Error + 'static)) -> i32 {
+    if let Some(failure) = err.downcast_ref::<Failure>() {
+        failure.code
+    } else if err.is::<rusqlite::Error>() {
+        DATABASE
+    } else {
+        FAILURE
+    }
+}
+
+/// The code of a request that got an error `status` from Discord.
+pub fn for_status(status: u16) -> i32 {
+    match status {
+        401 => AUTH,
+        403 | 404 => CHANNEL,
+        _ => FAILURE,
+    }
+}
@@ -0,0 +1,814 @@
+use clap::{ArgEnum, Args};
+use flate2::write::GzEncoder;
+use handlebars::{handlebars_helper, Handlebars};
+use regex::Regex;
+use rusqlite::ToSql;
+use serde::Serialize;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::anonymize::Anonymizer;
+use crate::cutoff::Cutoff;
+use crate::emoji;
+use crate::mentions::MentionResolver;
+use crate::redact::Redactor;
+use crate::snowflake::Snowflake;
+use crate::timezone;
+use crate::{db, dce};
+use crate::{values_placeholders, SimpleResult, User, HAS_SNAPSHOT};
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum Format {
+    /// One `[timestamp] #channel author: content` line per message
+    Txt,
+    /// One JSON object per line
+    Jsonl,
+    /// Comma separated values with a header row
+    Csv,
+    /// A readable HTML transcript
+    Html,
+    /// Chat fine-tuning examples: each message as a response to the messages before it
+    JsonlPairs,
+    /// DiscordChatExporter's JSON, one channel per file, readable by the tools built around it
+    Dce,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Txt => "txt",
+            Format::Jsonl | Format::JsonlPairs => "jsonl",
+            Format::Csv => "csv",
+            Format::Html => "html",
+            Format::Dce => "json",
+        }
+    }
+}
+
+/// Template of the `html` format, also a starting point for custom templates.
+const TRANSCRIPT_TEMPLATE: &str = include_str!("templates/transcript.hbs");
+/// Index page linking the channel transcripts of a `--per-channel` HTML export.
+const INDEX_TEMPLATE: &str = include_str!("templates/index.hbs");
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[clap(long, arg_enum, default_value = "txt")]
+    format: Format,
+
+    /// Render the export with this Handlebars template instead of --format
+    #[clap(long, value_name = "FILE")]
+    template: Option<String>,
+
+    /// Output file, defaults to stdout. Compressed when it ends in .gz or .zst
+    #[clap(short, long)]
+    output: Option<String>,
+
+    /// Write one file per channel into this directory, along with an index of them, or into
+    /// a tar archive when it ends in .tar, .tar.gz or .tar.zst
+    #[clap(long, value_name = "DIR", conflicts_with = "output")]
+    per_channel: Option<String>,
+
+    /// Only export this channel, can be given several times
+    #[clap(long = "channel", value_name = "CHANNEL_ID")]
+    channels: Vec<Snowflake>,
+
+    /// Only export messages by this user, given by ID or username, can be given several times
+    #[clap(long = "author", value_name = "AUTHOR")]
+    authors: Vec<String>,
+
+    /// Export every message by this user across all channels as one chronological transcript
+    #[clap(long, value_name = "USER_ID", conflicts_with_all = &["authors", "per-channel"])]
+    user: Option<Snowflake>,
+
+    /// Only export messages sent at or after this date (e.g. 2021-01-01) or age (e.g. 30d)
+    #[clap(long)]
+    after: Option<Cutoff>,
+
+    /// Only export messages sent before this date or age
+    #[clap(long)]
+    before: Option<Cutoff>,
+
+    /// Only export messages detected to be in this language, given as an ISO 639-3 code like
+    /// eng, can be given several times (needs scraping with --detect-language)
+    #[clap(long = "lang", value_name = "CODE")]
+    langs: Vec<String>,
+
+    /// Leave out messages by users flagged by `analyze spam`
+    #[clap(long)]
+    exclude_flagged: bool,
+
+    /// Only export messages whose content matches this regular expression
+    #[clap(long = "match", value_name = "REGEX")]
+    pattern: Option<Regex>,
+
+    /// Only export messages newer than the last export under this name (default "default"),
+    /// then remember the newest message of each channel for the next one
+    #[clap(
+        long,
+        value_name = "NAME",
+        min_values = 0,
+        default_missing_value = "default"
+    )]
+    delta: Option<String>,
+
+    /// Group messages into reply threads, each reply following and indented under the message it answers
+    #[clap(long)]
+    threads: bool,
+
+    /// Messages of context preceding each response in `jsonl-pairs` exports
+    #[clap(long, default_value_t = 5)]
+    context: usize,
+
+    /// Keep `<@id>`, `<@&id>` and `<#id>` mentions instead of replacing them with names
+    #[clap(long)]
+    raw_mentions: bool,
+}
+
+/// A message as it appears in exports.
+#[derive(Debug, Serialize)]
+pub struct ExportMessage {
+    pub id: Snowflake,
+    pub channel_id: Snowflake,
+    pub channel_name: String,
+    pub author_id: Snowflake,
+    pub author: String,
+    pub timestamp: String,
+    pub content: String,
+    pub reply_to: Option<Snowflake>,
+    /// Where a crossposted announcement was published.
+    pub origin_guild_id: Option<Snowflake>,
+    pub origin_channel_id: Option<Snowflake>,
+    pub origin_message_id: Option<Snowflake>,
+    /// Whether the message forwards the origin message, rather than being a crosspost of it.
+    pub forwarded: bool,
+    /// How long a voice message is, in seconds.
+    pub voice_duration_secs: Option<f64>,
+    /// Whether a refresh found the message deleted on Discord.
+    pub deleted: bool,
+    /// How deeply the message is nested in its reply thread, only set with `--threads`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+}
+
+/// Everything a template is rendered with.
+#[derive(Debug, Serialize)]
+struct Transcript {
+    title: String,
+    generated_at: String,
+    message_count: usize,
+    channels: Vec<TranscriptChannel>,
+}
+
+#[derive(Debug, Serialize)]
+struct TranscriptChannel {
+    id: Snowflake,
+    name: String,
+    messages: Vec<ExportMessage>,
+}
+
+/// Writes the messages selected by the filters, applying `redactor` to their content
+/// and replacing user identities when an `anonymizer` is given.
+impl ExportArgs {
+    /// The file or directory the export is written to, `None` for stdout.
+    pub fn destination(&self) -> Option<&str> {
+        self.per_channel.as_deref().or(self.output.as_deref())
+    }
+
+    /// Whether the export writes to the database, recording how far a delta got.
+    pub fn writes(&self) -> bool {
+        self.delta.is_some()
+    }
+}
+
+pub fn run(
+    conn: &rusqlite::Connection,
+    args: ExportArgs,
+    redactor: Option<&Redactor>,
+    anonymizer: Option<&Anonymizer>,
+) -> SimpleResult<()> {
+    // Read from one snapshot, so a scrape running alongside can't add messages to channels
+    // already written.
+    let snapshot = db::snapshot(conn)?;
+    let mut sql = String::from(
+        "SELECT m.id, m.channel_id, c.name, m.author_id, u.username, m.timestamp, m.content, m.reply_to,
+         m.origin_guild_id, m.origin_channel_id, m.origin_message_id, m.flags, m.voice_duration_secs,
+         m.deleted
+         FROM message m
+         JOIN user u ON u.id = m.author_id
+         JOIN channel c ON c.id = m.channel_id
+         WHERE 1 = 1",
+    );
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+    if !args.channels.is_empty() {
+        sql += &format!(
+            " AND m.channel_id IN {}",
+            values_placeholders(1, args.channels.len())
+        );
+        for channel_id in &args.channels {
+            params.push(Box::new(*channel_id));
+        }
+    }
+    if !args.authors.is_empty() {
+        let conditions = vec!["m.author_id = ? OR u.username = ?"; args.authors.len()];
+        sql += &format!(" AND ({})", conditions.join(" OR "));
+        for author in &args.authors {
+            params.push(Box::new(author.parse::<Snowflake>().ok()));
+            params.push(Box::new(author.clone()));
+        }
+    }
+    if !args.langs.is_empty() {
+        sql += &format!(
+            " AND m.id IN (SELECT message_id FROM message_language WHERE lang IN {})",
+            values_placeholders(1, args.langs.len())
+        );
+        for lang in &args.langs {
+            params.push(Box::new(lang.to_lowercase()));
+        }
+    }
+    if args.exclude_flagged {
+        sql += " AND m.author_id NOT IN (SELECT user_id FROM flagged_user)";
+    }
+    if let Some(after) = args.after {
+        sql += " AND m.created_at_unix >= ?";
+        params.push(Box::new(after.0));
+    }
+    if let Some(before) = args.before {
+        sql += " AND m.created_at_unix < ?";
+        params.push(Box::new(before.0));
+    }
+    if let Some(name) = &args.delta {
+        sql += " AND m.id > COALESCE((
+            SELECT last_message_id FROM export_watermark WHERE name = ? AND channel_id = m.channel_id), 0)";
+        params.push(Box::new(name.clone()));
+    }
+    let title = match args.user {
+        Some(user_id) => {
+            sql += " AND m.author_id = ?";
+            params.push(Box::new(user_id));
+            let user = conn
+                .query_row(
+                    "SELECT id, username, discriminator, global_name FROM user WHERE id = ?",
+                    [user_id],
+                    |row| {
+                        Ok(User {
+                            id: row.get(0)?,
+                            username: row.get(1)?,
+                            discriminator: row.get(2)?,
+                            global_name: row.get(3)?,
+                        })
+                    },
+                )
+                .map_err(|_| format!("No stored user with ID {}", user_id))?;
+            let user = match anonymizer {
+                Some(anonymizer) => anonymizer.user(user),
+                None => user,
+            };
+            format!("Messages by {} ({})", user.username, user.id)
+        }
+        None => "Discord Transcript".to_string(),
+    };
+    // A user's messages are read in the order they were sent, everything else channel by channel.
+    sql += match args.user {
+        Some(_) => " ORDER BY m.id",
+        None => " ORDER BY m.channel_id, m.id",
+    };
+
+    let template = match (&args.template, args.format) {
+        (Some(path), _) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("Could not read template {}: {}", path, e))?,
+        ),
+        (None, Format::Html) => Some(TRANSCRIPT_TEMPLATE.to_string()),
+        (None, _) => None,
+    };
+    let renderer = Renderer {
+        format: args.format,
+        handlebars: template.map(handlebars).transpose()?,
+        extension: match &args.template {
+            Some(path) => template_extension(path),
+            None => args.format.extension().to_string(),
+        },
+        context: args.context,
+        dce: match args.format {
+            Format::Dce => Some(dce::Context::load(
+                conn,
+                args.after.map(|c| c.0),
+                args.before.map(|c| c.0),
+            )?),
+            _ => None,
+        },
+        title,
+        generated_at: timezone::local(&conn.query_row(
+            "SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')",
+            [],
+            |row| row.get::<_, String>(0),
+        )?),
+    };
+
+    let mentions = if args.raw_mentions {
+        None
+    } else {
+        Some(MentionResolver::load(conn)?)
+    };
+
+    // Archives are packed from a directory of their files once those are complete.
+    let archive = args
+        .per_channel
+        .as_deref()
+        .filter(|path| archive_extension(path).is_some());
+    let dir = match archive {
+        Some(archive) => Some(format!("{}.parts", archive)),
+        None => args.per_channel.clone(),
+    };
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params.iter()))?;
+    if let Some(dir) = &dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut output = Output {
+        renderer: &renderer,
+        per_channel: dir.as_deref(),
+        sink: match &dir {
+            Some(_) => None,
+            None => {
+                let out: Box<dyn Write> = match &args.output {
+                    Some(path) => create(path)?,
+                    None => Box::new(std::io::stdout()),
+                };
+                Some(renderer.open(out))
+            }
+        },
+        files: Vec::new(),
+    };
+    let mut threaded = Vec::new();
+    let mut watermarks: Vec<(Snowflake, Snowflake)> = Vec::new();
+    let mut exported = 0;
+    while let Some(row) = rows.next()? {
+        let mut message = ExportMessage {
+            id: row.get(0)?,
+            channel_id: row.get(1)?,
+            channel_name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            author_id: row.get(3)?,
+            author: row.get(4)?,
+            timestamp: timezone::local(&row.get::<_, String>(5)?),
+            content: row.get(6)?,
+            reply_to: row.get(7)?,
+            origin_guild_id: row.get(8)?,
+            origin_channel_id: row.get(9)?,
+            origin_message_id: row.get(10)?,
+            forwarded: row.get::<_, i64>(11)? & HAS_SNAPSHOT != 0,
+            voice_duration_secs: row.get(12)?,
+            deleted: row.get(13)?,
+            depth: None,
+        };
+        if let Some(anonymizer) = anonymizer {
+            // Before the other mentions are resolved, so user mentions never show real names.
+            let user = anonymizer.user(User {
+                id: message.author_id,
+                username: message.author,
+                discriminator: None,
+                global_name: None,
+            });
+            message.author_id = user.id;
+            message.author = user.username;
+            message.content = anonymizer.content(&message.content);
+        }
+        // Messages filtered out below still count as covered by this delta.
+        match watermarks.last_mut() {
+            Some((channel_id, last)) if *channel_id == message.channel_id => *last = message.id,
+            _ => watermarks.push((message.channel_id, message.id)),
+        }
+        if let Some(pattern) = &args.pattern {
+            if !pattern.is_match(&message.content) {
+                continue;
+            }
+        }
+        if let Some(mentions) = &mentions {
+            message.content = mentions.resolve(&message.content);
+        }
+        if let Some(redactor) = redactor {
+            message.content = redactor.redact(&message.content);
+        }
+
+        if args.threads {
+            threaded.push(message);
+        } else {
+            output.write(message)?;
+        }
+        exported += 1;
+    }
+    for message in thread_order(threaded) {
+        output.write(message)?;
+    }
+    let files = output.finish()?;
+    drop(rows);
+    drop(stmt);
+    snapshot.commit()?;
+
+    // Only advanced once everything is written, so a failed export is simply repeated.
+    if let Some(name) = &args.delta {
+        let tx = conn.unchecked_transaction()?;
+        for (channel_id, last_message_id) in &watermarks {
+            tx.execute(
+                "INSERT INTO export_watermark (name, channel_id, last_message_id) VALUES (?,?,?)
+                 ON CONFLICT (name, channel_id) DO UPDATE SET
+                    last_message_id = excluded.last_message_id, exported_at = excluded.exported_at",
+                rusqlite::params![name, channel_id, last_message_id],
+            )?;
+        }
+        tx.commit()?;
+    }
+
+    if let Some(dir) = &dir {
+        write_index(dir, &renderer, files)?;
+        if let Some(archive) = archive {
+            pack(dir, archive)?;
+        }
+        info!(
+            "Exported {} Messages to {}",
+            exported,
+            args.per_channel.as_deref().unwrap_or_default()
+        );
+    } else if let Some(path) = &args.output {
+        info!("Exported {} Messages to {}", exported, path);
+    }
+    Ok(())
+}
+
+/// Where rendered messages go: a single sink for the whole export or, with `--per-channel`,
+/// a new file each time the channel changes.
+struct Output<'a> {
+    renderer: &'a Renderer,
+    per_channel: Option<&'a str>,
+    sink: Option<Sink<'a>>,
+    files: Vec<ChannelFile>,
+}
+
+impl Output<'_> {
+    fn write(&mut self, message: ExportMessage) -> SimpleResult<()> {
+        if let Some(dir) = self.per_channel {
+            // Messages arrive ordered by channel, so each channel's file is written in one go.
+            if self.files.last().map(|f| f.id) != Some(message.channel_id) {
+                if let Some(sink) = self.sink.take() {
+                    sink.finish()?;
+                }
+                let file = format!(
+                    "{}-{}.{}",
+                    message.channel_id,
+                    file_name_safe(&message.channel_name),
+                    self.renderer.extension
+                );
+                let out = File::create(Path::new(dir).join(&file))?;
+                self.sink = Some(self.renderer.open(Box::new(out)));
+                self.files.push(ChannelFile {
+                    id: message.channel_id,
+                    name: message.channel_name.clone(),
+                    file,
+                    message_count: 0,
+                    first_timestamp: message.timestamp.clone(),
+                    last_timestamp: message.timestamp.clone(),
+                });
+            }
+            let file = self.files.last_mut().unwrap();
+            file.message_count += 1;
+            file.last_timestamp = message.timestamp.clone();
+        }
+
+        match &mut self.sink {
+            Some(sink) => sink.write(message),
+            None => Ok(()),
+        }
+    }
+
+    /// Finishes the last file and returns the channel files written.
+    fn finish(self) -> SimpleResult<Vec<ChannelFile>> {
+        if let Some(sink) = self.sink {
+            sink.finish()?;
+        }
+        Ok(self.files)
+    }
+}
+
+/// Reorders messages so every reply follows the message it answers, depth first, with
+/// replies to the same message in the order they were sent. Messages replying to
+/// something outside the export start threads of their own.
+fn thread_order(messages: Vec<ExportMessage>) -> Vec<ExportMessage> {
+    let positions: HashMap<Snowflake, usize> = messages
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.id, i))
+        .collect();
+    let mut replies: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (i, message) in messages.iter().enumerate() {
+        match message.reply_to.and_then(|id| positions.get(&id)) {
+            Some(&parent) => replies.entry(parent).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut messages: Vec<Option<ExportMessage>> = messages.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(messages.len());
+    let mut stack: Vec<(usize, usize)> = roots.into_iter().rev().map(|i| (i, 0)).collect();
+    while let Some((i, depth)) = stack.pop() {
+        if let Some(mut message) = messages[i].take() {
+            message.depth = Some(depth);
+            ordered.push(message);
+        }
+        if let Some(children) = replies.get(&i) {
+            stack.extend(children.iter().rev().map(|&child| (child, depth + 1)));
+        }
+    }
+    ordered
+}
+
+/// The manifest entry of one channel written by `--per-channel`.
+#[derive(Debug, Serialize)]
+struct ChannelFile {
+    id: Snowflake,
+    name: String,
+    file: String,
+    message_count: usize,
+    first_timestamp: String,
+    last_timestamp: String,
+}
+
+/// Writes `index.json` listing every channel file, plus `index.html` for HTML exports.
+fn write_index(dir: &str, renderer: &Renderer, channels: Vec<ChannelFile>) -> SimpleResult<()> {
+    let index = serde_json::json!({
+        "generated_at": renderer.generated_at,
+        "channels": channels,
+    });
+    std::fs::write(
+        Path::new(dir).join("index.json"),
+        serde_json::to_string_pretty(&index)?,
+    )?;
+
+    if let Format::Html = renderer.format {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_template_string("index", INDEX_TEMPLATE)?;
+        let out = File::create(Path::new(dir).join("index.html"))?;
+        handlebars.render_to_write("index", &index, out)?;
+    }
+    Ok(())
+}
+
+/// Creates the file at `path`, compressed with gzip or zstd when its name ends in `.gz` or
+/// `.zst`. Compressed streams are completed when the writer is dropped.
+fn create(path: &str) -> SimpleResult<Box<dyn Write>> {
+    let file = File::create(path)?;
+    Ok(if path.ends_with(".gz") || path.ends_with(".tgz") {
+        Box::new(GzEncoder::new(file, flate2::Compression::default()))
+    } else if path.ends_with(".zst") || path.ends_with(".tzst") {
+        Box::new(zstd::Encoder::new(file, 0)?.auto_finish())
+    } else {
+        Box::new(file)
+    })
+}
+
+/// The extension of a tar archive's path, `None` for other paths.
+fn archive_extension(path: &str) -> Option<&'static str> {
+    [".tar", ".tar.gz", ".tgz", ".tar.zst", ".tzst"]
+        .into_iter()
+        .find(|extension| path.ends_with(extension))
+}
+
+/// Packs the files of `dir` into the tar `archive`, in a directory named like the archive
+/// without its extensions, then removes `dir`.
+fn pack(dir: &str, archive: &str) -> SimpleResult<()> {
+    let file_name = Path::new(archive)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let name = archive_extension(file_name)
+        .and_then(|extension| file_name.strip_suffix(extension))
+        .filter(|name| !name.is_empty())
+        .unwrap_or("export");
+    let mut builder = tar::Builder::new(create(archive)?);
+    builder.append_dir_all(name, dir)?;
+    builder.into_inner()?.flush()?;
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// Keeps channel names usable as part of a file name on every platform.
+fn file_name_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The extension of files rendered with a template, taken from the template name
+/// with `.hbs` removed, so `report.md.hbs` produces `.md` files.
+fn template_extension(path: &str) -> String {
+    let path = Path::new(path);
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some("hbs") => Path::new(path.file_stem().unwrap_or_default()),
+        _ => path,
+    };
+    name.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("txt")
+        .to_string()
+}
+
+fn handlebars(template: String) -> SimpleResult<Handlebars<'static>> {
+    let mut handlebars = Handlebars::new();
+    handlebars_helper!(emoji_helper: |content: str| emoji::render_html(content));
+    handlebars.register_helper("emoji", Box::new(emoji_helper));
+    handlebars_helper!(seconds_helper: |secs: f64| format!("{:.0}s", secs));
+    handlebars.register_helper("seconds", Box::new(seconds_helper));
+    handlebars.register_template_string("export", template)?;
+    Ok(handlebars)
+}
+
+/// Everything fixed for the whole export that each output file is written with.
+struct Renderer {
+    format: Format,
+    /// The template replacing `format`, if any.
+    handlebars: Option<Handlebars<'static>>,
+    extension: String,
+    /// Context window of `jsonl-pairs`.
+    context: usize,
+    /// Guilds and authors of `dce` exports.
+    dce: Option<dce::Context>,
+    title: String,
+    generated_at: String,
+}
+
+impl Renderer {
+    fn open(&self, out: Box<dyn Write>) -> Sink<'_> {
+        match (&self.handlebars, self.format) {
+            (Some(handlebars), _) => Sink::Template(
+                handlebars,
+                Transcript {
+                    title: self.title.clone(),
+                    generated_at: self.generated_at.clone(),
+                    message_count: 0,
+                    channels: Vec::new(),
+                },
+                out,
+            ),
+            (None, Format::Csv) => Sink::Csv(Box::new(csv::Writer::from_writer(out))),
+            (None, Format::JsonlPairs) => {
+                Sink::Pairs(self.context, VecDeque::new(), BufWriter::new(out))
+            }
+            (None, Format::Dce) => {
+                Sink::Dce(self.dce.as_ref().unwrap(), &self.generated_at, None, out)
+            }
+            (None, format) => Sink::Lines(format, BufWriter::new(out)),
+        }
+    }
+}
+
+enum Sink<'a> {
+    Lines(Format, BufWriter<Box<dyn Write>>),
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    /// Templates see the whole export at once, so messages are collected until `finish`.
+    Template(&'a Handlebars<'static>, Transcript, Box<dyn Write>),
+    /// The context window size and the latest messages of the current channel.
+    Pairs(usize, VecDeque<ExportMessage>, BufWriter<Box<dyn Write>>),
+    /// The channel being exported, collected until `finish` like templates.
+    Dce(
+        &'a dce::Context,
+        &'a str,
+        Option<Box<dce::Export>>,
+        Box<dyn Write>,
+    ),
+}
+
+/// One message of a `jsonl-pairs` example. The response author's messages are the
+/// assistant's turns, everybody else's the user's.
+#[derive(Debug, Serialize)]
+struct Turn<'a> {
+    role: &'static str,
+    name: &'a str,
+    content: &'a str,
+}
+
+impl Sink<'_> {
+    fn write(&mut self, message: ExportMessage) -> SimpleResult<()> {
+        match self {
+            Sink::Lines(Format::Jsonl, out) => {
+                serde_json::to_writer(&mut *out, &message)?;
+                writeln!(out)?;
+            }
+            Sink::Lines(_, out) => {
+                let indent = match message.depth {
+                    Some(depth) if depth > 0 => format!("{}↳ ", "    ".repeat(depth - 1)),
+                    _ => String::new(),
+                };
+                let origin = match message.origin_channel_id {
+                    Some(channel_id) if message.forwarded => {
+                        format!(" (forwarded from {})", channel_id)
+                    }
+                    Some(channel_id) => format!(" (crossposted from {})", channel_id),
+                    None => String::new(),
+                };
+                let mut voice = match message.voice_duration_secs {
+                    Some(secs) => format!(" (voice message, {:.0}s)", secs),
+                    None => String::new(),
+                };
+                if message.deleted {
+                    voice += " (deleted)";
+                }
+                writeln!(
+                    out,
+                    "{}[{}] #{} {}{}{}: {}",
+                    indent,
+                    message.timestamp,
+                    message.channel_name,
+                    message.author,
+                    origin,
+                    voice,
+                    message.content
+                )?
+            }
+            Sink::Csv(writer) => writer.serialize(&message)?,
+            Sink::Pairs(context, window, out) => {
+                // Messages without text, e.g. attachments only, make no useful turns.
+                if message.content.is_empty() {
+                    return Ok(());
+                }
+                if window.back().map(|m| m.channel_id) != Some(message.channel_id) {
+                    window.clear();
+                }
+                if !window.is_empty() {
+                    let turns: Vec<Turn> = window
+                        .iter()
+                        .chain(std::iter::once(&message))
+                        .map(|m| Turn {
+                            role: if m.author_id == message.author_id {
+                                "assistant"
+                            } else {
+                                "user"
+                            },
+                            name: &m.author,
+                            content: &m.content,
+                        })
+                        .collect();
+                    let example = serde_json::json!({ "id": message.id, "messages": turns });
+                    serde_json::to_writer(&mut *out, &example)?;
+                    writeln!(out)?;
+                }
+                window.push_back(message);
+                if window.len() > *context {
+                    window.pop_front();
+                }
+            }
+            Sink::Dce(context, exported_at, export, _) => {
+                let export =
+                    export.get_or_insert_with(|| Box::new(context.export(&message, exported_at)));
+                if export.channel.id != message.channel_id {
+                    let err_msg = "The dce format holds a single channel, export several with --per-channel or pick one with --channel";
+                    return Err(err_msg.into());
+                }
+                export
+                    .messages
+                    .push(context.message(message, export.guild.id));
+                export.message_count += 1;
+            }
+            Sink::Template(_, transcript, _) => {
+                transcript.message_count += 1;
+                match transcript.channels.last_mut() {
+                    Some(channel) if channel.id == message.channel_id => {
+                        channel.messages.push(message)
+                    }
+                    _ => transcript.channels.push(TranscriptChannel {
+                        id: message.channel_id,
+                        name: message.channel_name.clone(),
+                        messages: vec![message],
+                    }),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> SimpleResult<()> {
+        match self {
+            Sink::Lines(_, mut out) | Sink::Pairs(_, _, mut out) => out.flush()?,
+            Sink::Csv(mut writer) => writer.flush()?,
+            Sink::Template(handlebars, transcript, out) => {
+                handlebars.render_to_write("export", &transcript, out)?
+            }
+            Sink::Dce(_, _, export, out) => {
+                if let Some(export) = export {
+                    serde_json::to_writer_pretty(out, &export)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use clap::Args;
+use rusqlite::params;
+
+use std::collections::HashSet;
+
+use crate::snowflake::Snowflake;
+use crate::{db, Message, SimpleResult};
+
+/// The user Discord shows in place of deleted accounts, which anonymized messages are
+/// given as their author.
+const DELETED_USER: (Snowflake, &str) = (Snowflake(456226577798135808), "Deleted User");
+/// What mentions of a forgotten user are replaced with.
+const MENTION: &str = "@Deleted User";
+/// How data packages name direct message channels, after the recipient.
+const DIRECT_MESSAGE: &str = "Direct Message with ";
+/// The type of permission overwrites targeting a member rather than a role.
+pub const MEMBER_OVERWRITE: i64 = 1;
+
+#[derive(Debug, Args)]
+pub struct ForgetUserArgs {
+    /// The user to erase
+    #[clap(value_name = "USER_ID")]
+    user_id: Snowflake,
+
+    /// Keep the user's messages, attributed to Deleted User like Discord does for deleted
+    /// accounts, instead of deleting them
+    #[clap(long)]
+    anonymize: bool,
+
+    /// Count what would be erased without changing anything
+    #[clap(long)]
+    dry_run: bool,
+}
+
+/// Erases a user: their messages, with what was stored about them, or only their authorship
+/// with `--anonymize`, mentions of them in other messages, case notes and message fields,
+/// their names and flags, direct message channels named after them and the permission
+/// overwrites naming them. A tombstone is kept so later scrapes leave the user
+/// out. All of it happens in one transaction, which a dry run rolls back.
+pub fn run(db_path: &str, args: ForgetUserArgs) -> SimpleResult<()> {
+    let mut conn = db::open_db(db_path)?;
+    let tx = conn.transaction()?;
+    let user_id = args.user_id;
+    let erased = erase(&tx, user_id, args.anonymize)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO forgotten_user (user_id, messages, anonymized) VALUES (?, ?, ?)",
+        params![user_id, erased.messages, args.anonymize],
+    )?;
+
+    let done = if args.anonymize {
+        "anonymized"
+    } else {
+        "deleted"
+    };
+    if args.dry_run {
+        tx.rollback()?;
+        info!(
+            "Would forget {} ({} Users, {} Messages {}, mentions removed from {} Messages)",
+            user_id, erased.users, erased.messages, done, erased.mentions
+        );
+    } else {
+        tx.commit()?;
+        info!(
+            "Forgot {} ({} Users, {} Messages {}, mentions removed from {} Messages)",
+            user_id, erased.users, erased.messages, done, erased.mentions
+        );
+    }
+    Ok(())
+}
+
+/// How much `erase` changed.
+pub struct Erased {
+    pub users: usize,
+    pub messages: usize,
+    pub mentions: usize,
+}
+
+/// Deletes a user's messages, or gives them to Deleted User when `anonymize`, replaces
+/// mentions of the user and deletes what was stored about them, without recording them
+/// as forgotten.
+pub fn erase(
+    tx: &rusqlite::Transaction,
+    user_id: Snowflake,
+    anonymize: bool,
+) -> SimpleResult<Erased> {
+    let messages = if anonymize {
+        tx.execute(
+            "INSERT OR IGNORE INTO user (id, username) VALUES (?, ?)",
+            params![DELETED_USER.0, DELETED_USER.1],
+        )?;
+        tx.execute(
+            "UPDATE message SET author_id = ? WHERE author_id = ?",
+            params![DELETED_USER.0, user_id],
+        )?
+    } else {
+        let in_cases = tx.execute(
+            "DELETE FROM case_message
+             WHERE message_id IN (SELECT id FROM message WHERE author_id = ?)",
+            [user_id],
+        )?;
+        if in_cases > 0 {
+            info!("Removing {} Messages from cases", in_cases);
+        }
+        for table in [
+            "message_field",
+            "message_url",
+            "message_language",
+            "message_reaction",
+            "message_revision",
+        ] {
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE message_id IN (SELECT id FROM message WHERE author_id = ?)",
+                    table
+                ),
+                [user_id],
+            )?;
+        }
+        tx.execute("DELETE FROM message WHERE author_id = ?", [user_id])?
+    };
+
+    // Only mentions in message content are counted, notes and fields are about messages.
+    let mut mentions = 0;
+    for (table, column, counted) in [
+        ("message", "content", true),
+        ("message_revision", "content", true),
+        ("case_message", "note", false),
+        ("message_field", "value", false),
+    ] {
+        let replaced = tx.execute(
+            &format!(
+                "UPDATE {0} SET {1} = replace(replace({1}, ?1, ?3), ?2, ?3)
+                 WHERE typeof({1}) = 'text' AND (instr({1}, ?1) > 0 OR instr({1}, ?2) > 0)",
+                table, column
+            ),
+            params![
+                format!("<@{}>", user_id),
+                format!("<@!{}>", user_id),
+                MENTION
+            ],
+        )?;
+        if counted {
+            mentions += replaced;
+        }
+    }
+
+    // Imported direct message channels are named after the recipient, by any name they had.
+    let names: Vec<String> = tx
+        .prepare(
+            "SELECT username, discriminator, global_name FROM user WHERE id = ?1
+             UNION SELECT username, discriminator, global_name FROM user_history WHERE user_id = ?1",
+        )?
+        .query_map([user_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flat_map(|(username, discriminator, global_name)| {
+            let tag = discriminator.map(|d| format!("{}#{}", username, d));
+            [Some(username), tag, global_name].into_iter().flatten()
+        })
+        .collect();
+    for name in names {
+        for (table, id) in [("channel", "id"), ("channel_history", "channel_id")] {
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET name = ?2
+                     WHERE name = ?1 AND {} IN (SELECT id FROM channel WHERE guild_id IS NULL)",
+                    table, id
+                ),
+                params![
+                    format!("{}{}", DIRECT_MESSAGE, name),
+                    format!("{}{}", DIRECT_MESSAGE, DELETED_USER.1)
+                ],
+            )?;
+        }
+    }
+
+    for table in ["user_history", "flagged_user"] {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE user_id = ?", table),
+            [user_id],
+        )?;
+    }
+    tx.execute(
+        "UPDATE soundboard_sound SET user_id = NULL WHERE user_id = ?",
+        [user_id],
+    )?;
+    tx.execute(
+        "DELETE FROM permission_overwrite WHERE type = ? AND target_id = ?",
+        params![MEMBER_OVERWRITE, user_id],
+    )?;
+    let users = tx.execute("DELETE FROM user WHERE id = ?", [user_id])?;
+    Ok(Erased {
+        users,
+        messages,
+        mentions,
+    })
+}
+
+/// Leaves the messages of forgotten users out of a page about to be stored, and replaces
+/// mentions of them in the rest.
+pub fn scrub(conn: &rusqlite::Connection, messages: &mut Vec<Message>) -> SimpleResult<()> {
+    let forgotten = forgotten(conn)?;
+    if forgotten.is_empty() {
+        return Ok(());
+    }
+
+    messages.retain(|m| !forgotten.contains(&m.author.id));
+    for message in messages {
+        for user_id in &forgotten {
+            for mention in [format!("<@{}>", user_id), format!("<@!{}>", user_id)] {
+                if message.content.contains(&mention) {
+                    message.content = message.content.replace(&mention, MENTION);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The users erased with `forget-user`, which later scrapes leave out.
+pub fn forgotten(conn: &rusqlite::Connection) -> SimpleResult<HashSet<Snowflake>> {
+    let mut stmt = conn.prepare_cached("SELECT user_id FROM forgotten_user")?;
+    let forgotten = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<HashSet<Snowflake>, _>>()?;
+    Ok(forgotten)
+}
@@ -0,0 +1,129 @@
+use clap::Args;
+
+use crate::snowflake::Snowflake;
+use crate::{timezone, SimpleResult};
+
+/// Number of messages on each side of a gap used to estimate how active the channel was.
+const RATE_WINDOW: usize = 100;
+
+#[derive(Debug, Args)]
+pub struct GapsArgs {
+    /// Channel to analyze, defaults to every stored channel
+    #[clap(long)]
+    channel: Option<Snowflake>,
+
+    /// Ignore silences shorter than this many hours
+    #[clap(long, default_value_t = 6.0)]
+    min_hours: f64,
+
+    /// Flag a silence when the surrounding activity predicts at least this many messages in it
+    #[clap(long, default_value_t = 50.0)]
+    min_expected: f64,
+
+    /// Re-scrape the messages missing from each gap found
+    #[clap(long)]
+    pub fill: bool,
+}
+
+/// A stretch with no stored messages between two stored ones.
+#[derive(Debug, Clone)]
+pub struct Gap {
+    pub channel_id: Snowflake,
+    /// The last stored message before the gap.
+    pub after: Snowflake,
+    /// The first stored message after the gap.
+    pub before: Snowflake,
+    pub hours: f64,
+    /// Messages the surrounding activity would have produced in the gap.
+    pub expected: f64,
+}
+
+pub fn run(conn: &rusqlite::Connection, args: &GapsArgs) -> SimpleResult<Vec<Gap>> {
+    let channel_ids = match args.channel {
+        Some(channel_id) => vec![channel_id],
+        None => conn
+            .prepare("SELECT id FROM channel ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut gaps = Vec::new();
+    for channel_id in channel_ids {
+        let channel_gaps = find_gaps(conn, channel_id, args)?;
+        for gap in &channel_gaps {
+            warn!(
+                "Channel {}: no messages for {:.1}h between {} ({}) and {} ({}), ~{:.0} expected",
+                gap.channel_id,
+                gap.hours,
+                message_timestamp(conn, gap.after)?,
+                gap.after,
+                message_timestamp(conn, gap.before)?,
+                gap.before,
+                gap.expected
+            );
+        }
+        gaps.extend(channel_gaps);
+    }
+
+    info!("Found {} suspicious gaps", gaps.len());
+    Ok(gaps)
+}
+
+fn message_timestamp(conn: &rusqlite::Connection, id: Snowflake) -> SimpleResult<String> {
+    Ok(conn
+        .query_row("SELECT timestamp FROM message WHERE id = ?", [id], |row| {
+            row.get::<_, String>(0)
+        })
+        .map(|timestamp| timezone::local(&timestamp))?)
+}
+
+/// Flags silences that are improbable given the message rate on both sides of them,
+/// treating messages as a Poisson process over the surrounding window.
+fn find_gaps(
+    conn: &rusqlite::Connection,
+    channel_id: Snowflake,
+    args: &GapsArgs,
+) -> SimpleResult<Vec<Gap>> {
+    let messages: Vec<(Snowflake, i64)> = conn
+        .prepare("SELECT id, created_at_unix FROM message WHERE channel_id = ? ORDER BY id")?
+        .query_map([channel_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let rate = |from: usize, to: usize| -> f64 {
+        let span = (messages[to].1 - messages[from].1) as f64;
+        if span > 0.0 {
+            (to - from) as f64 / span
+        } else {
+            f64::INFINITY
+        }
+    };
+
+    let mut gaps = Vec::new();
+    for i in 1..messages.len() {
+        let gap_secs = (messages[i].1 - messages[i - 1].1) as f64;
+        if gap_secs < args.min_hours * 3600.0 {
+            continue;
+        }
+
+        // Both sides need some history for the gap to sit between active periods.
+        let before_start = (i - 1).saturating_sub(RATE_WINDOW);
+        let after_end = (i + RATE_WINDOW).min(messages.len() - 1);
+        if before_start == i - 1 || after_end == i {
+            continue;
+        }
+
+        let rate = rate(before_start, i - 1).min(rate(i, after_end));
+        let expected = rate * gap_secs;
+        if expected >= args.min_expected {
+            gaps.push(Gap {
+                channel_id,
+                after: messages[i - 1].0,
+                before: messages[i].0,
+                hours: gap_secs / 3600.0,
+                expected,
+            });
+        }
+    }
+
+    Ok(gaps)
+}
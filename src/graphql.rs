@@ -0,0 +1,264 @@
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema, ID};
+use rusqlite::{Connection, OptionalExtension};
+
+use std::sync::{Mutex, MutexGuard};
+
+use crate::export::ExportMessage;
+use crate::serve::{self, ChannelSummary};
+use crate::snowflake::Snowflake;
+use crate::{db, SimpleResult};
+
+pub type ArchiveSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// The schema reads through its own read-only connection, shared by all resolvers.
+pub fn schema(db_path: &str) -> SimpleResult<ArchiveSchema> {
+    let conn = db::open_read_only(db_path)?;
+    Ok(Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(Mutex::new(conn))
+        .finish())
+}
+
+/// Runs a request body of the form `{"query": ..., "variables": ..., "operationName": ...}`.
+pub fn execute(schema: &ArchiveSchema, body: &str) -> SimpleResult<serde_json::Value> {
+    let request: async_graphql::Request = serde_json::from_str(body)?;
+    let response = pollster::block_on(schema.execute(request));
+    Ok(serde_json::to_value(response)?)
+}
+
+fn conn<'a>(ctx: &'a Context<'_>) -> MutexGuard<'a, Connection> {
+    ctx.data_unchecked::<Mutex<Connection>>().lock().unwrap()
+}
+
+fn snowflake(id: &ID) -> Result<Snowflake> {
+    id.parse()
+        .map_err(|_| format!("Invalid ID {}", id.as_str()).into())
+}
+
+/// Newest first, paged back through history with `before` like the REST endpoints.
+fn messages(
+    ctx: &Context<'_>,
+    condition: &str,
+    value: &dyn rusqlite::ToSql,
+    before: Option<ID>,
+    limit: Option<i32>,
+) -> Result<Vec<Message>> {
+    let before = match before {
+        Some(before) => snowflake(&before)?,
+        None => Snowflake(i64::MAX),
+    };
+    let limit = match limit {
+        Some(limit) if limit < 1 || limit as usize > serve::MAX_LIMIT => {
+            let err_msg = format!("limit must be between 1 and {}", serve::MAX_LIMIT);
+            return Err(err_msg.into());
+        }
+        Some(limit) => limit as usize,
+        None => serve::DEFAULT_LIMIT,
+    };
+    let sql = format!(
+        "SELECT {} WHERE {} AND m.id < ? ORDER BY m.id DESC LIMIT ?",
+        serve::MESSAGE_COLUMNS,
+        condition
+    );
+    let messages = conn(ctx)
+        .prepare(&sql)?
+        .query_map(rusqlite::params![value, before, limit], serve::message_row)?
+        .map(|m| m.map(Message))
+        .collect::<Result<_, _>>()?;
+    Ok(messages)
+}
+
+fn channel(ctx: &Context<'_>, id: Snowflake) -> Result<Option<Channel>> {
+    let channels = serve::channel_summaries(&conn(ctx), "c.id = ?", [id])?;
+    Ok(channels.into_iter().next().map(Channel))
+}
+
+fn user(ctx: &Context<'_>, id: Snowflake) -> Result<Option<User>> {
+    let user = conn(ctx)
+        .query_row(
+            "SELECT id, username, discriminator, global_name FROM user WHERE id = ?",
+            [id],
+            |row| {
+                Ok(crate::User {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                    discriminator: row.get(2)?,
+                    global_name: row.get(3)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(user.map(User))
+}
+
+fn message(ctx: &Context<'_>, id: Snowflake) -> Result<Option<Message>> {
+    let sql = format!("SELECT {} WHERE m.id = ?", serve::MESSAGE_COLUMNS);
+    let message = conn(ctx)
+        .query_row(&sql, [id], serve::message_row)
+        .optional()?;
+    Ok(message.map(Message))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn channels(&self, ctx: &Context<'_>) -> Result<Vec<Channel>> {
+        let channels = serve::channel_summaries(&conn(ctx), "1 = 1", [])?;
+        Ok(channels.into_iter().map(Channel).collect())
+    }
+
+    async fn channel(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Channel>> {
+        channel(ctx, snowflake(&id)?)
+    }
+
+    async fn user(&self, ctx: &Context<'_>, id: ID) -> Result<Option<User>> {
+        user(ctx, snowflake(&id)?)
+    }
+
+    async fn message(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Message>> {
+        message(ctx, snowflake(&id)?)
+    }
+
+    /// Messages containing `q`, newest first.
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        q: String,
+        before: Option<ID>,
+        limit: Option<i32>,
+    ) -> Result<Vec<Message>> {
+        messages(
+            ctx,
+            "m.content LIKE ? ESCAPE '\\'",
+            &serve::like_pattern(&q),
+            before,
+            limit,
+        )
+    }
+}
+
+pub struct Channel(ChannelSummary);
+
+#[Object]
+impl Channel {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn guild_id(&self) -> Option<ID> {
+        self.0.guild_id.map(|id| ID(id.to_string()))
+    }
+
+    async fn guild_name(&self) -> Option<&str> {
+        self.0.guild_name.as_deref()
+    }
+
+    async fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    async fn message_count(&self) -> i64 {
+        self.0.message_count
+    }
+
+    /// The channel's messages, newest first.
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        before: Option<ID>,
+        limit: Option<i32>,
+    ) -> Result<Vec<Message>> {
+        messages(ctx, "m.channel_id = ?", &self.0.id, before, limit)
+    }
+}
+
+pub struct User(crate::User);
+
+#[Object]
+impl User {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    /// Only set for users that haven't moved to unique usernames.
+    async fn discriminator(&self) -> Option<&str> {
+        self.0.discriminator.as_deref()
+    }
+
+    /// The display name, or the username for users without one.
+    async fn display_name(&self) -> &str {
+        self.0.display_name()
+    }
+
+    /// The user's messages across all channels, newest first.
+    async fn messages(
+        &self,
+        ctx: &Context<'_>,
+        before: Option<ID>,
+        limit: Option<i32>,
+    ) -> Result<Vec<Message>> {
+        messages(ctx, "m.author_id = ?", &self.0.id, before, limit)
+    }
+}
+
+pub struct Message(ExportMessage);
+
+#[Object]
+impl Message {
+    async fn id(&self) -> ID {
+        ID(self.0.id.to_string())
+    }
+
+    async fn timestamp(&self) -> &str {
+        &self.0.timestamp
+    }
+
+    async fn content(&self) -> &str {
+        &self.0.content
+    }
+
+    async fn channel(&self, ctx: &Context<'_>) -> Result<Option<Channel>> {
+        channel(ctx, self.0.channel_id)
+    }
+
+    async fn author(&self, ctx: &Context<'_>) -> Result<Option<User>> {
+        user(ctx, self.0.author_id)
+    }
+
+    /// The channel a crossposted announcement or forwarded message was published in, usually
+    /// in another guild.
+    async fn origin_channel_id(&self) -> Option<ID> {
+        self.0.origin_channel_id.map(|id| ID(id.to_string()))
+    }
+
+    async fn origin_guild_id(&self) -> Option<ID> {
+        self.0.origin_guild_id.map(|id| ID(id.to_string()))
+    }
+
+    /// Whether the message forwards the one it was published as, rather than crossposting it.
+    async fn forwarded(&self) -> bool {
+        self.0.forwarded
+    }
+
+    /// How long the message's recording is, in seconds, if it is a voice message.
+    async fn voice_duration_secs(&self) -> Option<f64> {
+        self.0.voice_duration_secs
+    }
+
+    /// Whether a refresh found the message deleted on Discord.
+    async fn deleted(&self) -> bool {
+        self.0.deleted
+    }
+
+    /// The message this one replies to, if it is stored.
+    async fn reply_to(&self, ctx: &Context<'_>) -> Result<Option<Message>> {
+        match self.0.reply_to {
+            Some(id) => message(ctx, id),
+            None => Ok(None),
+        }
+    }
+}
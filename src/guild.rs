@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiClient;
+use crate::snowflake::Snowflake;
+use crate::{forget, insert_users, send_request, SimpleResult, User, BASE_URL};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Role {
+    id: Snowflake,
+    name: String,
+    permissions: String,
+    position: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guild {
+    id: Snowflake,
+    name: String,
+    premium_tier: i64,
+    premium_subscription_count: Option<i64>,
+    features: Vec<String>,
+}
+
+/// An event scheduled in a guild, in a voice or stage channel or somewhere else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    id: Snowflake,
+    channel_id: Option<Snowflake>,
+    name: String,
+    description: Option<String>,
+    scheduled_start_time: String,
+    scheduled_end_time: Option<String>,
+    /// 1 scheduled, 2 active, 3 completed, 4 canceled.
+    status: i64,
+    /// 1 in a stage channel, 2 in a voice channel, 3 elsewhere.
+    entity_type: i64,
+}
+
+/// A sound members can play in the guild's voice channels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoundboardSound {
+    sound_id: Snowflake,
+    name: String,
+    /// From 0 to 1.
+    volume: f64,
+    emoji_id: Option<Snowflake>,
+    emoji_name: Option<String>,
+    /// Whether the sound can be played, it may not be when the guild lost boosts.
+    available: bool,
+    /// Who uploaded the sound, only sent to those allowed to manage expressions.
+    user: Option<User>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SoundboardSounds {
+    items: Vec<SoundboardSound>,
+}
+
+pub fn get_guild(client: &dyn ApiClient, guild_id: Snowflake) -> SimpleResult<Guild> {
+    let req_url = format!("{}/guilds/{}", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let guild: Guild = serde_json::from_str(&body)?;
+    Ok(guild)
+}
+
+pub fn get_roles(client: &dyn ApiClient, guild_id: Snowflake) -> SimpleResult<Vec<Role>> {
+    let req_url = format!("{}/guilds/{}/roles", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let roles: Vec<Role> = serde_json::from_str(&body)?;
+    Ok(roles)
+}
+
+pub fn get_scheduled_events(
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+) -> SimpleResult<Vec<ScheduledEvent>> {
+    let req_url = format!("{}/guilds/{}/scheduled-events", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let events: Vec<ScheduledEvent> = serde_json::from_str(&body)?;
+    Ok(events)
+}
+
+pub fn get_soundboard_sounds(
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+) -> SimpleResult<Vec<SoundboardSound>> {
+    let req_url = format!("{}/guilds/{}/soundboard-sounds", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let sounds: SoundboardSounds = serde_json::from_str(&body)?;
+    Ok(sounds.items)
+}
+
+/// Stores a guild's scheduled events as they are now. Discord drops events once they're
+/// over, so stored ones are kept with the last status they were seen with.
+pub fn insert_scheduled_events(
+    conn: &mut rusqlite::Connection,
+    guild_id: Snowflake,
+    events: Vec<ScheduledEvent>,
+) -> SimpleResult<()> {
+    info!("Inserting {} Scheduled Events", events.len());
+
+    let tx = conn.transaction()?;
+    for event in events {
+        tx.execute(
+            "INSERT INTO scheduled_event (id, guild_id, channel_id, name, description,
+                scheduled_start_time, scheduled_end_time, status, entity_type)
+             VALUES (?,?,?,?,?,?,?,?,?)
+             ON CONFLICT (id) DO UPDATE SET channel_id = excluded.channel_id,
+                name = excluded.name, description = excluded.description,
+                scheduled_start_time = excluded.scheduled_start_time,
+                scheduled_end_time = excluded.scheduled_end_time, status = excluded.status,
+                entity_type = excluded.entity_type",
+            rusqlite::params![
+                event.id,
+                guild_id,
+                event.channel_id,
+                event.name,
+                event.description,
+                event.scheduled_start_time,
+                event.scheduled_end_time,
+                event.status,
+                event.entity_type
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Stores a guild's soundboard sounds and those who uploaded them, unless they were
+/// forgotten. Sounds removed from the guild are kept, like deleted messages are.
+pub fn insert_soundboard_sounds(
+    conn: &mut rusqlite::Connection,
+    guild_id: Snowflake,
+    mut sounds: Vec<SoundboardSound>,
+) -> SimpleResult<()> {
+    info!("Inserting {} Soundboard Sounds", sounds.len());
+
+    let forgotten = forget::forgotten(conn)?;
+    for sound in &mut sounds {
+        if matches!(&sound.user, Some(user) if forgotten.contains(&user.id)) {
+            sound.user = None;
+        }
+    }
+    insert_users(
+        conn,
+        sounds.iter().filter_map(|s| s.user.as_ref()).collect(),
+    )?;
+    let tx = conn.transaction()?;
+    for sound in sounds {
+        tx.execute(
+            "INSERT INTO soundboard_sound (id, guild_id, name, volume, emoji_id, emoji_name,
+                user_id, available)
+             VALUES (?,?,?,?,?,?,?,?)
+             ON CONFLICT (id) DO UPDATE SET name = excluded.name, volume = excluded.volume,
+                emoji_id = excluded.emoji_id, emoji_name = excluded.emoji_name,
+                user_id = coalesce(excluded.user_id, user_id), available = excluded.available",
+            rusqlite::params![
+                sound.sound_id,
+                guild_id,
+                sound.name,
+                sound.volume,
+                sound.emoji_id,
+                sound.emoji_name,
+                sound.user.map(|u| u.id),
+                sound.available
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Replaces the stored roles of a guild with its current ones.
+pub fn insert_roles(
+    conn: &mut rusqlite::Connection,
+    guild_id: Snowflake,
+    roles: Vec<Role>,
+) -> SimpleResult<()> {
+    info!("Inserting {} Roles", roles.len());
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM role WHERE guild_id = ?", [guild_id])?;
+    for role in roles {
+        tx.execute(
+            "INSERT INTO role (id, guild_id, name, permissions, position) VALUES (?,?,?,?,?)",
+            rusqlite::params![
+                role.id,
+                guild_id,
+                role.name,
+                role.permissions.parse::<i64>()?,
+                role.position
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Stores the guild and appends its boost tier, boost count and features to the
+/// history whenever they differ from the last recorded snapshot.
+pub fn insert_guild(conn: &mut rusqlite::Connection, mut guild: Guild) -> SimpleResult<()> {
+    guild.features.sort();
+    let features = serde_json::to_string(&guild.features)?;
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO guild (id, name) VALUES (?,?)
+         ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+        rusqlite::params![guild.id, guild.name],
+    )?;
+
+    let unchanged: bool = tx.query_row(
+        "SELECT EXISTS(
+            SELECT 1 FROM (
+                SELECT premium_tier, premium_subscription_count, features FROM guild_history
+                WHERE guild_id = ? ORDER BY id DESC LIMIT 1)
+            WHERE premium_tier = ? AND premium_subscription_count IS ? AND features = ?)",
+        rusqlite::params![
+            guild.id,
+            guild.premium_tier,
+            guild.premium_subscription_count,
+            features
+        ],
+        |row| row.get(0),
+    )?;
+    if !unchanged {
+        info!(
+            "Recording Guild {}: tier {}, {} boosts",
+            guild.name,
+            guild.premium_tier,
+            guild.premium_subscription_count.unwrap_or(0)
+        );
+        tx.execute(
+            "INSERT INTO guild_history (guild_id, premium_tier, premium_subscription_count, features)
+             VALUES (?,?,?,?)",
+            rusqlite::params![
+                guild.id,
+                guild.premium_tier,
+                guild.premium_subscription_count,
+                features
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::{Message, SimpleResult};
+
+/// Command run after each stored page, set once from `--hook`.
+static COMMAND: OnceLock<String> = OnceLock::new();
+
+pub fn set_command(command: String) -> SimpleResult<()> {
+    COMMAND
+        .set(command)
+        .map_err(|_| "Hook was already set".into())
+}
+
+/// Runs the hook through the shell with the page's `messages` as they were stored on its
+/// standard input, as a JSON array. Fails unless the hook exits successfully.
+pub fn run(messages: &[Message]) -> SimpleResult<()> {
+    let command = match COMMAND.get() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    let batch = serde_json::to_string(messages)?;
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Running hook `{}`: {}", command, e))?;
+    // A hook that exits without reading everything closes the pipe early, which its exit
+    // status reports better.
+    let _ = child.stdin.take().unwrap().write_all(batch.as_bytes());
+    let status = child.wait()?;
+    if !status.success() {
+        let err_msg = format!("Hook `{}` failed: {}", command, status);
+        return Err(err_msg.into());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(["-c", command]);
+    shell
+}
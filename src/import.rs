@@ -0,0 +1,197 @@
+use clap::Subcommand;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use crate::anonymize::Anonymizer;
+use crate::redact::Redactor;
+use crate::snowflake::Snowflake;
+use crate::{insert_page, Message, SimpleResult, User};
+
+/// Messages inserted per statement, well below SQLite's limit on bound parameters.
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommand {
+    /// Merge the messages of a Discord data package (the `package.zip` of a data request)
+    DataPackage { path: String },
+}
+
+pub fn run(
+    conn: &mut rusqlite::Connection,
+    cmd: ImportCommand,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) -> SimpleResult<()> {
+    match cmd {
+        ImportCommand::DataPackage { path } => {
+            import_data_package(conn, &path, anonymizer, redactor)
+        }
+    }
+}
+
+/// Reads `messages/*/channel.json` and the `messages.csv` (or, in newer packages,
+/// `messages.json`) beside it. Every message in a package was sent by its owner.
+fn import_data_package(
+    conn: &mut rusqlite::Connection,
+    path: &str,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) -> SimpleResult<()> {
+    let mut package = zip::ZipArchive::new(File::open(path)?)?;
+
+    let account: Value = serde_json::from_str(
+        &read_entry(&mut package, "account/user.json")?
+            .ok_or_else(|| format!("{} has no account/user.json", path))?,
+    )?;
+    let owner = User {
+        id: snowflake(&account["id"]).ok_or("account/user.json has no user ID")?,
+        username: account["username"].as_str().unwrap_or_default().to_string(),
+        discriminator: match &account["discriminator"] {
+            Value::String(discriminator) if discriminator != "0" => Some(discriminator.clone()),
+            Value::Number(n) if n.as_u64() != Some(0) => Some(format!("{:04}", n)),
+            _ => None,
+        },
+        global_name: account["global_name"].as_str().map(str::to_string),
+    };
+    let names: HashMap<String, Option<String>> =
+        match read_entry(&mut package, "messages/index.json")? {
+            Some(index) => serde_json::from_str(&index)?,
+            None => HashMap::new(),
+        };
+
+    let channel_dirs: Vec<String> = package
+        .file_names()
+        .filter_map(|name| name.strip_suffix("/channel.json"))
+        .filter(|dir| dir.starts_with("messages/"))
+        .map(|dir| dir.to_string())
+        .collect();
+
+    let mut imported = 0;
+    for dir in channel_dirs {
+        let info: Value = serde_json::from_str(
+            &read_entry(&mut package, &format!("{}/channel.json", dir))?.unwrap_or_default(),
+        )?;
+        let channel_id = match snowflake(&info["id"]) {
+            Some(id) => id,
+            None => {
+                warn!("Skipping {}, its channel.json has no ID", dir);
+                continue;
+            }
+        };
+        let guild_id = snowflake(&info["guild"]["id"]);
+        if let (Some(guild_id), Some(guild_name)) = (guild_id, info["guild"]["name"].as_str()) {
+            conn.execute(
+                "INSERT OR IGNORE INTO guild (id, name) VALUES (?,?)",
+                rusqlite::params![guild_id, guild_name],
+            )?;
+        }
+        // Direct messages have no channel name, the index names them after the recipient.
+        let name = info["name"]
+            .as_str()
+            .map(|n| n.to_string())
+            .or_else(|| names.get(&channel_id.to_string()).cloned().flatten());
+        // Packages know less about channels than scrapes, so what's stored already is kept.
+        conn.execute(
+            "INSERT OR IGNORE INTO channel (id, guild_id, name) VALUES (?,?,?)",
+            rusqlite::params![channel_id, guild_id, name.unwrap_or_default()],
+        )?;
+
+        let messages = read_messages(&mut package, &dir, channel_id, &owner)?;
+        imported += messages.len();
+        let mut messages = messages.into_iter().peekable();
+        while messages.peek().is_some() {
+            let batch: Vec<Message> = messages.by_ref().take(BATCH_SIZE).collect();
+            insert_page(conn, batch, anonymizer, redactor)?;
+        }
+    }
+
+    info!("Imported {} Messages from {}", imported, path);
+    Ok(())
+}
+
+fn read_messages(
+    package: &mut zip::ZipArchive<File>,
+    dir: &str,
+    channel_id: Snowflake,
+    owner: &User,
+) -> SimpleResult<Vec<Message>> {
+    // Each row holds an ID, a timestamp and the contents, under the same names in both formats.
+    let rows: Vec<(Value, String, String)> = if let Some(csv) =
+        read_entry(package, &format!("{}/messages.csv", dir))?
+    {
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| format!("{}/messages.csv has no {} column", dir, name))
+        };
+        let (id, timestamp, contents) = (column("ID")?, column("Timestamp")?, column("Contents")?);
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            rows.push((
+                Value::String(record[id].to_string()),
+                record[timestamp].to_string(),
+                record[contents].to_string(),
+            ));
+        }
+        rows
+    } else if let Some(json) = read_entry(package, &format!("{}/messages.json", dir))? {
+        let messages: Vec<Value> = serde_json::from_str(&json)?;
+        messages
+            .into_iter()
+            .map(|m| {
+                let text = |key: &str| m[key].as_str().unwrap_or_default().to_string();
+                (m["ID"].clone(), text("Timestamp"), text("Contents"))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(id, timestamp, content)| {
+            Some(Message {
+                id: snowflake(&id)?,
+                channel_id,
+                author: owner.clone(),
+                content,
+                // The package writes `2021-03-04 12:34:56.789000+00:00`, the API uses a `T`.
+                timestamp: timestamp.replacen(' ', "T", 1),
+                kind: 0,
+                message_reference: None,
+                edited_timestamp: None,
+                reactions: Vec::new(),
+                flags: 0,
+                message_snapshots: Vec::new(),
+                attachments: Vec::new(),
+            })
+        })
+        .collect())
+}
+
+fn read_entry(package: &mut zip::ZipArchive<File>, name: &str) -> SimpleResult<Option<String>> {
+    let mut entry = match package.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+/// Data packages write IDs as strings in some files and as numbers in others.
+fn snowflake(value: &Value) -> Option<Snowflake> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64().map(|n| Snowflake(n as i64)),
+        _ => None,
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::SimpleResult;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Turns Ctrl-C into a request to stop once the pages in flight are stored. A second
+/// Ctrl-C exits at once.
+pub fn install() -> SimpleResult<()> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::Relaxed) {
+            warn!("Interrupted again, exiting now");
+            std::process::exit(130);
+        }
+        info!("Interrupted, stopping after the pages in flight. Ctrl-C again to exit now");
+    })?;
+    Ok(())
+}
+
+/// Asks to stop like a first Ctrl-C, for the dashboard that reads keys itself.
+#[cfg_attr(not(all(feature = "tui", unix)), allow(dead_code))]
+pub fn request() {
+    if !REQUESTED.swap(true, Ordering::Relaxed) {
+        info!("Interrupted, stopping after the pages in flight");
+    }
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
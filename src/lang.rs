@@ -0,0 +1,29 @@
+use crate::enrich::Enricher;
+use crate::{Message, SimpleResult};
+
+/// Stores the language each message is written in as an ISO 639-3 code, e.g. `eng`, in
+/// the `message_language` table. Messages too short or mixed to tell are left without one.
+pub struct LanguageDetector;
+
+impl Enricher for LanguageDetector {
+    fn name(&self) -> &str {
+        "Language detection"
+    }
+
+    fn enrich(&self, tx: &rusqlite::Transaction, messages: &[Message]) -> SimpleResult<()> {
+        let mut insert = tx.prepare_cached(
+            "INSERT OR REPLACE INTO message_language (message_id, lang) VALUES (?,?)",
+        )?;
+        let mut delete = tx.prepare_cached("DELETE FROM message_language WHERE message_id = ?")?;
+        for message in messages {
+            let lang = whatlang::detect(&message.content)
+                .filter(|info| info.is_reliable())
+                .map(|info| info.lang().code());
+            match lang {
+                Some(lang) => insert.execute(rusqlite::params![message.id, lang])?,
+                None => delete.execute([message.id])?,
+            };
+        }
+        Ok(())
+    }
+}
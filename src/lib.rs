@@ -0,0 +1,1682 @@
+// First, so its macros are in scope in the modules after it.
+#[macro_use]
+pub mod console;
+mod analyze;
+pub mod anonymize;
+pub mod api;
+mod app_commands;
+mod archive;
+mod case;
+pub mod client;
+mod credentials;
+pub mod cutoff;
+pub mod db;
+mod dce;
+mod dry_run;
+mod duplicates;
+mod emoji;
+pub mod enrich;
+pub mod exit;
+mod export;
+mod forget;
+mod gaps;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod guild;
+pub mod hook;
+mod import;
+mod interrupt;
+pub mod lang;
+mod mentions;
+mod merge;
+mod privacy;
+mod progress;
+mod prune;
+mod query;
+mod ratelimit;
+pub mod read;
+pub mod redact;
+pub mod refresh;
+mod report;
+pub mod retry;
+mod scrape_state;
+mod script;
+mod search;
+mod serve;
+pub mod snowflake;
+mod spam;
+mod split;
+pub mod summary;
+mod throttle;
+mod timezone;
+mod tui;
+mod upload;
+pub mod urls;
+mod verify;
+
+use anonymize::Anonymizer;
+use api::ApiClient;
+use clap::{CommandFactory, Parser, Subcommand};
+use cutoff::Cutoff;
+use exit::Failure;
+use redact::Redactor;
+use serde::{Deserialize, Serialize};
+use snowflake::Snowflake;
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const BASE_URL: &str = "https://discord.com/api/v10";
+/// Messages per page, the most the API returns at once.
+const PAGE_SIZE: u64 = 100;
+
+pub type SimpleResult<T> = Result<T, Box<dyn Error>>;
+
+/// Runs the command line given to the process.
+pub fn run() -> SimpleResult<()> {
+    let args = Args::parse();
+    console::configure(args.no_color, args.quiet);
+    if let Some(key) = args
+        .db_key
+        .clone()
+        .or_else(|| env::var("DISCORD_DB_KEY").ok())
+    {
+        db::set_key(key)?;
+    }
+    if let Some(zone) = &args.timezone {
+        timezone::set(zone)?;
+    }
+    if let Some(rps) = args.rps {
+        throttle::set_rate(rps)?;
+    }
+    retry::set_policy(args.retries, args.retry_backoff, args.max_backoff)?;
+    if let Some(max_wait) = args.max_rate_limit_wait {
+        retry::set_max_rate_limit_wait(max_wait)?;
+    }
+    if let Some(command) = args.hook.clone() {
+        hook::set_command(command)?;
+    }
+    if let Some(path) = &args.script {
+        script::load(path)?;
+    }
+    if args.detect_language {
+        enrich::register(lang::LanguageDetector);
+    }
+    if args.extract_urls {
+        enrich::register(urls::UrlExtractor::new());
+    }
+
+    match args.command {
+        Some(Command::Auth(cmd)) => credentials::run(cmd, &args.client),
+        Some(Command::Case(cmd)) => {
+            let mut conn = db::open_db(&args.db_path)?;
+            case::run(&mut conn, cmd)
+        }
+        Some(Command::Report(report_args)) => {
+            let conn = db::open_db(&args.db_path)?;
+            report::run(&conn, &args.db_path, report_args)
+        }
+        Some(Command::Analyze(cmd)) => analyze::run(&args.db_path, cmd),
+        Some(Command::Export(export_args)) => {
+            let conn = if export_args.writes() {
+                db::open_db(&args.db_path)?
+            } else {
+                db::open_read_only(&args.db_path)?
+            };
+            let anonymizer = load_anonymizer(&args.anonymize)?;
+            let upload = match args.upload.uploader()? {
+                Some(uploader) => match export_args.destination() {
+                    Some(destination) => Some((uploader, destination.to_string())),
+                    None => return Err("--upload needs the export written to a file".into()),
+                },
+                None => None,
+            };
+            export::run(
+                &conn,
+                export_args,
+                args.redact.redactor().as_ref(),
+                anonymizer.as_ref(),
+            )?;
+            if let Some((uploader, destination)) = upload {
+                uploader.upload(&destination)?;
+            }
+            Ok(())
+        }
+        Some(Command::Import(cmd)) => {
+            let mut conn = db::open_db(&args.db_path)?;
+            let anonymizer = load_anonymizer(&args.anonymize)?;
+            import::run(
+                &mut conn,
+                cmd,
+                anonymizer.as_ref(),
+                args.redact.redactor().as_ref(),
+            )
+        }
+        Some(Command::Query(query_args)) => {
+            let conn = db::open_read_only(&args.db_path)?;
+            query::run(&conn, query_args)
+        }
+        Some(Command::Serve(serve_args)) => {
+            let anonymizer = load_anonymizer(&args.anonymize)?;
+            serve::run(
+                &args.db_path,
+                serve_args,
+                args.redact.redactor().as_ref(),
+                anonymizer.as_ref(),
+            )
+        }
+        Some(Command::Split(split_args)) => split::run(&args.db_path, split_args),
+        Some(Command::Archive(archive_args)) => archive::run(&args.db_path, archive_args),
+        Some(Command::Prune(prune_args)) => prune::run(&args.db_path, prune_args),
+        Some(Command::ForgetUser(forget_args)) => forget::run(&args.db_path, forget_args),
+        Some(Command::Merge(merge_args)) => merge::run(&args.db_path, merge_args),
+        Some(Command::Vacuum) => {
+            let conn = db::open_db(&args.db_path)?;
+            db::vacuum(&conn, &args.db_path)
+        }
+        Some(Command::Backup { destination }) => {
+            let conn = db::open_db(&args.db_path)?;
+            db::backup(&conn, &destination)
+        }
+        Some(Command::Verify) => {
+            let conn = db::open_db(&args.db_path)?;
+            verify::run(&conn)
+        }
+        Some(Command::Gaps(gaps_args)) => {
+            let mut conn = db::open_db(&args.db_path)?;
+            let gaps = gaps::run(&conn, &gaps_args)?;
+            if gaps_args.fill {
+                let client = args.client.build()?;
+                let anonymizer = load_anonymizer(&args.anonymize)?;
+                let redactor = args.redact.redactor();
+                for gap in &gaps {
+                    fill_gap(
+                        &mut conn,
+                        &client,
+                        gap,
+                        anonymizer.as_ref(),
+                        redactor.as_ref(),
+                    )?;
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Completions { shell }) => {
+            let mut command = Args::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+            Ok(())
+        }
+        Some(Command::ResolveChannel { guild_id, name }) => {
+            let client = args.client.build()?;
+            let channel = resolve_channel(&client, guild_id, &name)?;
+            println!("{}", channel.id);
+            Ok(())
+        }
+        None => scrape(args),
+    }
+}
+
+fn scrape(args: Args) -> SimpleResult<()> {
+    let started = Instant::now();
+    let client = args.client.build()?;
+    if args.dry_run {
+        return dry_run::run(&args, &client);
+    }
+    let anonymizer = load_anonymizer(&args.anonymize)?;
+    let redactor = args.redact.redactor();
+    let uploader = args.upload.uploader()?;
+
+    if args.concurrency == 0 {
+        return Err("--concurrency must be at least 1".into());
+    }
+    interrupt::install()?;
+    if args.search.is_set() {
+        return search::run(
+            &args,
+            &client,
+            anonymizer.as_ref(),
+            redactor.as_ref(),
+            uploader,
+            started,
+        );
+    }
+    if args.tui {
+        tui::start()?;
+    }
+    if args.app_commands && client::is_bot() {
+        warn!("Bots can't read the application command index, ignoring --app-commands");
+    }
+
+    let mut conns = HashMap::new();
+    let mut guilds = GuildCache::default();
+    let mut voice_chats = Vec::new();
+    let mut jobs = Vec::new();
+    // Keyed by position on the command line, so channels are reported in that order.
+    let mut summaries = BTreeMap::new();
+
+    for (index, channel_arg) in args.channel_ids.iter().enumerate() {
+        if interrupt::requested() {
+            break;
+        }
+        match prepare_channel(
+            &args,
+            &client,
+            anonymizer.as_ref(),
+            &mut conns,
+            &mut guilds,
+            &mut voice_chats,
+            channel_arg,
+        ) {
+            Ok((db_path, channel_id, name)) => jobs.push(Job {
+                index,
+                db_path,
+                channel_id,
+                name,
+            }),
+            Err(e) => {
+                warn!("Skipping channel {}: {}", channel_arg, e);
+                let mut summary = match channel_arg {
+                    ChannelArg::Id(channel_id) => {
+                        summary::ChannelSummary::new(Some(*channel_id), None)
+                    }
+                    ChannelArg::Name { name, .. } => {
+                        summary::ChannelSummary::new(None, Some(name.clone()))
+                    }
+                };
+                summary.fail(&*e);
+                summaries.insert(index, summary);
+            }
+        }
+    }
+    // Voice chats are scraped after the channels on the command line, once each.
+    for (db_path, channel_id, name) in voice_chats {
+        if jobs.iter().any(|job| job.channel_id == channel_id) {
+            continue;
+        }
+        jobs.push(Job {
+            index: args.channel_ids.len() + jobs.len(),
+            db_path,
+            channel_id,
+            name,
+        });
+    }
+    // Workers open connections of their own.
+    drop(conns);
+    for job in &jobs {
+        progress::queue(job.channel_id, job.name.clone());
+    }
+
+    // Each worker takes the next channel not yet scraped until none are left, so at most
+    // --concurrency channels and requests are in flight at once.
+    let next_job = AtomicUsize::new(0);
+    let summaries = Mutex::new(summaries);
+    let workers = args.concurrency.min(jobs.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                scrape_worker(
+                    &jobs,
+                    &next_job,
+                    &summaries,
+                    &client,
+                    args.refresh,
+                    anonymizer.as_ref(),
+                    redactor.as_ref(),
+                )
+            });
+        }
+    });
+    tui::stop()?;
+
+    let channels = summaries.into_inner().unwrap().into_values().collect();
+    let summary = summary::RunSummary::new(channels, started);
+    summary.print(args.output);
+
+    if interrupt::requested() {
+        return Err(Failure::new(exit::INTERRUPTED, "Interrupted").into());
+    }
+    // A failed channel doesn't stop the others, but still fails the run once they're done.
+    let codes: Vec<i32> = summary
+        .channels
+        .iter()
+        .filter_map(|c| c.exit_code)
+        .collect();
+    if !codes.is_empty() {
+        // When every channel failed the same way, the run exits as they did.
+        let code = if codes.len() < summary.channels.len() {
+            exit::PARTIAL
+        } else if codes.iter().all(|&code| code == codes[0]) {
+            codes[0]
+        } else {
+            exit::FAILURE
+        };
+        let err_msg = format!(
+            "{} of {} channels failed",
+            codes.len(),
+            summary.channels.len()
+        );
+        return Err(Failure::new(code, err_msg).into());
+    }
+
+    // The workers have closed their connections, so the databases are whole files again.
+    if let Some(uploader) = uploader {
+        let db_paths: BTreeSet<&str> = jobs.iter().map(|job| job.db_path.as_str()).collect();
+        for db_path in db_paths {
+            uploader.upload(db_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// A channel whose messages are to be scraped into the database at `db_path`.
+struct Job {
+    /// Position of the channel on the command line.
+    index: usize,
+    db_path: String,
+    channel_id: Snowflake,
+    name: Option<String>,
+}
+
+/// Fetches a channel named on the command line and stores it, along with its guild the
+/// first time that's seen. Returns the database it went to, its ID and its name. With
+/// `--voice-chats`, the guild's voice and stage channels are added to `voice_chats` the
+/// same way.
+fn prepare_channel(
+    args: &Args,
+    client: &dyn ApiClient,
+    anonymizer: Option<&Anonymizer>,
+    conns: &mut HashMap<String, rusqlite::Connection>,
+    guilds: &mut GuildCache,
+    voice_chats: &mut Vec<(String, Snowflake, Option<String>)>,
+    channel_arg: &ChannelArg,
+) -> SimpleResult<(String, Snowflake, Option<String>)> {
+    let channel = guilds.channel(client, channel_arg)?;
+    let channel_id = channel.id;
+    let guild_id = channel.guild_id;
+    let channel_name = channel.name.clone();
+
+    let db_path = match &args.db_per_guild {
+        Some(dir) => guild_db_path(dir, guild_id),
+        None => args.db_path.clone(),
+    };
+    let conn = match conns.entry(db_path.clone()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let conn = db::open_db(entry.key())?;
+            entry.insert(conn)
+        }
+    };
+
+    if let Some(guild_id) = guild_id {
+        // Only marked as seen once stored, so the guild's next channel tries again.
+        if !guilds.stored.contains(&guild_id) {
+            let guild = guild::get_guild(client, guild_id)?;
+            guild::insert_guild(conn, guild)?;
+            let roles = guild::get_roles(client, guild_id)?;
+            guild::insert_roles(conn, guild_id, roles)?;
+
+            if args.app_commands && !client::is_bot() {
+                app_commands::archive_app_commands(conn, client, guild_id)?;
+            }
+
+            // Voice and stage channels are kept even though only their chat has messages,
+            // so the guild's structure is whole.
+            let voice: Vec<Channel> = guilds
+                .guild_channels(client, guild_id)?
+                .iter()
+                .filter(|c| c.is_voice())
+                .cloned()
+                .collect();
+            for channel in voice {
+                if args.voice_chats {
+                    voice_chats.push((db_path.clone(), channel.id, channel.name.clone()));
+                }
+                let channel = match anonymizer {
+                    Some(anonymizer) => anonymizer.channel(channel),
+                    None => channel,
+                };
+                insert_channel(conn, channel)?;
+            }
+            let events = guild::get_scheduled_events(client, guild_id)?;
+            guild::insert_scheduled_events(conn, guild_id, events)?;
+            let sounds = guild::get_soundboard_sounds(client, guild_id)?;
+            guild::insert_soundboard_sounds(conn, guild_id, sounds)?;
+            guilds.stored.insert(guild_id);
+        }
+    }
+    let channel = match anonymizer {
+        Some(anonymizer) => anonymizer.channel(channel),
+        None => channel,
+    };
+    insert_channel(conn, channel)?;
+    Ok((db_path, channel_id, channel_name))
+}
+
+/// Scrapes channels from `jobs` until every one has been taken by some worker, adding
+/// what happened to each to `summaries`, and refreshes what was sent since `refresh`.
+/// A channel that fails is logged and left for the next one. Stops taking channels once
+/// interrupted.
+fn scrape_worker(
+    jobs: &[Job],
+    next_job: &AtomicUsize,
+    summaries: &Mutex<BTreeMap<usize, summary::ChannelSummary>>,
+    client: &dyn ApiClient,
+    refresh: Option<Cutoff>,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) {
+    let mut conns = HashMap::new();
+    while !interrupt::requested() {
+        let job = match jobs.get(next_job.fetch_add(1, Ordering::Relaxed)) {
+            Some(job) => job,
+            None => break,
+        };
+        let mut summary = summary::ChannelSummary::new(Some(job.channel_id), job.name.clone());
+        progress::start(job.channel_id);
+        let result = match conns.entry(job.db_path.clone()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => db::open_db(&job.db_path).map(|conn| entry.insert(conn)),
+        }
+        .and_then(|conn| {
+            get_channel_messages(
+                conn,
+                client,
+                job.channel_id,
+                anonymizer,
+                redactor,
+                &mut summary,
+            )?;
+            match refresh {
+                Some(since) if !interrupt::requested() => refresh::run(
+                    conn,
+                    client,
+                    job.channel_id,
+                    since,
+                    anonymizer,
+                    redactor,
+                    &mut summary,
+                ),
+                _ => Ok(()),
+            }
+        });
+        if let Err(e) = result {
+            warn!("Channel {} failed: {}", job.channel_id, e);
+            summary.fail(&*e);
+        }
+        progress::finish(job.channel_id, summary.error.is_some());
+        summaries.lock().unwrap().insert(job.index, summary);
+    }
+}
+
+fn load_anonymizer(salt_path: &Option<String>) -> SimpleResult<Option<Anonymizer>> {
+    salt_path.as_deref().map(Anonymizer::load).transpose()
+}
+
+/// The database a guild is written to with `--db-per-guild`, named by guild ID.
+/// Direct messages have no guild and share `direct.db`.
+fn guild_db_path(dir: &str, guild_id: Option<Snowflake>) -> String {
+    let file_name = match guild_id {
+        Some(id) => format!("{}.db", id),
+        None => "direct.db".to_string(),
+    };
+    Path::new(dir)
+        .join(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Debug, Parser)]
+#[clap(author, version, about)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    client: client::ClientArgs,
+
+    /// Channel IDs, or channel names written as `<guild_id>/#<name>`
+    channel_ids: Vec<ChannelArg>,
+
+    /// Also archive the application commands registered in each guild
+    #[clap(long)]
+    app_commands: bool,
+
+    /// Also scrape the text chat of every voice and stage channel in each guild
+    #[clap(long)]
+    voice_chats: bool,
+
+    /// Database path
+    #[clap(short, long, global = true, default_value_t = String::from("./data/messages.db"))]
+    db_path: String,
+
+    /// Passphrase of an encrypted database, defaults to DISCORD_DB_KEY (needs the `sqlcipher` feature)
+    #[clap(long, global = true)]
+    db_key: Option<String>,
+
+    /// Store salted hashes instead of user IDs and names, keeping the salt in this file
+    #[clap(long, global = true, value_name = "SALT_FILE")]
+    anonymize: Option<String>,
+
+    #[clap(flatten)]
+    redact: redact::RedactArgs,
+
+    #[clap(flatten)]
+    upload: upload::UploadArgs,
+
+    #[clap(flatten)]
+    search: search::SearchArgs,
+
+    /// Write each guild into its own database in this directory instead of --db-path
+    #[clap(long)]
+    db_per_guild: Option<String>,
+
+    /// Print what would be scraped and roughly how many requests it needs, writing nothing
+    #[clap(long)]
+    dry_run: bool,
+
+    /// After fetching new messages, fetch those sent within this age again (e.g. 7d) to
+    /// update their edits and reactions and mark the ones deleted since
+    #[clap(long, value_name = "AGE")]
+    refresh: Option<Cutoff>,
+
+    /// Scrape up to this many channels at once
+    #[clap(long, default_value_t = 1, value_name = "N")]
+    concurrency: usize,
+
+    /// Show progress on a terminal dashboard instead of the log (needs the `tui` feature)
+    #[clap(long)]
+    tui: bool,
+
+    /// How to report the run once it's done
+    #[clap(long, arg_enum, default_value = "text")]
+    output: summary::OutputFormat,
+
+    /// Print only errors and the summary at the end of a scrape
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Don't color warnings and errors, also turned off by setting NO_COLOR
+    #[clap(long, global = true)]
+    no_color: bool,
+
+    /// Show times in exports, reports and analyses in this IANA time zone, e.g. Europe/Berlin,
+    /// instead of UTC
+    #[clap(long, global = true, value_name = "TZ")]
+    timezone: Option<String>,
+
+    /// Send at most this many requests per second, e.g. 0.5 for one every two seconds
+    #[clap(long, global = true, value_name = "N")]
+    rps: Option<f64>,
+
+    /// Retry a request this many times when it fails to get through or gets a server error
+    #[clap(long, global = true, default_value_t = 3, value_name = "N")]
+    retries: u32,
+
+    /// Seconds to wait before the first retry, doubling with every further one
+    #[clap(long, global = true, default_value_t = 1.0, value_name = "SECS")]
+    retry_backoff: f64,
+
+    /// Longest wait between two retries in seconds
+    #[clap(long, global = true, default_value_t = 60.0, value_name = "SECS")]
+    max_backoff: f64,
+
+    /// Give up on a request instead of waiting out a rate limit longer than this many
+    /// seconds, by default any is waited out
+    #[clap(long, global = true, value_name = "SECS")]
+    max_rate_limit_wait: Option<f64>,
+
+    /// Run this shell command after each stored page of messages, passing the page on
+    /// standard input as a JSON array
+    #[clap(long, global = true, value_name = "CMD")]
+    hook: Option<String>,
+
+    /// Pass each message through the on_message function of this Lua script, which can drop
+    /// it by returning false or return a table of fields to store with it (needs the `lua`
+    /// feature)
+    #[clap(long, global = true, value_name = "FILE")]
+    script: Option<String>,
+
+    /// Detect the language of each message as it's stored, e.g. to export one language only
+    #[clap(long, global = true)]
+    detect_language: bool,
+
+    /// Store the links in each message, with their domains, as it's stored
+    #[clap(long, global = true)]
+    extract_urls: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the token saved in the platform keyring
+    #[clap(subcommand)]
+    Auth(credentials::AuthCommand),
+    /// Group archived messages into moderation cases
+    #[clap(subcommand)]
+    Case(case::CaseCommand),
+    /// Generate documents describing the archive
+    Report(report::ReportArgs),
+    /// Compute statistics over the stored messages
+    #[clap(subcommand)]
+    Analyze(analyze::AnalyzeCommand),
+    /// Write stored messages out as text, JSON lines or CSV
+    Export(export::ExportArgs),
+    /// Add messages from other sources to the database
+    #[clap(subcommand)]
+    Import(import::ImportCommand),
+    /// Run SQL against the database without being able to modify it
+    Query(query::QueryArgs),
+    /// Serve the archive over a read-only HTTP API
+    Serve(serve::ServeArgs),
+    /// Partition the database into several smaller ones
+    Split(split::SplitArgs),
+    /// Move old messages out of the working database into monthly archives
+    Archive(archive::ArchiveArgs),
+    /// Delete messages older than a retention window, and users left without messages
+    Prune(prune::PruneArgs),
+    /// Erase a user's messages, mentions and names, and leave them out of later scrapes
+    ForgetUser(forget::ForgetUserArgs),
+    /// Merge another scrape database into this one
+    Merge(merge::MergeArgs),
+    /// Reclaim unused space and refresh query planner statistics
+    Vacuum,
+    /// Copy the database to another file, safe to run while a scrape is writing to it
+    Backup { destination: String },
+    /// Check the archive for missing references, malformed data and corruption
+    Verify,
+    /// Find suspicious holes in the stored message history
+    Gaps(gaps::GapsArgs),
+    /// Print the ID of a guild channel given its name
+    ResolveChannel { guild_id: Snowflake, name: String },
+    /// Print a completion script for a shell, to be sourced from its startup file
+    Completions {
+        #[clap(arg_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// A channel named on the command line, either directly by ID or by guild and name.
+#[derive(Debug, Clone)]
+enum ChannelArg {
+    Id(Snowflake),
+    Name { guild_id: Snowflake, name: String },
+}
+
+impl FromStr for ChannelArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((guild_id, name)) => Ok(ChannelArg::Name {
+                guild_id: guild_id
+                    .parse()
+                    .map_err(|e| format!("invalid guild ID: {}", e))?,
+                name: name.to_string(),
+            }),
+            None => s
+                .parse()
+                .map(ChannelArg::Id)
+                .map_err(|e| format!("invalid channel ID: {}", e)),
+        }
+    }
+}
+
+impl fmt::Display for ChannelArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelArg::Id(channel_id) => write!(f, "{}", channel_id),
+            ChannelArg::Name { guild_id, name } => write!(f, "{}/{}", guild_id, name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    id: Snowflake,
+    guild_id: Option<Snowflake>,
+    name: Option<String>,
+    #[serde(rename = "type", default)]
+    kind: i64,
+    #[serde(default)]
+    topic: Option<String>,
+    /// The audio bitrate of voice and stage channels, in bits per second.
+    #[serde(default)]
+    bitrate: Option<i64>,
+    /// How many users may join a voice or stage channel at once, 0 for any number.
+    #[serde(default)]
+    user_limit: Option<i64>,
+    #[serde(default)]
+    permission_overwrites: Vec<PermissionOverwrite>,
+}
+
+/// The channel types users talk in, each with a text chat of its own.
+const GUILD_VOICE: i64 = 2;
+const GUILD_STAGE_VOICE: i64 = 13;
+
+impl Channel {
+    fn is_voice(&self) -> bool {
+        matches!(self.kind, GUILD_VOICE | GUILD_STAGE_VOICE)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PermissionOverwrite {
+    /// The role or member the overwrite applies to.
+    id: Snowflake,
+    /// 0 for a role, 1 for a member.
+    #[serde(rename = "type")]
+    kind: i64,
+    allow: String,
+    deny: String,
+}
+
+/// The message type Discord uses for replies.
+const REPLY: i64 = 19;
+/// The message flag set on copies of announcements in the channels following them.
+const IS_CROSSPOST: i64 = 1 << 1;
+/// The message flag set on forwards, which carry a snapshot of the message they forward.
+const HAS_SNAPSHOT: i64 = 1 << 14;
+/// The message reference type of forwards.
+const FORWARD: i64 = 1;
+/// The message flag set on voice messages, whose audio is their only attachment.
+const IS_VOICE_MESSAGE: i64 = 1 << 13;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub id: Snowflake,
+    pub channel_id: Snowflake,
+    pub author: User,
+    pub content: String,
+    pub timestamp: String,
+    #[serde(rename = "type", default)]
+    pub kind: i64,
+    pub message_reference: Option<MessageReference>,
+    pub edited_timestamp: Option<String>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    #[serde(default)]
+    pub flags: i64,
+    /// What a forward copies of the message it forwards.
+    #[serde(default)]
+    pub message_snapshots: Vec<MessageSnapshot>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+impl Message {
+    /// The message this one replies to, if it is a reply.
+    fn reply_to(&self) -> Option<Snowflake> {
+        match (&self.message_reference, self.kind) {
+            (Some(reference), REPLY) => reference.message_id,
+            _ => None,
+        }
+    }
+
+    /// The message this one is a copy of, if it was crossposted from a channel the
+    /// message's channel follows or forwarded.
+    fn origin(&self) -> Option<&MessageReference> {
+        match &self.message_reference {
+            Some(reference) if self.flags & IS_CROSSPOST != 0 || reference.kind == FORWARD => {
+                Some(reference)
+            }
+            _ => None,
+        }
+    }
+
+    /// The recording of a voice message.
+    fn voice(&self) -> Option<&Attachment> {
+        match self.attachments.first() {
+            Some(attachment) if self.flags & IS_VOICE_MESSAGE != 0 => Some(attachment),
+            _ => None,
+        }
+    }
+
+    /// Takes on the content of the message a forward copies. Forwards have none of their
+    /// own, comments on them are sent as messages of their own. The snapshots are dropped so
+    /// the copied text is only kept where it gets redacted and anonymized.
+    fn unwrap_forward(&mut self) {
+        let snapshots = std::mem::take(&mut self.message_snapshots);
+        if let Some(snapshot) = snapshots.into_iter().next() {
+            if self.content.is_empty() {
+                self.content = snapshot.message.content;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageSnapshot {
+    pub message: SnapshotMessage,
+}
+
+/// The parts of a forwarded message kept in a forward.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotMessage {
+    #[serde(default)]
+    pub content: String,
+}
+
+/// The parts of an attachment kept, those describing the recording of voice messages. The
+/// files themselves aren't downloaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(default)]
+    pub duration_secs: Option<f64>,
+    /// Base64 encoded bytes, each the volume of a slice of the recording.
+    #[serde(default)]
+    pub waveform: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MessageReference {
+    /// 0 for replies and crossposts, 1 for forwards.
+    #[serde(rename = "type", default)]
+    pub kind: i64,
+    pub message_id: Option<Snowflake>,
+    #[serde(default)]
+    pub channel_id: Option<Snowflake>,
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    /// Every reaction with the emoji, super reactions included.
+    pub count: i64,
+    pub emoji: ReactionEmoji,
+    #[serde(default)]
+    pub count_details: Option<ReactionCounts>,
+    /// The colors of the super reaction animation, as `#rrggbb`.
+    #[serde(default)]
+    pub burst_colors: Vec<String>,
+}
+
+impl Reaction {
+    /// How many of the reactions are super reactions.
+    pub fn burst_count(&self) -> i64 {
+        self.count_details.as_ref().map_or(0, |counts| counts.burst)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionCounts {
+    pub burst: i64,
+    pub normal: i64,
+}
+
+/// Custom emoji have an ID, and a name unless they were deleted. Unicode emoji have only
+/// a name, the emoji itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionEmoji {
+    pub id: Option<Snowflake>,
+    pub name: Option<String>,
+}
+
+impl ReactionEmoji {
+    /// How the emoji is stored in `message_reaction`: `name:id` for custom emoji, the way
+    /// Discord writes them in messages, and the emoji itself otherwise.
+    fn key(&self) -> String {
+        match self.id {
+            Some(id) => format!("{}:{}", self.name.as_deref().unwrap_or_default(), id),
+            None => self.name.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub id: Snowflake,
+    pub username: String,
+    /// Only left on bots and the accounts that haven't moved to unique usernames.
+    #[serde(default, deserialize_with = "discriminator")]
+    pub discriminator: Option<String>,
+    /// The display name users can pick on top of their username.
+    #[serde(default)]
+    pub global_name: Option<String>,
+}
+
+impl User {
+    /// The name Discord shows for the user outside of guilds.
+    pub fn display_name(&self) -> &str {
+        self.global_name.as_deref().unwrap_or(&self.username)
+    }
+
+    /// `name#1234` for users with a discriminator, their username otherwise.
+    pub fn tag(&self) -> String {
+        match &self.discriminator {
+            Some(discriminator) => format!("{}#{}", self.username, discriminator),
+            None => self.username.clone(),
+        }
+    }
+}
+
+/// Users without a discriminator come with `"0"`.
+fn discriminator<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let discriminator = Option::<String>::deserialize(deserializer)?;
+    Ok(discriminator.filter(|d| d != "0"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DiscordError {
+    message: String,
+    code: usize,
+}
+
+fn insert_channel(conn: &mut rusqlite::Connection, channel: Channel) -> SimpleResult<()> {
+    info!(
+        "Inserting 1 Channel: {}",
+        channel.name.as_ref().unwrap_or(&"".to_string())
+    );
+
+    let name = channel.name.unwrap_or_default();
+    let tx = conn.transaction()?;
+    // A topic stored as NULL may just not have been known yet, so gaining one loses nothing.
+    let changed = tx.execute(
+        "INSERT INTO channel_history (channel_id, name, topic)
+         SELECT id, name, topic FROM channel
+         WHERE id = ? AND (name IS NOT ? OR (topic IS NOT NULL AND topic IS NOT ?))",
+        rusqlite::params![channel.id, name, channel.topic],
+    )?;
+    if changed > 0 {
+        info!("Channel {} was renamed or changed its topic", channel.id);
+    }
+    tx.execute(
+        "INSERT INTO channel (id, guild_id, name, topic, type, bitrate, user_limit)
+         VALUES (?,?,?,?,?,?,?)
+         ON CONFLICT (id) DO UPDATE SET name = excluded.name, topic = excluded.topic,
+            type = excluded.type, bitrate = excluded.bitrate, user_limit = excluded.user_limit",
+        rusqlite::params![
+            channel.id,
+            channel.guild_id,
+            name,
+            channel.topic,
+            channel.kind,
+            channel.bitrate,
+            channel.user_limit
+        ],
+    )?;
+
+    tx.execute(
+        "DELETE FROM permission_overwrite WHERE channel_id = ?",
+        [channel.id],
+    )?;
+    let forgotten = forget::forgotten(&tx)?;
+    for overwrite in channel.permission_overwrites {
+        if overwrite.kind == forget::MEMBER_OVERWRITE && forgotten.contains(&overwrite.id) {
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO permission_overwrite (channel_id, target_id, type, allow, deny) VALUES (?,?,?,?,?)",
+            rusqlite::params![
+                channel.id,
+                overwrite.id,
+                overwrite.kind,
+                overwrite.allow.parse::<i64>()?,
+                overwrite.deny.parse::<i64>()?
+            ],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Builds the placeholder list for a multi-row insert, e.g. `(?,?),(?,?)` for two rows of two columns.
+fn values_placeholders(rows: usize, columns: usize) -> String {
+    let row = format!("({})", vec!["?"; columns].join(","));
+    vec![row; rows].join(",")
+}
+
+/// The users stored in each database file during this run, as they were stored, so the
+/// same authors on every page aren't checked and written again. In-memory databases are
+/// each their own and left out.
+static STORED_USERS: Mutex<BTreeMap<PathBuf, HashMap<Snowflake, User>>> =
+    Mutex::new(BTreeMap::new());
+
+fn insert_users(conn: &mut rusqlite::Connection, mut users: Vec<&User>) -> SimpleResult<()> {
+    users.sort_by_key(|u| u.id);
+    users.dedup_by_key(|u| u.id);
+    let db_path = conn
+        .path()
+        .filter(|path| !path.as_os_str().is_empty() && *path != Path::new(":memory:"))
+        .map(Path::to_path_buf);
+    if let Some(db_path) = &db_path {
+        if let Some(stored) = STORED_USERS.lock().unwrap().get(db_path) {
+            users.retain(|user| stored.get(&user.id) != Some(*user));
+        }
+    }
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    {
+        // Names about to be replaced go to the history first.
+        let mut history = tx.prepare_cached(
+            "INSERT INTO user_history (user_id, username, discriminator, global_name)
+             SELECT id, username, discriminator, global_name FROM user
+             WHERE id = ? AND (username IS NOT ? OR discriminator IS NOT ? OR global_name IS NOT ?)
+             RETURNING username",
+        )?;
+        let mut renamed = HashSet::new();
+        for user in &users {
+            let mut rows = history.query(rusqlite::params![
+                user.id,
+                user.username,
+                user.discriminator,
+                user.global_name
+            ])?;
+            if let Some(row) = rows.next()? {
+                info!(
+                    "Updating 1 User: {:?} is now {:?}",
+                    row.get::<_, String>(0)?,
+                    user.username
+                );
+                renamed.insert(user.id);
+            }
+        }
+
+        let mut stmt = tx.prepare_cached(&format!(
+            "INSERT INTO user (id, username, discriminator, global_name) VALUES {}
+             ON CONFLICT (id) DO UPDATE SET
+                username = excluded.username, discriminator = excluded.discriminator,
+                global_name = excluded.global_name
+             WHERE username IS NOT excluded.username OR discriminator IS NOT excluded.discriminator
+                OR global_name IS NOT excluded.global_name
+             RETURNING id, username",
+            values_placeholders(users.len(), 4)
+        ))?;
+
+        let params = users.iter().flat_map(|user| -> [&dyn rusqlite::ToSql; 4] {
+            [
+                &user.id,
+                &user.username,
+                &user.discriminator,
+                &user.global_name,
+            ]
+        });
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        while let Some(row) = rows.next()? {
+            if !renamed.contains(&row.get::<_, Snowflake>(0)?) {
+                info!("Inserting 1 User: {:?}", row.get::<_, String>(1)?);
+            }
+        }
+    }
+    tx.commit()?;
+
+    if let Some(db_path) = db_path {
+        STORED_USERS
+            .lock()
+            .unwrap()
+            .entry(db_path)
+            .or_default()
+            .extend(users.into_iter().map(|user| (user.id, user.clone())));
+    }
+
+    Ok(())
+}
+
+/// Stores the messages not stored yet, returning how many that were.
+fn insert_messages(conn: &mut rusqlite::Connection, messages: &[Message]) -> SimpleResult<usize> {
+    info!("Inserting {} Messages", &messages.len());
+    if messages.is_empty() {
+        return Ok(0);
+    }
+
+    let created_at: Vec<i64> = messages.iter().map(|m| m.id.created_at_unix()).collect();
+    let reply_to: Vec<Option<Snowflake>> = messages.iter().map(|m| m.reply_to()).collect();
+    let origin: Vec<MessageReference> = messages
+        .iter()
+        .map(|m| m.origin().cloned().unwrap_or_default())
+        .collect();
+    let voice: Vec<Attachment> = messages
+        .iter()
+        .map(|m| m.voice().cloned().unwrap_or_default())
+        .collect();
+
+    let inserted;
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO message (id, channel_id, author_id, content, timestamp, created_at_unix, reply_to, edited_timestamp, flags, origin_guild_id, origin_channel_id, origin_message_id, voice_duration_secs, voice_waveform) VALUES {}",
+            values_placeholders(messages.len(), 14)
+        ))?;
+
+        let params = messages
+            .iter()
+            .zip(&created_at)
+            .zip(&reply_to)
+            .zip(&origin)
+            .zip(&voice)
+            .flat_map(
+                |((((msg, created_at_unix), reply_to), origin), voice)| -> [&dyn rusqlite::ToSql; 14] {
+                    [
+                        &msg.id,
+                        &msg.channel_id,
+                        &msg.author.id,
+                        &msg.content,
+                        &msg.timestamp,
+                        created_at_unix,
+                        reply_to,
+                        &msg.edited_timestamp,
+                        &msg.flags,
+                        &origin.guild_id,
+                        &origin.channel_id,
+                        &origin.message_id,
+                        &voice.duration_secs,
+                        &voice.waveform,
+                    ]
+                },
+            );
+        inserted = stmt.execute(rusqlite::params_from_iter(params))?;
+    }
+    replace_reactions(&tx, messages)?;
+    tx.commit()?;
+
+    Ok(inserted)
+}
+
+/// Replaces the stored reactions of `messages` with the counts they came with.
+fn replace_reactions(tx: &rusqlite::Transaction, messages: &[Message]) -> SimpleResult<()> {
+    let mut delete = tx.prepare_cached("DELETE FROM message_reaction WHERE message_id = ?")?;
+    let mut insert = tx.prepare_cached(
+        "INSERT OR REPLACE INTO message_reaction (message_id, emoji, count, burst_count, burst_colors)
+         VALUES (?,?,?,?,?)",
+    )?;
+    for message in messages {
+        delete.execute([message.id])?;
+        for reaction in &message.reactions {
+            insert.execute(rusqlite::params![
+                message.id,
+                reaction.emoji.key(),
+                reaction.count,
+                reaction.burst_count(),
+                serde_json::to_string(&reaction.burst_colors)?
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends a GET request to the API, returning the body of the response.
+pub fn send_request(client: &dyn ApiClient, req_url: &str) -> SimpleResult<String> {
+    send_attempt(client, req_url, 0)
+}
+
+/// Sends a request that failed `attempt` times already. Rate limited requests are sent
+/// again once the limit allows, those failing on the way or with a server error after
+/// a backoff.
+fn send_attempt(client: &dyn ApiClient, req_url: &str, attempt: u32) -> SimpleResult<String> {
+    const RETRY_PAD: f64 = 0.1;
+    let token = ratelimit::wait(req_url)?;
+    throttle::wait();
+    summary::count_request();
+    let res = match client.get(req_url, client::authorization(token)) {
+        Ok(res) => res,
+        Err(e) => return retry_failed(client, req_url, attempt, e),
+    };
+    ratelimit::update(req_url, token, &res);
+    let status = reqwest::StatusCode::from_u16(res.status)?;
+
+    if status == reqwest::StatusCode::OK {
+        return Ok(res.body);
+    }
+
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_time = res
+            .header("Retry-After")
+            .ok_or("Rate limited without a Retry-After header")?
+            .parse::<f64>()?;
+        let global = res.header("X-RateLimit-Global").is_some()
+            || res.header("X-RateLimit-Scope") == Some("global");
+        if !retry::waits_out(retry_time) {
+            let err_msg = format!(
+                "While executing request {}: rate limited for {}s, longer than --max-rate-limit-wait",
+                req_url, retry_time
+            );
+            return Err(Failure::new(exit::RATE_LIMITED, err_msg).into());
+        }
+
+        warn!(
+            "Too many requests{}. Sleeping for {}s.",
+            if global { " (global)" } else { "" },
+            retry_time
+        );
+
+        summary::count_rate_limited();
+        // The retry waits in the limiter, along with every other request it holds back.
+        ratelimit::limited(req_url, token, retry_time + RETRY_PAD, global);
+        return send_attempt(client, req_url, attempt);
+    }
+
+    // Another token takes over from one that was revoked, or whose account was banned.
+    // Searches are accepted without results while Discord indexes the guild, saying when
+    // to ask again. They're asked again as many times as failed requests are retried.
+    if status == reqwest::StatusCode::ACCEPTED {
+        let retry_time = serde_json::from_str::<serde_json::Value>(&res.body)
+            .ok()
+            .and_then(|body| body["retry_after"].as_f64())
+            .unwrap_or(1.0);
+        if retry::backoff(attempt).is_none() || !retry::waits_out(retry_time) {
+            let err_msg = format!(
+                "While executing request {}: Discord is still indexing, try again later",
+                req_url
+            );
+            return Err(err_msg.into());
+        }
+        warn!("Not indexed yet. Sleeping for {}s.", retry_time);
+        std::thread::sleep(Duration::from_secs_f64(retry_time));
+        return send_attempt(client, req_url, attempt + 1);
+    }
+
+    if status == reqwest::StatusCode::UNAUTHORIZED && client::revoke(token) {
+        return send_attempt(client, req_url, attempt);
+    }
+
+    if status.is_server_error() {
+        let err_msg = format!("While executing request {}: {}", req_url, status);
+        return retry_failed(client, req_url, attempt, err_msg.into());
+    }
+
+    // Errors from proxies and the like aren't Discord's JSON.
+    let message = match serde_json::from_str::<DiscordError>(&res.body) {
+        Ok(err) => err.message,
+        Err(_) => status.to_string(),
+    };
+    let err_msg = format!("While executing request {}: {}", req_url, message);
+    Err(Failure::new(exit::for_status(status.as_u16()), err_msg).into())
+}
+
+fn retry_failed(
+    client: &dyn ApiClient,
+    req_url: &str,
+    attempt: u32,
+    err: Box<dyn Error>,
+) -> SimpleResult<String> {
+    match retry::backoff(attempt) {
+        Some(delay) => {
+            warn!("{}. Retrying in {:.1}s.", err, delay.as_secs_f64());
+            std::thread::sleep(delay);
+            send_attempt(client, req_url, attempt + 1)
+        }
+        None => Err(err),
+    }
+}
+
+fn get_messages(
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    before: Option<Snowflake>,
+) -> SimpleResult<Vec<Message>> {
+    let req_url = if let Some(before_id) = before {
+        format!(
+            "{}/channels/{}/messages?limit={}&before={}",
+            BASE_URL, channel_id, PAGE_SIZE, before_id
+        )
+    } else {
+        format!(
+            "{}/channels/{}/messages?limit={}",
+            BASE_URL, channel_id, PAGE_SIZE
+        )
+    };
+
+    let body = send_request(client, &req_url)?;
+    let messages: Vec<Message> = serde_json::from_str(&body)?;
+    Ok(messages)
+}
+
+pub fn get_channel_messages(
+    conn: &mut rusqlite::Connection,
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+    summary: &mut summary::ChannelSummary,
+) -> SimpleResult<()> {
+    // Messages newer than earlier runs got are fetched first. Unless those runs reached
+    // the channel's first message, paging then skips to where they stopped.
+    let last_run = scrape_state::load(conn, channel_id)?;
+    match &last_run {
+        Some(state) if state.complete => info!(
+            "Fetching Messages of channel {} newer than {}",
+            channel_id, state.newest_id
+        ),
+        Some(state) => info!(
+            "Resuming channel {} before Message {}",
+            channel_id, state.oldest_id
+        ),
+        None => {}
+    }
+
+    // Pages are fetched on a thread of their own while earlier ones are stored, never more
+    // than PAGES_AHEAD ahead, so memory stays flat however slow storing is.
+    let (sender, pages) = mpsc::sync_channel(PAGES_AHEAD);
+    let last_run = last_run.as_ref();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            if let Err(e) = fetch_pages(client, channel_id, last_run, &sender) {
+                let failure = Failure::new(exit::code(&*e), e.to_string());
+                let _ = sender.send(Err(failure));
+            }
+        });
+
+        let mut complete = false;
+        for page in pages {
+            let page = page?;
+            if !page.messages.is_empty() {
+                let fetched = page.messages.len() as u64;
+                let new = insert_page(conn, page.messages, anonymizer, redactor)? as u64;
+                summary.new += new;
+                summary.skipped += fetched - new;
+            }
+            progress::page(channel_id, summary.new + summary.skipped, page.oldest_id);
+            if let (true, Some(newest_id), Some(oldest_id)) =
+                (page.caught_up, page.newest_id, page.oldest_id)
+            {
+                scrape_state::save(
+                    conn,
+                    channel_id,
+                    &scrape_state::ScrapeState {
+                        newest_id,
+                        oldest_id,
+                        complete: page.complete,
+                    },
+                )?;
+            }
+            complete = page.complete;
+        }
+        if !complete && interrupt::requested() {
+            info!("Stopped channel {}, run again to resume", channel_id);
+        }
+        Ok(())
+    })
+}
+
+/// Pages fetched ahead of the one being stored.
+const PAGES_AHEAD: usize = 2;
+
+/// A page of a channel as fetched, with how far paging has got including it.
+struct Page {
+    messages: Vec<Message>,
+    newest_id: Option<Snowflake>,
+    oldest_id: Option<Snowflake>,
+    /// Until caught up with earlier runs, what's stored has a gap in it not worth saving.
+    caught_up: bool,
+    /// Whether the channel's first message has been reached.
+    complete: bool,
+}
+
+/// Fetches the pages of a channel back from its newest message into `pages`, until the
+/// channel's first message, an interrupt, or storing them stopped.
+fn fetch_pages(
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    last_run: Option<&scrape_state::ScrapeState>,
+    pages: &mpsc::SyncSender<Result<Page, Failure>>,
+) -> SimpleResult<()> {
+    let mut caught_up = last_run.is_none();
+    let mut newest_id = last_run.map(|state| state.newest_id);
+    let mut oldest_id = None;
+
+    let mut before = None;
+    loop {
+        let mut messages = clean_page(
+            channel_id,
+            get_messages(client, channel_id, before)?,
+            before,
+        );
+        if before.is_none() {
+            newest_id = messages.first().map(|m| m.id).or(newest_id);
+        }
+        // None once the channel's first message has been reached.
+        let mut next_before = messages.last().map(|m| m.id);
+        if let (false, Some(state)) = (caught_up, last_run) {
+            if next_before.is_none_or(|id| id <= state.newest_id) {
+                messages.retain(|m| m.id > state.newest_id);
+                next_before = (!state.complete).then_some(state.oldest_id);
+                oldest_id = Some(state.oldest_id);
+                caught_up = true;
+            }
+        }
+        oldest_id = next_before.or(oldest_id);
+
+        let page = Page {
+            messages,
+            newest_id,
+            oldest_id,
+            caught_up,
+            complete: next_before.is_none(),
+        };
+        if pages.send(Ok(page)).is_err() {
+            // Storing failed, and reports why.
+            return Ok(());
+        }
+        before = match next_before {
+            Some(id) => Some(id),
+            None => return Ok(()),
+        };
+        if interrupt::requested() {
+            return Ok(());
+        }
+    }
+}
+
+/// Re-scrapes only the messages between the two stored messages bounding a gap.
+fn fill_gap(
+    conn: &mut rusqlite::Connection,
+    client: &dyn ApiClient,
+    gap: &gaps::Gap,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) -> SimpleResult<()> {
+    info!(
+        "Filling gap in channel {} between {} and {}",
+        gap.channel_id, gap.after, gap.before
+    );
+
+    // The API takes a single cursor, so walk back from the newer bound until the older one.
+    let mut before = Some(gap.before);
+    loop {
+        let mut messages = clean_page(
+            gap.channel_id,
+            get_messages(client, gap.channel_id, before)?,
+            before,
+        );
+        let reached_end = messages.last().is_none_or(|m| m.id <= gap.after);
+        messages.retain(|m| m.id > gap.after);
+
+        if let Some(last) = messages.last() {
+            before = Some(last.id);
+            insert_page(conn, messages, anonymizer, redactor)?;
+        }
+        if reached_end {
+            return Ok(());
+        }
+    }
+}
+
+fn insert_page(
+    conn: &mut rusqlite::Connection,
+    messages: Vec<Message>,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) -> SimpleResult<usize> {
+    let mut messages = protect_page(messages, anonymizer, redactor);
+    forget::scrub(conn, &mut messages)?;
+    let mut fields = Vec::new();
+    if script::is_set() {
+        (messages, fields) = script::apply(messages)?;
+    }
+    let users: Vec<&User> = messages.iter().map(|m| &m.author).collect();
+    insert_users(conn, users)?;
+    let inserted = insert_messages(conn, &messages)?;
+    enrich::apply(conn, &messages)?;
+    script::store_fields(conn, fields)?;
+    hook::run(&messages)?;
+    Ok(inserted)
+}
+
+/// Redacts and anonymizes messages as fetched into what gets stored. Forwards are given
+/// the content they copy first, so it's redacted like any other.
+fn protect_page(
+    mut messages: Vec<Message>,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+) -> Vec<Message> {
+    for message in &mut messages {
+        message.unwrap_forward();
+    }
+    if let Some(redactor) = redactor {
+        for message in &mut messages {
+            message.content = redactor.redact(&message.content);
+        }
+    }
+    if let Some(anonymizer) = anonymizer {
+        messages = messages
+            .into_iter()
+            .map(|m| anonymizer.message(m))
+            .collect();
+    }
+    messages
+}
+
+/// Puts a page into strictly decreasing ID order and drops anything not older than the
+/// `before` cursor, so the cursor always moves backwards and no message is seen twice.
+fn clean_page(
+    channel_id: Snowflake,
+    mut messages: Vec<Message>,
+    before: Option<Snowflake>,
+) -> Vec<Message> {
+    if !messages.windows(2).all(|w| w[0].id > w[1].id) {
+        warn!(
+            "Page of channel {} was out of order or repeated messages, reordering.",
+            channel_id
+        );
+        messages.sort_by_key(|m| std::cmp::Reverse(m.id));
+        messages.dedup_by_key(|m| m.id);
+    }
+
+    if let Some(before) = before {
+        let len = messages.len();
+        messages.retain(|m| m.id < before);
+        if messages.len() != len {
+            warn!(
+                "Discarded {} messages of channel {} overlapping the previous page.",
+                len - messages.len(),
+                channel_id
+            );
+        }
+    }
+
+    messages
+}
+
+fn get_channel(client: &dyn ApiClient, channel_id: Snowflake) -> SimpleResult<Channel> {
+    let req_url = format!("{}/channels/{}", BASE_URL, channel_id);
+
+    let body = send_request(client, &req_url)?;
+    let channel: Channel = serde_json::from_str(&body)?;
+    Ok(channel)
+}
+
+fn get_guild_channels(client: &dyn ApiClient, guild_id: Snowflake) -> SimpleResult<Vec<Channel>> {
+    let req_url = format!("{}/guilds/{}/channels", BASE_URL, guild_id);
+
+    let body = send_request(client, &req_url)?;
+    let channels: Vec<Channel> = serde_json::from_str(&body)?;
+    Ok(channels)
+}
+
+/// What a run has fetched about guilds, so channels of the same guild cost no requests
+/// beyond the first.
+#[derive(Default)]
+struct GuildCache {
+    /// Guilds stored along with their roles, voice channels and events.
+    stored: HashSet<Snowflake>,
+    /// The channels of each guild listed so far.
+    channels: HashMap<Snowflake, Vec<Channel>>,
+}
+
+impl GuildCache {
+    /// The channels of a guild, only requested the first time.
+    fn guild_channels(
+        &mut self,
+        client: &dyn ApiClient,
+        guild_id: Snowflake,
+    ) -> SimpleResult<&[Channel]> {
+        let channels = match self.channels.entry(guild_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(get_guild_channels(client, guild_id)?),
+        };
+        Ok(channels)
+    }
+
+    /// A channel from the guilds listed so far. Threads and channels of other guilds
+    /// aren't in any.
+    fn listed(&self, channel_id: Snowflake) -> Option<&Channel> {
+        self.channels
+            .values()
+            .flatten()
+            .find(|c| c.id == channel_id)
+    }
+
+    /// Fetches a channel given on the command line, unless it was already listed.
+    fn channel(
+        &mut self,
+        client: &dyn ApiClient,
+        channel_arg: &ChannelArg,
+    ) -> SimpleResult<Channel> {
+        match channel_arg {
+            ChannelArg::Id(channel_id) => match self.listed(*channel_id) {
+                Some(channel) => Ok(channel.clone()),
+                None => get_channel(client, *channel_id),
+            },
+            ChannelArg::Name { guild_id, name } => {
+                find_channel(self.guild_channels(client, *guild_id)?, *guild_id, name)
+            }
+        }
+    }
+}
+
+/// Finds a guild channel by name. A leading `#` is ignored and the match is case-insensitive.
+fn resolve_channel(
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+    name: &str,
+) -> SimpleResult<Channel> {
+    find_channel(&get_guild_channels(client, guild_id)?, guild_id, name)
+}
+
+/// Finds a channel by name among the channels of a guild, like `resolve_channel`.
+fn find_channel(channels: &[Channel], guild_id: Snowflake, name: &str) -> SimpleResult<Channel> {
+    let name = name.trim_start_matches('#');
+    let mut matches: Vec<Channel> = channels
+        .iter()
+        .filter(|c| {
+            c.name
+                .as_deref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .cloned()
+        .collect();
+
+    match matches.len() {
+        0 => Err(format!("No channel named #{} in guild {}", name, guild_id).into()),
+        1 => Ok(matches.remove(0)),
+        n => Err(format!(
+            "{} channels are named #{} in guild {}, use a channel ID instead",
+            n, name, guild_id
+        )
+        .into()),
+    }
+}
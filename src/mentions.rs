@@ -0,0 +1,49 @@
+use regex::{Captures, Regex};
+
+use std::collections::HashMap;
+
+use crate::SimpleResult;
+
+/// Replaces user, role and channel mention tokens with the names stored for them.
+/// Mentions of anything the database doesn't know are left as they are.
+pub struct MentionResolver {
+    users: HashMap<i64, String>,
+    roles: HashMap<i64, String>,
+    channels: HashMap<i64, String>,
+    pattern: Regex,
+}
+
+impl MentionResolver {
+    pub fn load(conn: &rusqlite::Connection) -> SimpleResult<Self> {
+        let names = |sql: &str| -> SimpleResult<HashMap<i64, String>> {
+            Ok(conn
+                .prepare(sql)?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?)
+        };
+
+        Ok(MentionResolver {
+            users: names("SELECT id, username FROM user")?,
+            roles: names("SELECT id, name FROM role")?,
+            channels: names("SELECT id, name FROM channel WHERE name IS NOT NULL")?,
+            pattern: Regex::new(r"<(@!?|@&|#)(\d+)>").unwrap(),
+        })
+    }
+
+    pub fn resolve(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, |caps: &Captures| {
+                let (names, prefix) = match &caps[1] {
+                    "@&" => (&self.roles, "@"),
+                    "#" => (&self.channels, "#"),
+                    _ => (&self.users, "@"),
+                };
+                match caps[2].parse().ok().and_then(|id: i64| names.get(&id)) {
+                    // The @everyone role is already named with its @.
+                    Some(name) => format!("{}{}", prefix, name.trim_start_matches('@')),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
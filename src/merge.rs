@@ -0,0 +1,196 @@
+use clap::Args;
+
+use crate::snowflake::Snowflake;
+use crate::{db, forget, SimpleResult};
+
+/// How many conflicting IDs are listed per table.
+const EXAMPLES: usize = 5;
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Database to merge into the one given by --db-path
+    source: String,
+}
+
+/// Rows both databases hold under the same ID but with different values. Each query
+/// returns the conflicting IDs; the destination's values are the ones kept.
+const CONFLICTS: &[(&str, &str)] = &[
+    (
+        "Messages with different content",
+        "SELECT m.id FROM message m JOIN src.message s ON s.id = m.id
+         WHERE s.content IS NOT m.content",
+    ),
+    (
+        "Users with different names",
+        "SELECT u.id FROM user u JOIN src.user s ON s.id = u.id
+         WHERE s.username IS NOT u.username OR s.discriminator IS NOT u.discriminator
+            OR s.global_name IS NOT u.global_name",
+    ),
+    (
+        "Channels with different names or topics",
+        "SELECT c.id FROM channel c JOIN src.channel s ON s.id = c.id
+         WHERE s.name IS NOT c.name OR s.topic IS NOT c.topic",
+    ),
+];
+
+/// Tables copied row by row, skipping IDs the destination already has. Parents come
+/// before the tables referencing them, and forgotten users first so both databases' are
+/// known before anything about them is merged.
+const TABLES: &[&str] = &[
+    "forgotten_user",
+    "guild",
+    "role",
+    "channel",
+    "permission_overwrite",
+    "scheduled_event",
+    "soundboard_sound",
+    "user",
+    "flagged_user",
+    "message",
+    "message_field",
+    "message_url",
+    "message_language",
+    "message_reaction",
+    "application",
+    "application_command",
+];
+
+pub fn run(db_path: &str, args: MergeArgs) -> SimpleResult<()> {
+    // The source is only read, so it has to exist and be at this build's schema version
+    // already, like the destination is once opened.
+    drop(db::open_read_only(&args.source)?);
+    let mut conn = db::open_db(db_path)?;
+    db::attach(&conn, &args.source, "src")?;
+
+    let broken_references: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_foreign_key_check(NULL, 'src')",
+        [],
+        |row| row.get(0),
+    )?;
+    if broken_references > 0 {
+        let err_msg = format!(
+            "{} has {} broken references, run `verify` on it before merging",
+            args.source, broken_references
+        );
+        return Err(err_msg.into());
+    }
+
+    let tx = conn.transaction()?;
+    for (name, sql) in CONFLICTS {
+        let ids: Vec<i64> = tx
+            .prepare(sql)?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        if !ids.is_empty() {
+            let examples: Vec<String> =
+                ids.iter().take(EXAMPLES).map(|id| id.to_string()).collect();
+            warn!(
+                "{}: {} (keeping existing values), e.g. {}",
+                name,
+                ids.len(),
+                examples.join(", ")
+            );
+        }
+    }
+
+    for table in TABLES {
+        let inserted = tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO main.{0} SELECT * FROM src.{0}",
+                table
+            ),
+            [],
+        )?;
+        info!("Merged {} new rows into {}", inserted, table);
+    }
+
+    let inserted = tx.execute(
+        "INSERT INTO guild_history (guild_id, captured_at, premium_tier, premium_subscription_count, features)
+         SELECT guild_id, captured_at, premium_tier, premium_subscription_count, features
+         FROM src.guild_history s
+         WHERE NOT EXISTS (
+            SELECT 1 FROM guild_history g WHERE g.guild_id = s.guild_id AND g.captured_at = s.captured_at)",
+        [],
+    )?;
+    info!("Merged {} new rows into guild_history", inserted);
+
+    let inserted = tx.execute(
+        "INSERT INTO user_history (user_id, replaced_at, username, discriminator, global_name)
+         SELECT user_id, replaced_at, username, discriminator, global_name
+         FROM src.user_history s
+         WHERE NOT EXISTS (
+            SELECT 1 FROM user_history u WHERE u.user_id = s.user_id AND u.replaced_at = s.replaced_at
+                AND u.username = s.username AND u.discriminator IS s.discriminator
+                AND u.global_name IS s.global_name)",
+        [],
+    )?;
+    info!("Merged {} new rows into user_history", inserted);
+
+    let inserted = tx.execute(
+        "INSERT INTO channel_history (channel_id, replaced_at, name, topic)
+         SELECT channel_id, replaced_at, name, topic
+         FROM src.channel_history s
+         WHERE NOT EXISTS (
+            SELECT 1 FROM channel_history c WHERE c.channel_id = s.channel_id
+                AND c.replaced_at = s.replaced_at AND c.name IS s.name AND c.topic IS s.topic)",
+        [],
+    )?;
+    info!("Merged {} new rows into channel_history", inserted);
+
+    let inserted = tx.execute(
+        "INSERT INTO message_revision (message_id, replaced_at, content, edited_timestamp)
+         SELECT message_id, replaced_at, content, edited_timestamp
+         FROM src.message_revision s
+         WHERE NOT EXISTS (
+            SELECT 1 FROM message_revision r WHERE r.message_id = s.message_id
+                AND r.replaced_at = s.replaced_at AND r.content = s.content)",
+        [],
+    )?;
+    info!("Merged {} new rows into message_revision", inserted);
+
+    // Case IDs are local to each database, so source cases are added as new cases here,
+    // skipping ones an earlier merge already brought over.
+    let cases: Vec<(i64, String, Option<String>, String)> = tx
+        .prepare(
+            "SELECT id, name, description, created_at FROM src.case_record s
+             WHERE NOT EXISTS (
+                SELECT 1 FROM case_record c WHERE c.name = s.name AND c.created_at = s.created_at)
+             ORDER BY id",
+        )?
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    for (id, name, description, created_at) in &cases {
+        tx.execute(
+            "INSERT INTO case_record (name, description, created_at) VALUES (?,?,?)",
+            rusqlite::params![name, description, created_at],
+        )?;
+        tx.execute(
+            "INSERT INTO case_message (case_id, message_id, note, added_at)
+             SELECT ?, message_id, note, added_at FROM src.case_message WHERE case_id = ?",
+            rusqlite::params![tx.last_insert_rowid(), id],
+        )?;
+    }
+    info!("Merged {} new cases", cases.len());
+
+    // Users forgotten in either database stay forgotten, so what the other one still held
+    // of them is erased the way it was when they were forgotten.
+    let forgotten: Vec<(Snowflake, bool)> = tx
+        .prepare("SELECT user_id, anonymized FROM forgotten_user")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    for (user_id, anonymized) in forgotten {
+        let erased = forget::erase(&tx, user_id, anonymized)?;
+        if erased.users + erased.messages + erased.mentions > 0 {
+            info!(
+                "Erased forgotten user {} again ({} Users, {} Messages, mentions removed from {} Messages)",
+                user_id, erased.users, erased.messages, erased.mentions
+            );
+        }
+    }
+    tx.commit()?;
+
+    conn.execute("DETACH DATABASE src", [])?;
+    Ok(())
+}
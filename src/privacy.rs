@@ -0,0 +1,83 @@
+use clap::Args;
+use rand::Rng;
+
+use crate::SimpleResult;
+
+/// Opt-in protections for aggregate statistics that leave the archive.
+#[derive(Debug, Clone, Default, Args)]
+pub struct PrivacyArgs {
+    /// Add Laplace noise with this privacy budget to published counts (smaller is more private)
+    #[clap(long)]
+    epsilon: Option<f64>,
+
+    /// Suppress published counts below this threshold
+    #[clap(long)]
+    suppress_below: Option<i64>,
+}
+
+impl PrivacyArgs {
+    /// Fails on an `--epsilon` no noise can be calibrated to.
+    pub fn check(&self) -> SimpleResult<()> {
+        match self.epsilon {
+            Some(epsilon) if !(epsilon > 0.0 && epsilon.is_finite()) => {
+                let err_msg = format!("--epsilon must be a positive number, got {}", epsilon);
+                Err(err_msg.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Renders a count published once for publication, applying noise first and then
+    /// suppression.
+    pub fn count(&self, n: i64) -> String {
+        self.show(self.noise(n))
+    }
+
+    /// Adds noise to a count. A count published more than once is noised once and the
+    /// result reused, as averaging independently noised copies would cancel the noise out.
+    ///
+    /// Noise is calibrated to a sensitivity of one, i.e. it hides the presence of any
+    /// single record being counted rather than everything a user contributed.
+    pub fn noise(&self, n: i64) -> i64 {
+        match self.epsilon {
+            Some(epsilon) => (n as f64 + laplace(1.0 / epsilon)).round().max(0.0) as i64,
+            None => n,
+        }
+    }
+
+    /// Renders a count that was already noised for publication, suppressing it if small.
+    pub fn show(&self, n: i64) -> String {
+        match self.suppress_below {
+            Some(threshold) if n < threshold => format!("<{}", threshold),
+            _ => n.to_string(),
+        }
+    }
+
+    /// A count that was already noised as drawn in a chart, where suppressed ones are zero.
+    pub fn plot(&self, n: i64) -> i64 {
+        match self.suppress_below {
+            Some(threshold) if n < threshold => 0,
+            _ => n,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.epsilon.is_some() || self.suppress_below.is_some()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(epsilon) = self.epsilon {
+            parts.push(format!("Laplace noise (epsilon = {})", epsilon));
+        }
+        if let Some(threshold) = self.suppress_below {
+            parts.push(format!("counts below {} suppressed", threshold));
+        }
+        parts.join(", ")
+    }
+}
+
+fn laplace(scale: f64) -> f64 {
+    let u: f64 = rand::thread_rng().gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::snowflake::Snowflake;
+
+/// Only tracked while something shows it, i.e. with `--tui`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Every channel of the run, in command line order.
+static CHANNELS: Mutex<Vec<ChannelProgress>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// How far the scrape of one channel has got.
+#[cfg_attr(not(all(feature = "tui", unix)), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct ChannelProgress {
+    pub id: Snowflake,
+    pub name: Option<String>,
+    pub status: Status,
+    /// Messages fetched so far this run, stored or not.
+    pub messages: u64,
+    /// The oldest message reached so far.
+    pub oldest: Option<Snowflake>,
+}
+
+#[cfg_attr(not(all(feature = "tui", unix)), allow(dead_code))]
+impl ChannelProgress {
+    /// The share of the channel's lifetime walked back from now, from 0 to 1.
+    pub fn fraction(&self, now: i64) -> f64 {
+        match (self.status, self.oldest) {
+            (Status::Done, _) => 1.0,
+            (_, None) => 0.0,
+            (_, Some(oldest)) => {
+                let lifetime = (now - self.id.created_at_unix()).max(1);
+                ((now - oldest.created_at_unix()) as f64 / lifetime as f64).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn queue(id: Snowflake, name: Option<String>) {
+    if ENABLED.load(Ordering::Relaxed) {
+        CHANNELS.lock().unwrap().push(ChannelProgress {
+            id,
+            name,
+            status: Status::Queued,
+            messages: 0,
+            oldest: None,
+        });
+    }
+}
+
+pub fn start(id: Snowflake) {
+    update(id, |channel| channel.status = Status::Running);
+}
+
+/// Records the `messages` fetched so far and the `oldest` one reached.
+pub fn page(id: Snowflake, messages: u64, oldest: Option<Snowflake>) {
+    update(id, |channel| {
+        channel.messages = messages;
+        channel.oldest = oldest.or(channel.oldest);
+    });
+}
+
+pub fn finish(id: Snowflake, failed: bool) {
+    update(id, |channel| {
+        channel.status = if failed { Status::Failed } else { Status::Done }
+    });
+}
+
+#[cfg_attr(not(all(feature = "tui", unix)), allow(dead_code))]
+pub fn channels() -> Vec<ChannelProgress> {
+    CHANNELS.lock().unwrap().clone()
+}
+
+fn update(id: Snowflake, f: impl FnOnce(&mut ChannelProgress)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(channel) = CHANNELS.lock().unwrap().iter_mut().find(|c| c.id == id) {
+        f(channel);
+    }
+}
@@ -0,0 +1,100 @@
+use clap::Args;
+use rusqlite::ToSql;
+
+use crate::cutoff::Cutoff;
+use crate::snowflake::Snowflake;
+use crate::{db, values_placeholders, SimpleResult};
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    /// Delete messages older than this age (e.g. 365d) or date (e.g. 2021-01-01)
+    #[clap(long)]
+    older_than: Cutoff,
+
+    /// Only delete messages of this channel, can be given several times
+    #[clap(long = "channel", value_name = "CHANNEL_ID")]
+    channels: Vec<Snowflake>,
+
+    /// Count what would be deleted without deleting anything
+    #[clap(long)]
+    dry_run: bool,
+}
+
+/// Deletes old messages, with what was stored about them, and the users left without
+/// any messages. Messages that belong to a case are kept so cases remain complete. All
+/// of it happens in one transaction, which a dry run rolls back.
+pub fn run(db_path: &str, args: PruneArgs) -> SimpleResult<()> {
+    let mut conn = db::open_db(db_path)?;
+    let tx = conn.transaction()?;
+
+    let mut sql = String::from(
+        "CREATE TEMP TABLE prune_message AS
+         SELECT id FROM message WHERE created_at_unix < ?",
+    );
+    let mut params: Vec<&dyn ToSql> = vec![&args.older_than.0];
+    if !args.channels.is_empty() {
+        sql += &format!(
+            " AND channel_id IN {}",
+            values_placeholders(1, args.channels.len())
+        );
+        params.extend(args.channels.iter().map(|id| id as &dyn ToSql));
+    }
+    tx.execute(&sql, params.as_slice())?;
+    let in_cases = tx.execute(
+        "DELETE FROM prune_message WHERE id IN (SELECT message_id FROM case_message)",
+        [],
+    )?;
+
+    for table in [
+        "message_field",
+        "message_url",
+        "message_language",
+        "message_reaction",
+        "message_revision",
+    ] {
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE message_id IN (SELECT id FROM prune_message)",
+                table
+            ),
+            [],
+        )?;
+    }
+    let messages = tx.execute(
+        "DELETE FROM message WHERE id IN (SELECT id FROM prune_message)",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TEMP TABLE prune_user AS
+         SELECT id FROM user WHERE id NOT IN (
+            SELECT author_id FROM message WHERE author_id IS NOT NULL)",
+        [],
+    )?;
+    for table in ["user_history", "flagged_user"] {
+        tx.execute(
+            &format!(
+                "DELETE FROM {} WHERE user_id IN (SELECT id FROM prune_user)",
+                table
+            ),
+            [],
+        )?;
+    }
+    let users = tx.execute(
+        "DELETE FROM user WHERE id IN (SELECT id FROM prune_user)",
+        [],
+    )?;
+    tx.execute_batch("DROP TABLE prune_message; DROP TABLE prune_user;")?;
+
+    if in_cases > 0 {
+        info!("Keeping {} old Messages that belong to cases", in_cases);
+    }
+    if args.dry_run {
+        tx.rollback()?;
+        info!("Would delete {} Messages and {} Users", messages, users);
+    } else {
+        tx.commit()?;
+        info!("Deleted {} Messages and {} Users", messages, users);
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use clap::{ArgEnum, Args};
+use rusqlite::types::Value;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use std::io::Write;
+
+use crate::SimpleResult;
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum QueryFormat {
+    /// Aligned columns for reading in a terminal
+    Table,
+    /// Comma separated values with a header row
+    Csv,
+    /// A JSON array with one object per row
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// The SQL to run, e.g. "SELECT name FROM channel"
+    sql: String,
+
+    #[clap(long, arg_enum, default_value = "table")]
+    format: QueryFormat,
+}
+
+/// Runs a statement against a read-only connection, so nothing typed here can change the archive.
+pub fn run(conn: &rusqlite::Connection, args: QueryArgs) -> SimpleResult<()> {
+    let mut stmt = conn.prepare(&args.sql)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let rows = stmt
+        .query_map([], |row| {
+            (0..columns.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<Result<Vec<_>, _>>()
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        QueryFormat::Table => write_table(&mut out, &columns, &rows)?,
+        QueryFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(&columns)?;
+            for row in &rows {
+                writer.write_record(row.iter().map(|value| text(value, "")))?;
+            }
+            writer.flush()?;
+        }
+        QueryFormat::Json => {
+            let rows: Vec<JsonRow> = rows
+                .iter()
+                .map(|values| JsonRow {
+                    columns: &columns,
+                    values,
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut out, &rows)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_table(out: &mut impl Write, columns: &[String], rows: &[Vec<Value>]) -> SimpleResult<()> {
+    // Line breaks would break the columns apart, so they are shown escaped.
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| text(value, "NULL").replace('\n', "\\n"))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(columns[i].chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    writeln!(out, "{}", line(columns))?;
+    let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+    writeln!(out, "{}", rule.join("-+-"))?;
+    for row in &cells {
+        writeln!(out, "{}", line(row))?;
+    }
+    writeln!(
+        out,
+        "({} row{})",
+        cells.len(),
+        if cells.len() == 1 { "" } else { "s" }
+    )?;
+    Ok(())
+}
+
+fn text(value: &Value, null: &str) -> String {
+    match value {
+        Value::Null => null.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => s.clone(),
+        Value::Blob(bytes) => hex(bytes),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A row as a JSON object, keeping the columns in the order the query selected them.
+struct JsonRow<'a> {
+    columns: &'a [String],
+    values: &'a [Value],
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (column, value) in self.columns.iter().zip(self.values) {
+            match value {
+                Value::Null => map.serialize_entry(column, &None::<()>)?,
+                Value::Integer(i) => map.serialize_entry(column, i)?,
+                Value::Real(f) => map.serialize_entry(column, f)?,
+                Value::Text(s) => map.serialize_entry(column, s)?,
+                Value::Blob(bytes) => map.serialize_entry(column, &hex(bytes))?,
+            }
+        }
+        map.end()
+    }
+}
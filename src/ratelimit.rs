@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::api::ApiResponse;
+use crate::exit::{self, Failure};
+use crate::{client, summary, SimpleResult, BASE_URL};
+
+/// Discord's rate limits as last reported, shared by every worker so one worker's
+/// requests count against the quota the others see. Each token has limits of its own.
+#[derive(Default)]
+struct Limits {
+    /// The bucket of each route, learned from `X-RateLimit-Bucket`.
+    buckets: HashMap<String, String>,
+    /// Requests left in each bucket, keyed by token, bucket and major parameter, until
+    /// it resets.
+    quotas: HashMap<String, Quota>,
+    /// Nothing may be sent with a token before this after a global rate limit.
+    global_until: HashMap<usize, Instant>,
+}
+
+struct Quota {
+    remaining: u64,
+    reset_at: Instant,
+}
+
+impl Limits {
+    /// Counts a request to `route` with `token` against its bucket, or returns how long
+    /// to wait until the token may send it.
+    fn reserve(&mut self, token: usize, route: &str, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.global_until.get(&token) {
+            if *until > now {
+                return Some(*until - now);
+            }
+        }
+        let bucket = self
+            .buckets
+            .get(route)
+            .map(|b| quota_key(token, b, route))?;
+        match self.quotas.get_mut(&bucket) {
+            Some(quota) if quota.reset_at <= now => {
+                // The next response reports the new quota.
+                self.quotas.remove(&bucket);
+                None
+            }
+            Some(quota) if quota.remaining == 0 => Some(quota.reset_at - now),
+            Some(quota) => {
+                quota.remaining -= 1;
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+fn limits() -> MutexGuard<'static, Limits> {
+    static LIMITS: OnceLock<Mutex<Limits>> = OnceLock::new();
+    LIMITS.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Blocks until some token may send a request to `url` within the known rate limits,
+/// then counts it against its bucket and returns the token. Tokens take turns when
+/// several are free.
+pub fn wait(url: &str) -> SimpleResult<usize> {
+    static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+    let route = route(url);
+    let tokens = client::token_count();
+    let first = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    loop {
+        let delay = {
+            let mut limits = limits();
+            let now = Instant::now();
+            let mut shortest: Option<Duration> = None;
+            for token in (0..tokens).map(|i| (first + i) % tokens) {
+                if !client::is_usable(token) {
+                    continue;
+                }
+                match limits.reserve(token, &route, now) {
+                    Some(delay) => shortest = Some(shortest.map_or(delay, |d| d.min(delay))),
+                    None => return Ok(token),
+                }
+            }
+            match shortest {
+                Some(delay) => delay,
+                None => return Err(Failure::new(exit::AUTH, "Discord rejected every token").into()),
+            }
+        };
+        summary::add_rate_limit_wait(delay);
+        std::thread::sleep(delay);
+    }
+}
+
+/// Records the quota reported by the response to a request to `url` sent with `token`.
+pub fn update(url: &str, token: usize, res: &ApiResponse) {
+    let header = |name: &str| res.header(name);
+    let bucket = match header("X-RateLimit-Bucket") {
+        Some(bucket) => bucket.to_string(),
+        None => return,
+    };
+    let remaining = header("X-RateLimit-Remaining").and_then(|v| v.parse().ok());
+    let reset_after = header("X-RateLimit-Reset-After").and_then(|v| v.parse::<f64>().ok());
+
+    let route = route(url);
+    let mut limits = limits();
+    if let (Some(remaining), Some(reset_after)) = (remaining, reset_after) {
+        limits.quotas.insert(
+            quota_key(token, &bucket, &route),
+            Quota {
+                remaining,
+                reset_at: Instant::now() + Duration::from_secs_f64(reset_after),
+            },
+        );
+    }
+    limits.buckets.insert(route, bucket);
+}
+
+/// Holds back requests with `token` after a 429: every request when the limit was
+/// global, otherwise those in the bucket of `url`.
+pub fn limited(url: &str, token: usize, retry_after: f64, global: bool) {
+    let until = Instant::now() + Duration::from_secs_f64(retry_after);
+    let route = route(url);
+    let mut limits = limits();
+    if global {
+        limits.global_until.insert(token, until);
+        return;
+    }
+    // Without a known bucket the route stands in for it.
+    let bucket = limits
+        .buckets
+        .entry(route.clone())
+        .or_insert_with(|| route.clone())
+        .clone();
+    limits.quotas.insert(
+        quota_key(token, &bucket, &route),
+        Quota {
+            remaining: 0,
+            reset_at: until,
+        },
+    );
+}
+
+/// The path of `url` without its query, e.g. `/channels/1/messages`.
+fn route(url: &str) -> String {
+    let path = url.strip_prefix(BASE_URL).unwrap_or(url);
+    path.split('?').next().unwrap_or_default().to_string()
+}
+
+/// Buckets are shared by routes, but limits apply separately to each token and to each
+/// channel or guild.
+fn quota_key(token: usize, bucket: &str, route: &str) -> String {
+    let segments: Vec<&str> = route.trim_start_matches('/').split('/').collect();
+    let major = match segments.as_slice() {
+        ["channels" | "guilds" | "webhooks", id, ..] => format!("{}/{}", segments[0], id),
+        _ => String::new(),
+    };
+    format!("{}:{}:{}", token, bucket, major)
+}
@@ -0,0 +1,58 @@
+use rusqlite::ToSql;
+
+use crate::cutoff::Cutoff;
+use crate::serve::{self, MESSAGE_COLUMNS};
+use crate::snowflake::Snowflake;
+
+pub use crate::export::ExportMessage as StoredMessage;
+pub use crate::serve::ChannelSummary;
+
+/// Messages sent from `after` up to but excluding `before`, oldest first, only those of
+/// `channel_id` if given.
+pub fn messages_in_range(
+    conn: &rusqlite::Connection,
+    after: Cutoff,
+    before: Cutoff,
+    channel_id: Option<Snowflake>,
+) -> rusqlite::Result<Vec<StoredMessage>> {
+    let mut sql = format!(
+        "SELECT {} WHERE m.created_at_unix >= ? AND m.created_at_unix < ?",
+        MESSAGE_COLUMNS
+    );
+    let mut params: Vec<&dyn ToSql> = vec![&after.0, &before.0];
+    if let Some(channel_id) = &channel_id {
+        sql += " AND m.channel_id = ?";
+        params.push(channel_id);
+    }
+    sql += " ORDER BY m.id";
+    conn.prepare(&sql)?
+        .query_map(params.as_slice(), serve::message_row)?
+        .collect()
+}
+
+/// Every stored message of a user, oldest first.
+pub fn messages_by_author(
+    conn: &rusqlite::Connection,
+    author_id: Snowflake,
+) -> rusqlite::Result<Vec<StoredMessage>> {
+    let sql = format!(
+        "SELECT {} WHERE m.author_id = ? ORDER BY m.id",
+        MESSAGE_COLUMNS
+    );
+    conn.prepare(&sql)?
+        .query_map([author_id], serve::message_row)?
+        .collect()
+}
+
+/// A channel with its guild and message statistics, `None` if it isn't stored.
+pub fn channel_summary(
+    conn: &rusqlite::Connection,
+    channel_id: Snowflake,
+) -> rusqlite::Result<Option<ChannelSummary>> {
+    Ok(serve::channel_summaries(conn, "c.id = ?", [channel_id])?.pop())
+}
+
+/// Every stored channel with its guild and message statistics, by guild and name.
+pub fn channel_summaries(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<ChannelSummary>> {
+    serve::channel_summaries(conn, "1 = 1", [])
+}
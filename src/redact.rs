@@ -0,0 +1,66 @@
+use clap::Args;
+use regex::{NoExpand, Regex};
+
+/// Built-in patterns enabled by `--redact`, with the label their matches are replaced by.
+const PATTERNS: &[(&str, &str)] = &[
+    ("EMAIL", r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b"),
+    (
+        "PHONE",
+        r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)|\b\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b",
+    ),
+    (
+        "ADDRESS",
+        r"(?i)\b\d{1,6}\s+(?:[a-z0-9.'-]+\s+){1,4}(?:street|st|avenue|ave|road|rd|boulevard|blvd|lane|ln|drive|dr|court|ct|way|place|pl|terrace|parkway|pkwy)\b\.?",
+    ),
+];
+
+#[derive(Debug, Args)]
+pub struct RedactArgs {
+    /// Replace email addresses, phone numbers and street addresses in message content
+    #[clap(long, global = true)]
+    pub redact: bool,
+
+    /// Also replace matches of this regular expression, can be given several times
+    #[clap(long = "redact-pattern", global = true, value_name = "REGEX")]
+    pub redact_patterns: Vec<Regex>,
+}
+
+impl RedactArgs {
+    /// The redactor the flags describe, or `None` when nothing is to be redacted.
+    pub fn redactor(&self) -> Option<Redactor> {
+        let mut rules: Vec<(String, Regex)> = Vec::new();
+        if self.redact {
+            for (label, pattern) in PATTERNS {
+                rules.push((
+                    format!("[REDACTED {}]", label),
+                    Regex::new(pattern).unwrap(),
+                ));
+            }
+        }
+        for pattern in &self.redact_patterns {
+            rules.push(("[REDACTED]".to_string(), pattern.clone()));
+        }
+
+        if rules.is_empty() {
+            None
+        } else {
+            Some(Redactor { rules })
+        }
+    }
+}
+
+pub struct Redactor {
+    rules: Vec<(String, Regex)>,
+}
+
+impl Redactor {
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (replacement, pattern) in &self.rules {
+            text = pattern
+                .replace_all(&text, NoExpand(replacement))
+                .into_owned();
+        }
+        text
+    }
+}
@@ -0,0 +1,154 @@
+use crate::anonymize::Anonymizer;
+use crate::api::ApiClient;
+use crate::cutoff::Cutoff;
+use crate::redact::Redactor;
+use crate::snowflake::Snowflake;
+use crate::{enrich, forget, interrupt, summary, Message, SimpleResult};
+
+/// Walks the channel back to `since` again, storing what's new, updating stored messages
+/// that were edited or had their reactions change, and marking those no longer there
+/// as deleted. Each changed message counts as updated in `summary`.
+pub fn run(
+    conn: &mut rusqlite::Connection,
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    since: Cutoff,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+    summary: &mut summary::ChannelSummary,
+) -> SimpleResult<()> {
+    let oldest = Snowflake::first_at_unix(since.0);
+    info!(
+        "Refreshing Messages of channel {} from {} on",
+        channel_id, oldest
+    );
+
+    let mut before = None;
+    loop {
+        let mut messages = crate::clean_page(
+            channel_id,
+            crate::get_messages(client, channel_id, before)?,
+            before,
+        );
+        let reached_end = messages.last().is_none_or(|m| m.id < oldest);
+        messages.retain(|m| m.id >= oldest);
+
+        // Every stored message between this page's and the previous one's is gone.
+        let after = match (reached_end, messages.last()) {
+            (false, Some(last)) => last.id,
+            _ => oldest,
+        };
+        let deleted = mark_deleted(conn, channel_id, after, before, &messages)?;
+
+        before = messages.last().map(|m| m.id).or(before);
+
+        let (mut stored, mut new) = (Vec::new(), Vec::new());
+        {
+            let mut exists = conn.prepare_cached("SELECT 1 FROM message WHERE id = ?")?;
+            for message in messages {
+                if exists.exists([message.id])? {
+                    stored.push(message);
+                } else {
+                    new.push(message);
+                }
+            }
+        }
+
+        let mut stored = crate::protect_page(stored, anonymizer, redactor);
+        forget::scrub(conn, &mut stored)?;
+        let changed = update_messages(conn, stored)?;
+        summary.updated += (changed + deleted) as u64;
+        if !new.is_empty() {
+            let fetched = new.len() as u64;
+            let inserted = crate::insert_page(conn, new, anonymizer, redactor)? as u64;
+            summary.new += inserted;
+            summary.skipped += fetched - inserted;
+        }
+
+        if reached_end {
+            return Ok(());
+        }
+        if interrupt::requested() {
+            info!("Stopped refreshing channel {}", channel_id);
+            return Ok(());
+        }
+    }
+}
+
+/// Marks the stored messages of the channel from `after` up to but not including
+/// `before` that aren't on `page` as deleted, returning how many were newly marked.
+fn mark_deleted(
+    conn: &rusqlite::Connection,
+    channel_id: Snowflake,
+    after: Snowflake,
+    before: Option<Snowflake>,
+    page: &[Message],
+) -> SimpleResult<usize> {
+    let mut sql = String::from(
+        "UPDATE message SET deleted = 1
+         WHERE channel_id = ? AND id >= ? AND id < ? AND NOT deleted",
+    );
+    if !page.is_empty() {
+        sql += &format!(
+            " AND id NOT IN {}",
+            crate::values_placeholders(1, page.len())
+        );
+    }
+    let before = before.unwrap_or(Snowflake(i64::MAX));
+    let mut params: Vec<&dyn rusqlite::ToSql> = vec![&channel_id, &after, &before];
+    params.extend(page.iter().map(|m| &m.id as &dyn rusqlite::ToSql));
+    Ok(conn.execute(&sql, params.as_slice())?)
+}
+
+/// Brings stored messages up to date with how they were fetched, returning how many of
+/// them had changed. The content of edited messages is kept in `message_revision`, and
+/// changed messages are enriched again.
+fn update_messages(conn: &mut rusqlite::Connection, messages: Vec<Message>) -> SimpleResult<usize> {
+    let tx = conn.transaction()?;
+    let mut changed = Vec::new();
+    {
+        let mut revision = tx.prepare_cached(
+            "INSERT INTO message_revision (message_id, content, edited_timestamp)
+             SELECT id, content, edited_timestamp FROM message WHERE id = ? AND content IS NOT ?",
+        )?;
+        let mut update = tx.prepare_cached(
+            "UPDATE message SET content = ?, edited_timestamp = ?, flags = ?, deleted = 0
+             WHERE id = ? AND (content IS NOT ? OR edited_timestamp IS NOT ? OR flags IS NOT ?
+                OR deleted)",
+        )?;
+        let mut reactions = tx.prepare_cached(
+            "SELECT emoji, count, burst_count FROM message_reaction WHERE message_id = ?
+             ORDER BY emoji",
+        )?;
+        for message in messages {
+            revision.execute(rusqlite::params![message.id, message.content])?;
+            let edited = update.execute(rusqlite::params![
+                message.content,
+                message.edited_timestamp,
+                message.flags,
+                message.id,
+                message.content,
+                message.edited_timestamp,
+                message.flags
+            ])? > 0;
+            let stored: Vec<(String, i64, i64)> = reactions
+                .query_map([message.id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<Result<_, _>>()?;
+            let mut fetched: Vec<(String, i64, i64)> = message
+                .reactions
+                .iter()
+                .map(|r| (r.emoji.key(), r.count, r.burst_count()))
+                .collect();
+            fetched.sort();
+            if edited || stored != fetched {
+                changed.push(message);
+            }
+        }
+    }
+    crate::replace_reactions(&tx, &changed)?;
+    tx.commit()?;
+    enrich::apply(conn, &changed)?;
+    Ok(changed.len())
+}
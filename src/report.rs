@@ -0,0 +1,410 @@
+use clap::{ArgGroup, Args};
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::privacy::PrivacyArgs;
+use crate::{timezone, SimpleResult};
+
+#[derive(Debug, Args)]
+#[clap(group(ArgGroup::new("kind").required(true).args(&["inventory", "permissions"])))]
+pub struct ReportArgs {
+    /// Describe which categories of data the archive holds
+    #[clap(long)]
+    inventory: bool,
+
+    /// Tabulate which roles can view, send and read history in each channel
+    #[clap(long)]
+    permissions: bool,
+
+    /// Output file, defaults to stdout
+    #[clap(short, long)]
+    output: Option<String>,
+
+    #[clap(flatten)]
+    privacy: PrivacyArgs,
+}
+
+pub fn run(conn: &rusqlite::Connection, db_path: &str, args: ReportArgs) -> SimpleResult<()> {
+    let report = if args.permissions {
+        permissions_report(conn)?
+    } else {
+        inventory_report(conn, db_path, &args.privacy)?
+    };
+
+    match args.output {
+        Some(path) => std::fs::write(path, report)?,
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
+
+fn count(conn: &rusqlite::Connection, sql: &str) -> SimpleResult<i64> {
+    Ok(conn.query_row(sql, [], |row| row.get(0))?)
+}
+
+fn inventory_report(
+    conn: &rusqlite::Connection,
+    db_path: &str,
+    privacy: &PrivacyArgs,
+) -> SimpleResult<String> {
+    privacy.check()?;
+    let file_size = std::fs::metadata(db_path)?.len();
+    let schema_version = count(conn, "SELECT MAX(version) FROM schema_version")?;
+    let generated_at: String =
+        conn.query_row("SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')", [], |row| {
+            row.get(0)
+        })?;
+
+    let channels = count(conn, "SELECT COUNT(*) FROM channel")?;
+    let guilds = count(conn, "SELECT COUNT(DISTINCT guild_id) FROM channel")?;
+    let direct_channels = count(conn, "SELECT COUNT(*) FROM channel WHERE guild_id IS NULL")?;
+    let channel_history = count(conn, "SELECT COUNT(*) FROM channel_history")?;
+    let messages = count(conn, "SELECT COUNT(*) FROM message")?;
+    let revisions = count(conn, "SELECT COUNT(*) FROM message_revision")?;
+    let edited_messages = count(
+        conn,
+        "SELECT COUNT(DISTINCT message_id) FROM message_revision",
+    )?;
+    let users = count(conn, "SELECT COUNT(*) FROM user")?;
+    let earlier_names = count(conn, "SELECT COUNT(*) FROM user_history")?;
+    let renamed_users = count(conn, "SELECT COUNT(DISTINCT user_id) FROM user_history")?;
+    let cases = count(conn, "SELECT COUNT(*) FROM case_record")?;
+    let case_messages = count(conn, "SELECT COUNT(*) FROM case_message")?;
+    let case_notes = count(
+        conn,
+        "SELECT COUNT(*) FROM case_message WHERE note IS NOT NULL",
+    )?;
+    let (oldest, newest): (Option<String>, Option<String>) = conn.query_row(
+        "SELECT datetime(local(MIN(created_at_unix)), 'unixepoch'), datetime(local(MAX(created_at_unix)), 'unixepoch')
+         FROM message",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let email_like = count(
+        conn,
+        "SELECT COUNT(*) FROM message WHERE content LIKE '%_@_%._%'",
+    )?;
+    let links = count(
+        conn,
+        "SELECT COUNT(*) FROM message WHERE content LIKE '%http://%' OR content LIKE '%https://%'",
+    )?;
+    // These are published twice, in the table and among the flags, so they're noised once.
+    let [users, earlier_names, renamed_users, revisions, edited_messages] = [
+        users,
+        earlier_names,
+        renamed_users,
+        revisions,
+        edited_messages,
+    ]
+    .map(|n| privacy.noise(n));
+
+    let mut report = String::new();
+    writeln!(report, "# Archive Inventory")?;
+    writeln!(report)?;
+    writeln!(report, "- Database: {}", db_path)?;
+    writeln!(report, "- File size: {} bytes", file_size)?;
+    writeln!(report, "- Schema version: {}", schema_version)?;
+    writeln!(report, "- Generated: {}", timezone::local(&generated_at))?;
+    if privacy.is_enabled() {
+        writeln!(report, "- Published counts: {}", privacy.describe())?;
+    }
+
+    writeln!(report)?;
+    writeln!(report, "## Data Categories")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "| Category | Records | Fields stored | Personal data |"
+    )?;
+    writeln!(report, "|---|---|---|---|")?;
+    writeln!(
+        report,
+        "| Channels | {} ({} guilds, {} direct, {} earlier names or topics) | ID, guild ID, name, topic, earlier names and topics with when they were replaced | No |",
+        privacy.count(channels),
+        privacy.count(guilds),
+        privacy.count(direct_channels),
+        privacy.count(channel_history)
+    )?;
+    writeln!(
+        report,
+        "| Users | {} | ID, username, discriminator, display name | Yes: account identifiers |",
+        privacy.show(users)
+    )?;
+    writeln!(
+        report,
+        "| Earlier user names | {} ({} users) | User, username, discriminator, display name, when replaced | Yes: former account identifiers |",
+        privacy.show(earlier_names),
+        privacy.show(renamed_users)
+    )?;
+    writeln!(
+        report,
+        "| Messages | {} | ID, channel, author, content, timestamp | Yes: authored free text |",
+        privacy.count(messages)
+    )?;
+    writeln!(
+        report,
+        "| Message revisions | {} ({} messages) | Message, earlier content, edit time, when replaced | Yes: authored free text, including text later removed |",
+        privacy.show(revisions),
+        privacy.show(edited_messages)
+    )?;
+    writeln!(
+        report,
+        "| Media | 0 | Not archived: attachments and embeds are not stored | No |"
+    )?;
+    writeln!(
+        report,
+        "| Moderation cases | {} ({} messages, {} notes) | Name, description, notes | Yes: moderator notes about users |",
+        privacy.count(cases),
+        privacy.count(case_messages),
+        privacy.count(case_notes)
+    )?;
+
+    writeln!(report)?;
+    writeln!(report, "## Date Range")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "Messages from {} to {} ({}).",
+        oldest.unwrap_or_else(|| "-".to_string()),
+        newest.unwrap_or_else(|| "-".to_string()),
+        timezone::name()
+    )?;
+
+    writeln!(report)?;
+    writeln!(report, "## Channels")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "| Guild | Channel | Messages | Authors | From | To |"
+    )?;
+    writeln!(report, "|---|---|---|---|---|---|")?;
+    let mut stmt = conn.prepare(
+        "SELECT c.guild_id, c.name, COUNT(m.id), COUNT(DISTINCT m.author_id),
+                datetime(local(MIN(m.created_at_unix)), 'unixepoch'), datetime(local(MAX(m.created_at_unix)), 'unixepoch')
+         FROM channel c
+         LEFT JOIN message m ON m.channel_id = c.id
+         GROUP BY c.id
+         ORDER BY c.guild_id, c.name",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        writeln!(
+            report,
+            "| {} | {} | {} | {} | {} | {} |",
+            row.get::<_, Option<i64>>(0)?
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "Direct".to_string()),
+            row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            privacy.count(row.get(2)?),
+            privacy.count(row.get(3)?),
+            row.get::<_, Option<String>>(4)?
+                .unwrap_or_else(|| "-".to_string()),
+            row.get::<_, Option<String>>(5)?
+                .unwrap_or_else(|| "-".to_string())
+        )?;
+    }
+
+    writeln!(report)?;
+    writeln!(report, "## Personal Data Flags")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "- Usernames and user IDs of {} users are stored in clear text.",
+        privacy.show(users)
+    )?;
+    writeln!(
+        report,
+        "- {} earlier names of {} users are kept after they changed them.",
+        privacy.show(earlier_names),
+        privacy.show(renamed_users)
+    )?;
+    writeln!(
+        report,
+        "- {} messages contain text resembling an email address.",
+        privacy.count(email_like)
+    )?;
+    writeln!(report, "- {} messages contain links.", privacy.count(links))?;
+    writeln!(
+        report,
+        "- {} earlier versions of {} edited messages are kept, including text their authors removed.",
+        privacy.show(revisions),
+        privacy.show(edited_messages)
+    )?;
+    writeln!(
+        report,
+        "- Message content is stored verbatim and may contain further personal data."
+    )?;
+
+    Ok(report)
+}
+
+const ADMINISTRATOR: i64 = 1 << 3;
+const VIEW_CHANNEL: i64 = 1 << 10;
+const SEND_MESSAGES: i64 = 1 << 11;
+const READ_MESSAGE_HISTORY: i64 = 1 << 16;
+
+/// Flags of the three permissions shown in the report, e.g. `V S -`.
+fn permission_flags(perms: i64) -> String {
+    if perms & VIEW_CHANNEL == 0 {
+        return "- - -".to_string();
+    }
+    format!(
+        "V {} {}",
+        if perms & SEND_MESSAGES != 0 { "S" } else { "-" },
+        if perms & READ_MESSAGE_HISTORY != 0 {
+            "H"
+        } else {
+            "-"
+        }
+    )
+}
+
+/// Names of the reported permissions set in an allow or deny mask.
+fn permission_names(mask: i64) -> String {
+    let names: Vec<&str> = [
+        (VIEW_CHANNEL, "view"),
+        (SEND_MESSAGES, "send"),
+        (READ_MESSAGE_HISTORY, "history"),
+    ]
+    .iter()
+    .filter(|(bit, _)| mask & bit != 0)
+    .map(|(_, name)| *name)
+    .collect();
+
+    if names.is_empty() {
+        "nothing".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+fn apply_overwrite(perms: i64, overwrite: Option<&(i64, i64)>) -> i64 {
+    match overwrite {
+        Some((allow, deny)) => (perms & !deny) | allow,
+        None => perms,
+    }
+}
+
+fn permissions_report(conn: &rusqlite::Connection) -> SimpleResult<String> {
+    let mut report = String::new();
+    writeln!(report, "# Channel Permissions")?;
+    writeln!(report)?;
+    writeln!(
+        report,
+        "Effective permissions of each role, from the stored role permissions and channel overwrites:"
+    )?;
+    writeln!(
+        report,
+        "`V` View Channel, `S` Send Messages, `H` Read Message History."
+    )?;
+
+    let guilds: Vec<(i64, String)> = conn
+        .prepare("SELECT id, name FROM guild ORDER BY name")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for (guild_id, guild_name) in guilds {
+        // The @everyone role shares its ID with the guild.
+        let roles: Vec<(i64, String, i64)> = conn
+            .prepare(
+                "SELECT id, name, permissions FROM role WHERE guild_id = ? ORDER BY position DESC",
+            )?
+            .query_map([guild_id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        let everyone = roles
+            .iter()
+            .find(|(id, _, _)| *id == guild_id)
+            .map_or(0, |(_, _, perms)| *perms);
+
+        let channels: Vec<(i64, String)> = conn
+            .prepare("SELECT id, name FROM channel WHERE guild_id = ? ORDER BY name")?
+            .query_map([guild_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let mut overwrites: HashMap<i64, HashMap<i64, (i64, i64)>> = HashMap::new();
+        let mut member_overwrites = Vec::new();
+        let mut stmt = conn.prepare(
+            "SELECT o.channel_id, o.target_id, o.type, o.allow, o.deny, c.name, u.username
+             FROM permission_overwrite o
+             JOIN channel c ON c.id = o.channel_id
+             LEFT JOIN user u ON u.id = o.target_id
+             WHERE c.guild_id = ?",
+        )?;
+        let mut rows = stmt.query([guild_id])?;
+        while let Some(row) = rows.next()? {
+            let (channel_id, target_id, kind, allow, deny): (i64, i64, i64, i64, i64) = (
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            );
+            if kind == 0 {
+                overwrites
+                    .entry(channel_id)
+                    .or_default()
+                    .insert(target_id, (allow, deny));
+            } else {
+                member_overwrites.push((
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<String>>(6)?
+                        .unwrap_or_else(|| target_id.to_string()),
+                    allow,
+                    deny,
+                ));
+            }
+        }
+
+        writeln!(report)?;
+        writeln!(report, "## {} ({})", guild_name, guild_id)?;
+        writeln!(report)?;
+        let header: Vec<String> = channels
+            .iter()
+            .map(|(_, name)| format!("#{}", name))
+            .collect();
+        writeln!(report, "| Role | {} |", header.join(" | "))?;
+        writeln!(report, "|---|{}", "---|".repeat(channels.len()))?;
+        for (role_id, role_name, role_perms) in &roles {
+            let mut cells = Vec::new();
+            for (channel_id, _) in &channels {
+                let base = everyone | role_perms;
+                let perms = if base & ADMINISTRATOR != 0 {
+                    !0
+                } else {
+                    let channel_overwrites = overwrites.get(channel_id);
+                    let perms =
+                        apply_overwrite(base, channel_overwrites.and_then(|o| o.get(&guild_id)));
+                    if *role_id == guild_id {
+                        perms
+                    } else {
+                        apply_overwrite(perms, channel_overwrites.and_then(|o| o.get(role_id)))
+                    }
+                };
+                cells.push(permission_flags(perms));
+            }
+            writeln!(report, "| {} | {} |", role_name, cells.join(" | "))?;
+        }
+
+        if !member_overwrites.is_empty() {
+            writeln!(report)?;
+            writeln!(report, "Member overwrites:")?;
+            writeln!(report)?;
+            for (channel_name, member, allow, deny) in member_overwrites {
+                writeln!(
+                    report,
+                    "- #{}: {} allowed {}, denied {}",
+                    channel_name,
+                    member,
+                    permission_names(allow),
+                    permission_names(deny)
+                )?;
+            }
+        }
+    }
+
+    Ok(report)
+}
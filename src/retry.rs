@@ -0,0 +1,68 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::SimpleResult;
+
+/// How requests that fail on the way or with a server error are retried, set once from
+/// `--retries`, `--retry-backoff` and `--max-backoff`.
+static POLICY: OnceLock<Policy> = OnceLock::new();
+/// The longest rate limit waited out, from `--max-rate-limit-wait`. Unset waits for any.
+static MAX_RATE_LIMIT_WAIT: OnceLock<Duration> = OnceLock::new();
+
+struct Policy {
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+pub fn set_policy(retries: u32, backoff: f64, max_backoff: f64) -> SimpleResult<()> {
+    let seconds = |flag: &str, value: f64| {
+        Duration::try_from_secs_f64(value)
+            .map_err(|_| format!("{} must be a number of seconds, got {}", flag, value))
+    };
+    let policy = Policy {
+        retries,
+        backoff: seconds("--retry-backoff", backoff)?,
+        max_backoff: seconds("--max-backoff", max_backoff)?,
+    };
+    POLICY
+        .set(policy)
+        .map_err(|_| "Retry policy was already set".into())
+}
+
+/// How long to wait before retrying a request that failed `attempt` times already, or
+/// `None` once it's out of retries. The wait doubles with every attempt.
+pub fn backoff(attempt: u32) -> Option<Duration> {
+    let policy = POLICY.get_or_init(|| Policy {
+        retries: 3,
+        backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(60),
+    });
+    if attempt >= policy.retries {
+        return None;
+    }
+    let backoff = policy
+        .backoff
+        .checked_mul(2u32.saturating_pow(attempt))
+        .unwrap_or(policy.max_backoff);
+    Some(backoff.min(policy.max_backoff))
+}
+
+pub fn set_max_rate_limit_wait(max_wait: f64) -> SimpleResult<()> {
+    let max_wait = Duration::try_from_secs_f64(max_wait).map_err(|_| {
+        format!(
+            "--max-rate-limit-wait must be a number of seconds, got {}",
+            max_wait
+        )
+    })?;
+    MAX_RATE_LIMIT_WAIT
+        .set(max_wait)
+        .map_err(|_| "Longest rate limit wait was already set".into())
+}
+
+/// Whether a rate limit lasting `seconds` is to be waited out rather than given up on.
+pub fn waits_out(seconds: f64) -> bool {
+    MAX_RATE_LIMIT_WAIT
+        .get()
+        .is_none_or(|max_wait| seconds <= max_wait.as_secs_f64())
+}
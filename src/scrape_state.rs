@@ -0,0 +1,46 @@
+use rusqlite::OptionalExtension;
+
+use crate::snowflake::Snowflake;
+use crate::SimpleResult;
+
+/// How far scrapes of a channel have got: every message from `newest_id` down to
+/// `oldest_id` is stored, and when `complete` that's all of them up to `newest_id`.
+pub struct ScrapeState {
+    pub newest_id: Snowflake,
+    pub oldest_id: Snowflake,
+    pub complete: bool,
+}
+
+pub fn load(
+    conn: &rusqlite::Connection,
+    channel_id: Snowflake,
+) -> SimpleResult<Option<ScrapeState>> {
+    Ok(conn
+        .query_row(
+            "SELECT newest_id, oldest_id, complete FROM scrape_state WHERE channel_id = ?",
+            [channel_id],
+            |row| {
+                Ok(ScrapeState {
+                    newest_id: row.get(0)?,
+                    oldest_id: row.get(1)?,
+                    complete: row.get(2)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+pub fn save(
+    conn: &rusqlite::Connection,
+    channel_id: Snowflake,
+    state: &ScrapeState,
+) -> SimpleResult<()> {
+    conn.execute(
+        "INSERT INTO scrape_state (channel_id, newest_id, oldest_id, complete) VALUES (?,?,?,?)
+         ON CONFLICT (channel_id) DO UPDATE SET
+            newest_id = excluded.newest_id, oldest_id = excluded.oldest_id,
+            complete = excluded.complete, last_run_at = excluded.last_run_at",
+        rusqlite::params![channel_id, state.newest_id, state.oldest_id, state.complete],
+    )?;
+    Ok(())
+}
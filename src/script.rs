@@ -0,0 +1,183 @@
+use rusqlite::types::Value;
+
+use std::sync::OnceLock;
+
+use crate::snowflake::Snowflake;
+use crate::{Message, SimpleResult};
+
+/// Path and source of the script every message goes through, set once from `--script`.
+static SCRIPT: OnceLock<(String, String)> = OnceLock::new();
+
+/// Fields the script computed for a message, stored in `message_field`.
+pub type Fields = Vec<(String, Value)>;
+/// The fields computed for each message of a page that got any.
+pub type PageFields = Vec<(Snowflake, Fields)>;
+
+/// What the script decided for one message.
+#[cfg_attr(not(feature = "lua"), allow(dead_code))]
+enum Verdict {
+    Drop,
+    Keep(Fields),
+}
+
+/// Loads the script at `path`, checking it defines `on_message` before anything is scraped.
+pub fn load(path: &str) -> SimpleResult<()> {
+    if !cfg!(feature = "lua") {
+        return Err("Scripts need a build with the `lua` feature".into());
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path, e))?;
+    engine::check(path, &source)?;
+    SCRIPT
+        .set((path.to_string(), source))
+        .map_err(|_| "Script was already loaded".into())
+}
+
+pub fn is_set() -> bool {
+    SCRIPT.get().is_some()
+}
+
+/// Passes each message to the script's `on_message`, keeping those it doesn't return
+/// `false` for along with any fields it returned.
+pub fn apply(messages: Vec<Message>) -> SimpleResult<(Vec<Message>, PageFields)> {
+    let (path, source) = match SCRIPT.get() {
+        Some(script) => script,
+        None => return Ok((messages, Vec::new())),
+    };
+    let mut kept = Vec::new();
+    let mut fields = Vec::new();
+    for message in messages {
+        let verdict = engine::on_message(path, source, &message)
+            .map_err(|e| format!("Script {} failed on Message {}: {}", path, message.id, e))?;
+        if let Verdict::Keep(computed) = verdict {
+            if !computed.is_empty() {
+                fields.push((message.id, computed));
+            }
+            kept.push(message);
+        }
+    }
+    Ok((kept, fields))
+}
+
+/// Stores the fields computed for messages already inserted, replacing earlier values.
+pub fn store_fields(conn: &mut rusqlite::Connection, fields: PageFields) -> SimpleResult<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR REPLACE INTO message_field (message_id, name, value) VALUES (?,?,?)",
+        )?;
+        for (message_id, computed) in &fields {
+            for (name, value) in computed {
+                stmt.execute(rusqlite::params![message_id, name, value])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(feature = "lua")]
+mod engine {
+    use mlua::{Lua, LuaSerdeExt, SerializeOptions};
+    use rusqlite::types::Value;
+
+    use std::cell::RefCell;
+
+    use super::Verdict;
+    use crate::{Message, SimpleResult};
+
+    thread_local! {
+        /// Lua states can't be shared between threads, so each worker runs its own.
+        static LUA: RefCell<Option<Lua>> = const { RefCell::new(None) };
+    }
+
+    fn new_state(path: &str, source: &str) -> SimpleResult<Lua> {
+        let lua = Lua::new();
+        lua.load(source)
+            .set_name(path)
+            .exec()
+            .map_err(|e| format!("Loading script {}: {}", path, e))?;
+        if lua
+            .globals()
+            .get::<_, Option<mlua::Function>>("on_message")?
+            .is_none()
+        {
+            let err_msg = format!("Script {} doesn't define on_message", path);
+            return Err(err_msg.into());
+        }
+        Ok(lua)
+    }
+
+    pub fn check(path: &str, source: &str) -> SimpleResult<()> {
+        new_state(path, source).map(drop)
+    }
+
+    pub fn on_message(path: &str, source: &str, message: &Message) -> SimpleResult<Verdict> {
+        LUA.with(|state| {
+            let mut state = state.borrow_mut();
+            if state.is_none() {
+                *state = Some(new_state(path, source)?);
+            }
+            let lua = state.as_ref().unwrap();
+            // Missing values are left out of the table rather than set to a null sentinel.
+            let options = SerializeOptions::new().serialize_none_to_null(false);
+            let message = lua.to_value_with(message, options)?;
+            let on_message: mlua::Function = lua.globals().get("on_message")?;
+            let verdict = match on_message.call(message)? {
+                mlua::Value::Boolean(false) => Ok(Verdict::Drop),
+                mlua::Value::Nil | mlua::Value::Boolean(true) => Ok(Verdict::Keep(Vec::new())),
+                mlua::Value::Table(table) => {
+                    let mut fields = Vec::new();
+                    for pair in table.pairs::<String, mlua::Value>() {
+                        let (name, value) = pair?;
+                        fields.push((name, to_sql(lua, value)?));
+                    }
+                    fields.sort_by(|a, b| a.0.cmp(&b.0));
+                    Ok(Verdict::Keep(fields))
+                }
+                other => {
+                    let err_msg = format!(
+                        "on_message must return a boolean, nil or a table of fields, not {}",
+                        other.type_name()
+                    );
+                    Err(err_msg.into())
+                }
+            };
+            verdict
+        })
+    }
+
+    /// Tables are stored as JSON, everything else as the closest SQLite type.
+    fn to_sql(lua: &Lua, value: mlua::Value) -> SimpleResult<Value> {
+        Ok(match value {
+            mlua::Value::Boolean(b) => Value::Integer(b as i64),
+            mlua::Value::Integer(i) => Value::Integer(i),
+            mlua::Value::Number(n) => Value::Real(n),
+            mlua::Value::String(s) => Value::Text(s.to_str()?.to_string()),
+            mlua::Value::Table(_) => {
+                let json: serde_json::Value = lua.from_value(value)?;
+                Value::Text(json.to_string())
+            }
+            other => {
+                let err_msg = format!("Can't store {} values as fields", other.type_name());
+                return Err(err_msg.into());
+            }
+        })
+    }
+}
+
+#[cfg(not(feature = "lua"))]
+mod engine {
+    use super::Verdict;
+    use crate::{Message, SimpleResult};
+
+    pub fn check(_path: &str, _source: &str) -> SimpleResult<()> {
+        unreachable!("scripts aren't loaded without the `lua` feature")
+    }
+
+    pub fn on_message(_path: &str, _source: &str, _message: &Message) -> SimpleResult<Verdict> {
+        unreachable!("scripts aren't loaded without the `lua` feature")
+    }
+}
@@ -0,0 +1,211 @@
+use clap::Args;
+use serde::Deserialize;
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::anonymize::Anonymizer;
+use crate::api::ApiClient;
+use crate::exit::{self, Failure};
+use crate::redact::Redactor;
+use crate::snowflake::Snowflake;
+use crate::summary::{ChannelSummary, RunSummary};
+use crate::{client, db, guild, interrupt, upload};
+use crate::{get_channel, guild_db_path, insert_channel, insert_page, resolve_channel};
+use crate::{send_request, ChannelArg, Message, SimpleResult, BASE_URL};
+
+/// Matches per page of search results.
+const PAGE: u64 = 25;
+/// The furthest into the results Discord lets a search page.
+const MAX_OFFSET: u64 = 9975;
+/// The most messages one request fetches around a match.
+const MAX_AROUND: usize = 100;
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Only fetch the messages of --guild matching this search, instead of whole channels.
+    /// Channels given as well narrow the search down to them. Needs a user token
+    #[clap(long, value_name = "QUERY", requires = "guild")]
+    search: Option<String>,
+
+    /// Only fetch the messages of --guild sent by this user, found with search like --search,
+    /// can be given several times
+    #[clap(long = "author", value_name = "USER_ID", requires = "guild")]
+    authors: Vec<Snowflake>,
+
+    /// The guild --search and --author look through
+    #[clap(long, value_name = "GUILD_ID")]
+    guild: Option<Snowflake>,
+
+    /// Also fetch this many messages before and after each match of --search or --author
+    #[clap(long, default_value_t = 2, value_name = "N")]
+    context: usize,
+}
+
+impl SearchArgs {
+    pub fn is_set(&self) -> bool {
+        self.search.is_some() || !self.authors.is_empty() || self.guild.is_some()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchPage {
+    total_results: u64,
+    /// Each match, in an array of its own.
+    messages: Vec<Vec<Message>>,
+}
+
+/// Stores the messages matching `--search` and sent by the `--author`s, with the messages
+/// around them, and reports how many were new in each channel they were found in.
+pub fn run(
+    args: &crate::Args,
+    client: &dyn ApiClient,
+    anonymizer: Option<&Anonymizer>,
+    redactor: Option<&Redactor>,
+    uploader: Option<upload::Uploader>,
+    started: Instant,
+) -> SimpleResult<()> {
+    let search = &args.search;
+    let guild_id = match search.guild {
+        Some(guild_id) if search.search.is_some() || !search.authors.is_empty() => guild_id,
+        _ => return Err("--guild needs --search or --author".into()),
+    };
+    if client::is_bot() {
+        return Err(Failure::new(
+            exit::AUTH,
+            "Bots can't search messages, --search needs a user token",
+        )
+        .into());
+    }
+    let channels: Vec<Snowflake> = args
+        .channel_ids
+        .iter()
+        .map(|channel_arg| match channel_arg {
+            ChannelArg::Id(channel_id) => Ok(*channel_id),
+            ChannelArg::Name { guild_id, name } => {
+                resolve_channel(client, *guild_id, name).map(|channel| channel.id)
+            }
+        })
+        .collect::<SimpleResult<_>>()?;
+
+    let db_path = match &args.db_per_guild {
+        Some(dir) => guild_db_path(dir, Some(guild_id)),
+        None => args.db_path.clone(),
+    };
+    let mut conn = db::open_db(&db_path)?;
+    guild::insert_guild(&mut conn, guild::get_guild(client, guild_id)?)?;
+    let roles = guild::get_roles(client, guild_id)?;
+    guild::insert_roles(&mut conn, guild_id, roles)?;
+
+    // Keyed by channel, each stored the first time a match is found in it.
+    let mut summaries: BTreeMap<Snowflake, ChannelSummary> = BTreeMap::new();
+    let mut offset = 0;
+    while !interrupt::requested() {
+        let page = search_page(client, guild_id, search, &channels, offset)?;
+        if offset == 0 {
+            info!("Search matched {} Messages", page.total_results);
+            if page.total_results > MAX_OFFSET + PAGE {
+                warn!(
+                    "Discord only returns the first {} matches, narrow the search down to get the rest",
+                    MAX_OFFSET + PAGE
+                );
+            }
+        }
+        if page.messages.is_empty() {
+            break;
+        }
+        for hit in page
+            .messages
+            .into_iter()
+            .filter_map(|m| m.into_iter().next())
+        {
+            let summary = match summaries.entry(hit.channel_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let channel = get_channel(client, hit.channel_id)?;
+                    let name = channel.name.clone();
+                    let channel = match anonymizer {
+                        Some(anonymizer) => anonymizer.channel(channel),
+                        None => channel,
+                    };
+                    insert_channel(&mut conn, channel)?;
+                    entry.insert(ChannelSummary::new(Some(hit.channel_id), name))
+                }
+            };
+            let messages = match search.context {
+                0 => vec![hit],
+                context => get_around(client, hit.channel_id, hit.id, context)?,
+            };
+            let fetched = messages.len() as u64;
+            let new = insert_page(&mut conn, messages, anonymizer, redactor)? as u64;
+            summary.new += new;
+            summary.skipped += fetched - new;
+        }
+        offset += PAGE;
+        if offset >= page.total_results || offset > MAX_OFFSET {
+            break;
+        }
+    }
+    drop(conn);
+
+    let summary = RunSummary::new(summaries.into_values().collect(), started);
+    summary.print(args.output);
+    if interrupt::requested() {
+        return Err(Failure::new(exit::INTERRUPTED, "Interrupted").into());
+    }
+    if let Some(uploader) = uploader {
+        uploader.upload(&db_path)?;
+    }
+    Ok(())
+}
+
+fn search_page(
+    client: &dyn ApiClient,
+    guild_id: Snowflake,
+    search: &SearchArgs,
+    channels: &[Snowflake],
+    offset: u64,
+) -> SimpleResult<SearchPage> {
+    let mut params = form_urlencoded::Serializer::new(String::new());
+    if let Some(query) = &search.search {
+        params.append_pair("content", query);
+    }
+    for author_id in &search.authors {
+        params.append_pair("author_id", &author_id.to_string());
+    }
+    for channel_id in channels {
+        params.append_pair("channel_id", &channel_id.to_string());
+    }
+    params.append_pair("offset", &offset.to_string());
+    let req_url = format!(
+        "{}/guilds/{}/messages/search?{}",
+        BASE_URL,
+        guild_id,
+        params.finish()
+    );
+
+    let body = send_request(client, &req_url)?;
+    let page: SearchPage = serde_json::from_str(&body)?;
+    Ok(page)
+}
+
+/// The `context` messages before and after a message, and the message itself.
+fn get_around(
+    client: &dyn ApiClient,
+    channel_id: Snowflake,
+    message_id: Snowflake,
+    context: usize,
+) -> SimpleResult<Vec<Message>> {
+    let req_url = format!(
+        "{}/channels/{}/messages?around={}&limit={}",
+        BASE_URL,
+        channel_id,
+        message_id,
+        (2 * context + 1).min(MAX_AROUND)
+    );
+
+    let body = send_request(client, &req_url)?;
+    let messages: Vec<Message> = serde_json::from_str(&body)?;
+    Ok(messages)
+}
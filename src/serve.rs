@@ -0,0 +1,380 @@
+use clap::Args;
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Response, Server};
+
+use std::collections::HashMap;
+
+use crate::anonymize::Anonymizer;
+use crate::export::ExportMessage;
+use crate::redact::Redactor;
+use crate::snowflake::Snowflake;
+use crate::{db, SimpleResult, User, HAS_SNAPSHOT};
+
+/// Messages returned by a request when no `limit` is given, and the most it may ask for.
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 100;
+
+/// The web viewer, compiled into the binary so `serve` needs nothing beside the database.
+const ASSETS: &[(&str, &str, &str)] = &[
+    ("/", "text/html", include_str!("web/index.html")),
+    ("/app.js", "text/javascript", include_str!("web/app.js")),
+    ("/style.css", "text/css", include_str!("web/style.css")),
+];
+
+/// Selects the columns read by `message_row`.
+pub const MESSAGE_COLUMNS: &str =
+    "m.id, m.channel_id, c.name, m.author_id, u.username, m.timestamp, m.content, m.reply_to,
+     m.origin_guild_id, m.origin_channel_id, m.origin_message_id, m.flags, m.voice_duration_secs,
+     m.deleted
+     FROM message m
+     JOIN user u ON u.id = m.author_id
+     JOIN channel c ON c.id = m.channel_id";
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on, only reachable from this machine by default
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    bind: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelSummary {
+    pub id: Snowflake,
+    pub guild_id: Option<Snowflake>,
+    pub guild_name: Option<String>,
+    pub name: Option<String>,
+    pub message_count: i64,
+    pub last_message_id: Option<Snowflake>,
+}
+
+/// An error answered with an HTTP status instead of stopping the server.
+struct HttpError(u16, String);
+
+/// What served messages go through before they're sent, as they would for an export.
+#[derive(Clone, Copy)]
+struct Privacy<'a> {
+    redactor: Option<&'a Redactor>,
+    anonymizer: Option<&'a Anonymizer>,
+}
+
+impl Privacy<'_> {
+    fn is_enabled(&self) -> bool {
+        self.redactor.is_some() || self.anonymizer.is_some()
+    }
+
+    fn protect(&self, message: &mut ExportMessage) {
+        if let Some(anonymizer) = self.anonymizer {
+            let user = anonymizer.user(User {
+                id: message.author_id,
+                username: std::mem::take(&mut message.author),
+                discriminator: None,
+                global_name: None,
+            });
+            message.author_id = user.id;
+            message.author = user.username;
+            message.content = anonymizer.content(&message.content);
+        }
+        if let Some(redactor) = self.redactor {
+            message.content = redactor.redact(&message.content);
+        }
+    }
+}
+
+impl<E: std::error::Error> From<E> for HttpError {
+    fn from(e: E) -> Self {
+        HttpError(500, e.to_string())
+    }
+}
+
+/// Serves read-only JSON endpoints over the database, one request at a time and each from a
+/// snapshot of its own, and the web viewer built on them. Messages are redacted and
+/// anonymized like exports are when `redactor` or `anonymizer` are given.
+pub fn run(
+    db_path: &str,
+    args: ServeArgs,
+    redactor: Option<&Redactor>,
+    anonymizer: Option<&Anonymizer>,
+) -> SimpleResult<()> {
+    let privacy = Privacy {
+        redactor,
+        anonymizer,
+    };
+    let conn = db::open_read_only(db_path)?;
+    #[cfg(feature = "graphql")]
+    let schema = crate::graphql::schema(db_path)?;
+    let server = Server::http(&args.bind)
+        .map_err(|e| format!("Could not listen on {}: {}", args.bind, e))?;
+    info!("Serving {} on http://{}", db_path, args.bind);
+
+    for request in server.incoming_requests() {
+        let (path, query) = match request.url().split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (request.url().to_string(), String::new()),
+        };
+        if let Some((_, content_type, body)) = ASSETS.iter().find(|(p, _, _)| *p == path) {
+            let response = Response::from_string(*body).with_header(
+                Header::from_bytes("Content-Type", format!("{}; charset=utf-8", content_type))
+                    .unwrap(),
+            );
+            if let Err(e) = request.respond(response) {
+                warn!("Could not send response: {}", e);
+            }
+            continue;
+        }
+        #[cfg(feature = "graphql")]
+        if path == "/graphql" {
+            let mut request = request;
+            // Users are looked up by their stored IDs, which pseudonyms can't be turned back
+            // into, so GraphQL answers only for archives served as they are.
+            let result = if privacy.is_enabled() {
+                Err(HttpError(
+                    403,
+                    "GraphQL is off with --anonymize or --redact".to_string(),
+                ))
+            } else {
+                graphql(&schema, &mut request)
+            };
+            respond(request, result);
+            continue;
+        }
+        let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+
+        let result = match request.method() {
+            Method::Get => db::snapshot(&conn)
+                .map_err(HttpError::from)
+                .and_then(|snapshot| route(&snapshot, &path, &params, privacy)),
+            _ => Err(HttpError(
+                405,
+                "Only GET requests are supported".to_string(),
+            )),
+        };
+        respond(request, result);
+    }
+    Ok(())
+}
+
+fn respond(request: tiny_http::Request, result: Result<serde_json::Value, HttpError>) {
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(HttpError(status, message)) => {
+            if status == 500 {
+                warn!("{} failed: {}", request.url(), message);
+            }
+            (status, json!({ "error": message }))
+        }
+    };
+
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap(),
+        );
+    if let Err(e) = request.respond(response) {
+        warn!("Could not send response: {}", e);
+    }
+}
+
+/// Answers a GraphQL query posted as `{"query": ..., "variables": ...}`.
+#[cfg(feature = "graphql")]
+fn graphql(
+    schema: &crate::graphql::ArchiveSchema,
+    request: &mut tiny_http::Request,
+) -> Result<serde_json::Value, HttpError> {
+    if *request.method() != Method::Post {
+        return Err(HttpError(405, "GraphQL queries must be POSTed".to_string()));
+    }
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    crate::graphql::execute(schema, &body).map_err(|e| HttpError(400, e.to_string()))
+}
+
+fn route(
+    conn: &rusqlite::Connection,
+    path: &str,
+    params: &HashMap<String, String>,
+    privacy: Privacy,
+) -> Result<serde_json::Value, HttpError> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["channels"] => Ok(json!(channel_summaries(conn, "1 = 1", [])?)),
+        ["channels", channel_id, "messages"] => {
+            let channel_id: Snowflake = channel_id
+                .parse()
+                .map_err(|_| HttpError(400, format!("Invalid channel ID {}", channel_id)))?;
+            let exists = conn
+                .prepare("SELECT 1 FROM channel WHERE id = ?")?
+                .exists([channel_id])?;
+            if !exists {
+                return Err(HttpError(404, format!("No channel with ID {}", channel_id)));
+            }
+            let sql = format!(
+                "SELECT {} WHERE m.channel_id = ? AND m.id < ? ORDER BY m.id DESC LIMIT ?",
+                MESSAGE_COLUMNS
+            );
+            let messages = messages(
+                conn,
+                &sql,
+                rusqlite::params![channel_id, before(params)?, limit(params)?],
+                privacy,
+            )?;
+            Ok(json!(messages))
+        }
+        ["search"] => {
+            let q = match params.get("q") {
+                Some(q) if !q.is_empty() => q,
+                _ => return Err(HttpError(400, "Missing search query q".to_string())),
+            };
+            if privacy.is_enabled() {
+                let found = search_protected(conn, q, before(params)?, limit(params)?, privacy)?;
+                return Ok(json!(found));
+            }
+            let pattern = like_pattern(q);
+            let sql = format!(
+                "SELECT {} WHERE m.content LIKE ? ESCAPE '\\' AND m.id < ? ORDER BY m.id DESC LIMIT ?",
+                MESSAGE_COLUMNS
+            );
+            let messages = messages(
+                conn,
+                &sql,
+                rusqlite::params![pattern, before(params)?, limit(params)?],
+                privacy,
+            )?;
+            Ok(json!(messages))
+        }
+        _ => Err(HttpError(404, format!("No endpoint {}", path))),
+    }
+}
+
+/// Every channel matching `condition`, with its guild and message statistics.
+pub fn channel_summaries<P: rusqlite::Params>(
+    conn: &rusqlite::Connection,
+    condition: &str,
+    params: P,
+) -> rusqlite::Result<Vec<ChannelSummary>> {
+    let sql = format!(
+        "SELECT c.id, c.guild_id, g.name, c.name, COUNT(m.id), MAX(m.id)
+         FROM channel c
+         LEFT JOIN guild g ON g.id = c.guild_id
+         LEFT JOIN message m ON m.channel_id = c.id
+         WHERE {}
+         GROUP BY c.id
+         ORDER BY c.guild_id, c.name",
+        condition
+    );
+    conn.prepare(&sql)?
+        .query_map(params, |row| {
+            Ok(ChannelSummary {
+                id: row.get(0)?,
+                guild_id: row.get(1)?,
+                guild_name: row.get(2)?,
+                name: row.get(3)?,
+                message_count: row.get(4)?,
+                last_message_id: row.get(5)?,
+            })
+        })?
+        .collect()
+}
+
+/// A message selected with `MESSAGE_COLUMNS`.
+pub fn message_row(row: &rusqlite::Row) -> rusqlite::Result<ExportMessage> {
+    Ok(ExportMessage {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        channel_name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        author_id: row.get(3)?,
+        author: row.get(4)?,
+        timestamp: row.get(5)?,
+        content: row.get(6)?,
+        reply_to: row.get(7)?,
+        origin_guild_id: row.get(8)?,
+        origin_channel_id: row.get(9)?,
+        origin_message_id: row.get(10)?,
+        forwarded: row.get::<_, i64>(11)? & HAS_SNAPSHOT != 0,
+        voice_duration_secs: row.get(12)?,
+        deleted: row.get(13)?,
+        depth: None,
+    })
+}
+
+/// A `LIKE ? ESCAPE '\'` pattern matching content containing `text`. LIKE treats % and _
+/// as wildcards, searches are for the literal text.
+pub fn like_pattern(text: &str) -> String {
+    format!(
+        "%{}%",
+        text.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// Newest messages first, like Discord's own API, so `before` pages back through history.
+fn messages(
+    conn: &rusqlite::Connection,
+    sql: &str,
+    params: &[&dyn rusqlite::ToSql],
+    privacy: Privacy,
+) -> Result<Vec<ExportMessage>, HttpError> {
+    let mut messages: Vec<ExportMessage> = conn
+        .prepare(sql)?
+        .query_map(params, message_row)?
+        .collect::<Result<_, _>>()?;
+    for message in &mut messages {
+        privacy.protect(message);
+    }
+    Ok(messages)
+}
+
+/// Searches messages as they're served rather than as stored, newest first, so text that
+/// was redacted or anonymized can't be found by guessing it.
+fn search_protected(
+    conn: &rusqlite::Connection,
+    q: &str,
+    before: Snowflake,
+    limit: usize,
+    privacy: Privacy,
+) -> Result<Vec<ExportMessage>, HttpError> {
+    let sql = format!(
+        "SELECT {} WHERE m.id < ? ORDER BY m.id DESC",
+        MESSAGE_COLUMNS
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map([before], message_row)?;
+    let q = q.to_lowercase();
+    let mut found = Vec::new();
+    for row in rows {
+        let mut message = row?;
+        privacy.protect(&mut message);
+        if message.content.to_lowercase().contains(&q) {
+            found.push(message);
+            if found.len() == limit {
+                break;
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn before(params: &HashMap<String, String>) -> Result<Snowflake, HttpError> {
+    match params.get("before") {
+        Some(before) => before
+            .parse()
+            .map_err(|_| HttpError(400, format!("Invalid message ID {}", before))),
+        None => Ok(Snowflake(i64::MAX)),
+    }
+}
+
+fn limit(params: &HashMap<String, String>) -> Result<usize, HttpError> {
+    match params.get("limit") {
+        Some(limit) => match limit.parse::<usize>() {
+            Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => Ok(limit),
+            _ => Err(HttpError(
+                400,
+                format!("limit must be between 1 and {}", MAX_LIMIT),
+            )),
+        },
+        None => Ok(DEFAULT_LIMIT),
+    }
+}
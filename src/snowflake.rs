@@ -0,0 +1,70 @@
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Milliseconds between the unix epoch and the first second of 2015.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// A Discord ID. The API sends these as strings, but they always fit in an `i64`,
+/// which lets SQLite store and order them as integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Snowflake(pub i64);
+
+impl Snowflake {
+    /// The creation time, in unix seconds, encoded in the ID.
+    pub fn created_at_unix(&self) -> i64 {
+        let ms = (self.0 as u64 >> 22) + DISCORD_EPOCH;
+        (ms / 1000) as i64
+    }
+
+    /// The lowest ID Discord could give something created at `unix` seconds, for
+    /// comparing IDs against a time. Times past what IDs can hold give the highest ID.
+    pub fn first_at_unix(unix: i64) -> Snowflake {
+        let ms = unix
+            .saturating_mul(1000)
+            .saturating_sub(DISCORD_EPOCH as i64)
+            .clamp(0, i64::MAX >> 22);
+        Snowflake(ms << 22)
+    }
+}
+
+impl fmt::Display for Snowflake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Snowflake {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Snowflake(s.parse::<u64>()? as i64))
+    }
+}
+
+impl Serialize for Snowflake {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Snowflake {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl ToSql for Snowflake {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for Snowflake {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(Snowflake)
+    }
+}
@@ -0,0 +1,171 @@
+use clap::Args;
+use regex::Regex;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::snowflake::Snowflake;
+use crate::SimpleResult;
+
+#[derive(Debug, Args)]
+pub struct SpamArgs {
+    /// Flag users posting the same message this many times within --burst-window
+    #[clap(long, default_value_t = 3, value_name = "N")]
+    duplicates: usize,
+
+    /// Seconds within which repeated messages count as a burst
+    #[clap(long, default_value_t = 600, value_name = "SECS")]
+    burst_window: i64,
+
+    /// Flag users posting this many messages with server invites
+    #[clap(long, default_value_t = 3, value_name = "N")]
+    invites: usize,
+
+    /// Flag users posting this many messages within a minute
+    #[clap(long, default_value_t = 20, value_name = "N")]
+    per_minute: usize,
+}
+
+/// Messages shorter than this are too common to repeat as spam, e.g. "lol" or "+1".
+const MIN_DUPLICATE_LENGTH: usize = 10;
+
+/// Why a user was flagged, stored in `flagged_user.reason`.
+const DUPLICATES: &str = "duplicates";
+const INVITES: &str = "invites";
+const RATE: &str = "rate";
+
+/// Replaces the flags in `flagged_user` with those of the current heuristics and lists them.
+pub fn run(conn: &mut rusqlite::Connection, args: SpamArgs) -> SimpleResult<()> {
+    // Keyed by user and reason, with what triggered it.
+    let mut flags: BTreeMap<(Snowflake, &str), String> = BTreeMap::new();
+
+    for (user_id, count) in duplicate_bursts(conn, &args)? {
+        let detail = format!("{} identical messages within {}s", count, args.burst_window);
+        flags.insert((user_id, DUPLICATES), detail);
+    }
+    for (user_id, count) in invite_posters(conn, &args)? {
+        flags.insert(
+            (user_id, INVITES),
+            format!("{} messages with invites", count),
+        );
+    }
+    for (user_id, count) in fastest_posters(conn, &args)? {
+        flags.insert(
+            (user_id, RATE),
+            format!("{} messages within a minute", count),
+        );
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM flagged_user", [])?;
+    {
+        let mut stmt =
+            tx.prepare("INSERT INTO flagged_user (user_id, reason, detail) VALUES (?,?,?)")?;
+        for ((user_id, reason), detail) in &flags {
+            stmt.execute(rusqlite::params![user_id, reason, detail])?;
+        }
+    }
+    tx.commit()?;
+
+    let mut names = conn.prepare("SELECT username FROM user WHERE id = ?")?;
+    for ((user_id, reason), detail) in &flags {
+        let name: String = names.query_row([user_id], |row| row.get(0))?;
+        println!("{} {} ({}): {}", user_id, name, reason, detail);
+    }
+    let users: BTreeSet<&Snowflake> = flags.keys().map(|(user_id, _)| user_id).collect();
+    info!("Flagged {} users", users.len());
+    Ok(())
+}
+
+/// The most times each user posted one message within the burst window, for users
+/// reaching --duplicates.
+fn duplicate_bursts(
+    conn: &rusqlite::Connection,
+    args: &SpamArgs,
+) -> SimpleResult<BTreeMap<Snowflake, usize>> {
+    let mut stmt = conn.prepare(
+        "SELECT author_id, content, created_at_unix FROM message
+         WHERE (author_id, content) IN (
+             SELECT author_id, content FROM message
+             WHERE length(content) >= ?
+             GROUP BY author_id, content HAVING COUNT(*) >= ?)
+         ORDER BY author_id, content, created_at_unix",
+    )?;
+    let mut rows = stmt.query(rusqlite::params![MIN_DUPLICATE_LENGTH, args.duplicates])?;
+
+    let mut bursts = BTreeMap::new();
+    let mut group: Option<(Snowflake, String)> = None;
+    let mut window = VecDeque::new();
+    while let Some(row) = rows.next()? {
+        let key = (row.get(0)?, row.get(1)?);
+        if group.as_ref() != Some(&key) {
+            window.clear();
+            group = Some(key);
+        }
+        let at: i64 = row.get(2)?;
+        window.push_back(at);
+        while at - window[0] > args.burst_window {
+            window.pop_front();
+        }
+        if window.len() >= args.duplicates {
+            let most = bursts.entry(group.as_ref().unwrap().0).or_insert(0);
+            *most = window.len().max(*most);
+        }
+    }
+    Ok(bursts)
+}
+
+/// How many messages with invites each user reaching --invites posted.
+fn invite_posters(
+    conn: &rusqlite::Connection,
+    args: &SpamArgs,
+) -> SimpleResult<BTreeMap<Snowflake, usize>> {
+    let invite = Regex::new(r"(?i)(discord\.gg|discord(app)?\.com/invite)/[\w-]+").unwrap();
+    let mut stmt = conn.prepare(
+        "SELECT author_id, content FROM message
+         WHERE content LIKE '%discord.gg/%' OR content LIKE '%/invite/%'",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut counts = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let content: String = row.get(1)?;
+        if invite.is_match(&content) {
+            *counts.entry(row.get(0)?).or_insert(0) += 1;
+        }
+    }
+    counts.retain(|_, count| *count >= args.invites);
+    Ok(counts)
+}
+
+/// The most messages each user posted within a minute, for users reaching --per-minute.
+fn fastest_posters(
+    conn: &rusqlite::Connection,
+    args: &SpamArgs,
+) -> SimpleResult<BTreeMap<Snowflake, usize>> {
+    let mut stmt = conn.prepare(
+        "SELECT author_id, created_at_unix FROM message
+         WHERE author_id IN (
+             SELECT author_id FROM message GROUP BY author_id HAVING COUNT(*) >= ?)
+         ORDER BY author_id, created_at_unix",
+    )?;
+    let mut rows = stmt.query([args.per_minute])?;
+
+    let mut rates = BTreeMap::new();
+    let mut author = None;
+    let mut window = VecDeque::new();
+    while let Some(row) = rows.next()? {
+        let (user_id, at): (Snowflake, i64) = (row.get(0)?, row.get(1)?);
+        if author != Some(user_id) {
+            window.clear();
+            author = Some(user_id);
+        }
+        window.push_back(at);
+        while at - window[0] >= 60 {
+            window.pop_front();
+        }
+        if window.len() >= args.per_minute {
+            let most = rates.entry(user_id).or_insert(0);
+            *most = window.len().max(*most);
+        }
+    }
+    Ok(rates)
+}
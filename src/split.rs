@@ -0,0 +1,115 @@
+use clap::Args;
+
+use std::path::Path;
+
+use crate::db;
+use crate::snowflake::Snowflake;
+use crate::{guild_db_path, SimpleResult};
+
+#[derive(Debug, Args)]
+pub struct SplitArgs {
+    /// Write one database per guild; direct messages go to `direct.db`
+    #[clap(long, required = true)]
+    by_guild: bool,
+
+    /// Directory the per-guild databases are written to
+    #[clap(long)]
+    out_dir: String,
+}
+
+pub fn run(db_path: &str, args: SplitArgs) -> SimpleResult<()> {
+    let conn = db::open_db(db_path)?;
+    let mut stmt = conn.prepare("SELECT DISTINCT guild_id FROM channel ORDER BY guild_id")?;
+    let guild_ids = stmt
+        .query_map([], |row| row.get::<_, Option<Snowflake>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    std::fs::create_dir_all(&args.out_dir)?;
+    for guild_id in guild_ids {
+        let out_path = guild_db_path(&args.out_dir, guild_id);
+        let out_path = Path::new(&out_path);
+        if out_path.exists() {
+            let err_msg = format!("{} already exists", out_path.display());
+            return Err(err_msg.into());
+        }
+
+        let count = copy_guild(db_path, out_path, guild_id)?;
+        info!("Wrote {} Messages to {}", count, out_path.display());
+    }
+
+    Ok(())
+}
+
+/// Copies one guild, its roles, its scheduled events and soundboard sounds, its channels,
+/// their messages and what was computed about them, the authors of those messages, any
+/// cases referring to them and the guild's application commands into a new database, with
+/// the flags of those authors and every forgotten user so later scrapes still leave them
+/// out. `None` selects direct message channels.
+fn copy_guild(src_path: &str, out_path: &Path, guild_id: Option<Snowflake>) -> SimpleResult<i64> {
+    let mut conn = db::connect_db(out_path)?;
+    db::attach(&conn, src_path, "src")?;
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "CREATE TEMP TABLE split_channel AS SELECT id FROM src.channel WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    tx.execute_batch(
+        "INSERT INTO channel SELECT * FROM src.channel WHERE id IN (SELECT id FROM split_channel);
+        INSERT INTO channel_history SELECT * FROM src.channel_history
+            WHERE channel_id IN (SELECT id FROM split_channel);
+        INSERT INTO user SELECT * FROM src.user WHERE id IN (
+            SELECT author_id FROM src.message WHERE channel_id IN (SELECT id FROM split_channel));
+        INSERT INTO user_history SELECT * FROM src.user_history WHERE user_id IN (SELECT id FROM user);
+        INSERT INTO flagged_user SELECT * FROM src.flagged_user WHERE user_id IN (SELECT id FROM user);
+        INSERT INTO forgotten_user SELECT * FROM src.forgotten_user;
+        INSERT INTO message SELECT * FROM src.message WHERE channel_id IN (SELECT id FROM split_channel);
+        INSERT INTO message_field SELECT * FROM src.message_field WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO message_url SELECT * FROM src.message_url WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO message_language SELECT * FROM src.message_language WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO message_reaction SELECT * FROM src.message_reaction WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO message_revision SELECT * FROM src.message_revision WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO case_record SELECT * FROM src.case_record WHERE id IN (
+            SELECT case_id FROM src.case_message WHERE message_id IN (SELECT id FROM message));
+        INSERT INTO case_message SELECT * FROM src.case_message WHERE message_id IN (SELECT id FROM message);
+        INSERT INTO permission_overwrite SELECT * FROM src.permission_overwrite
+            WHERE channel_id IN (SELECT id FROM split_channel);
+        INSERT INTO scrape_state SELECT * FROM src.scrape_state
+            WHERE channel_id IN (SELECT id FROM split_channel);
+        DROP TABLE split_channel;",
+    )?;
+    tx.execute(
+        "INSERT INTO guild SELECT * FROM src.guild WHERE id IS ?",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO guild_history SELECT * FROM src.guild_history WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO role SELECT * FROM src.role WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO scheduled_event SELECT * FROM src.scheduled_event WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO soundboard_sound SELECT * FROM src.soundboard_sound WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO application SELECT * FROM src.application WHERE id IN (
+            SELECT application_id FROM src.application_command WHERE guild_id IS ?)",
+        [guild_id],
+    )?;
+    tx.execute(
+        "INSERT INTO application_command SELECT * FROM src.application_command WHERE guild_id IS ?",
+        [guild_id],
+    )?;
+    let count = tx.query_row("SELECT COUNT(*) FROM message", [], |row| row.get(0))?;
+    tx.commit()?;
+
+    conn.execute("DETACH DATABASE src", [])?;
+    Ok(count)
+}
@@ -0,0 +1,153 @@
+use clap::ArgEnum;
+use serde::Serialize;
+
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::console::{self, Level};
+use crate::exit;
+use crate::snowflake::Snowflake;
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMITED: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMIT_WAIT_MS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum OutputFormat {
+    /// Log lines only
+    Text,
+    /// Also print the run summary as a single line of JSON, last thing before exiting
+    Json,
+}
+
+pub fn count_request() {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a response with status 429.
+pub fn count_rate_limited() {
+    RATE_LIMITED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Adds time spent waiting for Discord's rate limits.
+pub fn add_rate_limit_wait(wait: Duration) {
+    RATE_LIMIT_WAIT_MS.fetch_add(wait.as_millis() as u64, Ordering::Relaxed);
+}
+
+pub fn requests() -> u64 {
+    REQUESTS.load(Ordering::Relaxed)
+}
+
+pub fn rate_limited() -> u64 {
+    RATE_LIMITED.load(Ordering::Relaxed)
+}
+
+pub fn rate_limit_wait() -> Duration {
+    Duration::from_millis(RATE_LIMIT_WAIT_MS.load(Ordering::Relaxed))
+}
+
+/// What happened to one channel during a run.
+#[derive(Debug, Serialize)]
+pub struct ChannelSummary {
+    /// Unknown when a channel given by name couldn't be resolved.
+    pub id: Option<Snowflake>,
+    pub name: Option<String>,
+    /// Messages that weren't stored before.
+    pub new: u64,
+    /// Stored messages found edited, deleted or with other reactions by --refresh.
+    pub updated: u64,
+    /// Messages fetched that were already stored as they are.
+    pub skipped: u64,
+    pub error: Option<String>,
+    /// The exit code the error maps to.
+    pub exit_code: Option<i32>,
+}
+
+impl ChannelSummary {
+    pub fn new(id: Option<Snowflake>, name: Option<String>) -> Self {
+        ChannelSummary {
+            id,
+            name,
+            new: 0,
+            updated: 0,
+            skipped: 0,
+            error: None,
+            exit_code: None,
+        }
+    }
+
+    pub fn fail(&mut self, err: &(dyn Error + 'static)) {
+        self.error = Some(err.to_string());
+        self.exit_code = Some(exit::code(err));
+    }
+
+    /// The channel's ID, or its name when that's all there is.
+    fn label(&self) -> String {
+        match (self.id, &self.name) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(name)) => format!("#{}", name),
+            (None, None) => "?".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub channels: Vec<ChannelSummary>,
+    pub requests: u64,
+    /// Responses with status 429.
+    pub rate_limited: u64,
+    pub rate_limit_wait_seconds: f64,
+    pub duration_seconds: f64,
+    pub errors: Vec<String>,
+}
+
+impl RunSummary {
+    pub fn new(channels: Vec<ChannelSummary>, started: Instant) -> Self {
+        let errors = channels
+            .iter()
+            .filter_map(|c| {
+                c.error
+                    .as_ref()
+                    .map(|e| format!("Channel {}: {}", c.label(), e))
+            })
+            .collect();
+        RunSummary {
+            channels,
+            requests: requests(),
+            rate_limited: rate_limited(),
+            rate_limit_wait_seconds: rate_limit_wait().as_secs_f64(),
+            duration_seconds: started.elapsed().as_secs_f64(),
+            errors,
+        }
+    }
+
+    pub fn print(&self, format: OutputFormat) {
+        let count = |f: fn(&ChannelSummary) -> u64| self.channels.iter().map(f).sum::<u64>();
+        console::print_always(
+            Level::Info,
+            format_args!(
+                "Scraped {} channels in {:.1}s: {} new, {} updated, {} skipped Messages",
+                self.channels.len(),
+                self.duration_seconds,
+                count(|c| c.new),
+                count(|c| c.updated),
+                count(|c| c.skipped)
+            ),
+        );
+        console::print_always(
+            Level::Info,
+            format_args!(
+                "Sent {} requests, {} rate limited, waited {:.1}s for rate limits",
+                self.requests, self.rate_limited, self.rate_limit_wait_seconds
+            ),
+        );
+        for error in &self.errors {
+            console::print_always(Level::Warn, format_args!("{}", error));
+        }
+        if let OutputFormat::Json = format {
+            println!("{}", serde_json::to_string(self).unwrap());
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::SimpleResult;
+
+/// Least time between two requests, set once from `--rps`.
+static INTERVAL: OnceLock<Duration> = OnceLock::new();
+/// When the next request may be sent.
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Caps requests at `rps` per second, on top of whatever Discord's rate limits allow.
+pub fn set_rate(rps: f64) -> SimpleResult<()> {
+    if !(rps.is_finite() && rps > 0.0) {
+        let err_msg = format!("--rps must be a positive number, got {}", rps);
+        return Err(err_msg.into());
+    }
+    INTERVAL
+        .set(Duration::from_secs_f64(1.0 / rps))
+        .map_err(|_| "Request rate was already set".into())
+}
+
+/// Blocks until the next request may be sent, returning at once without `--rps`.
+pub fn wait() {
+    let interval = match INTERVAL.get() {
+        Some(interval) => *interval,
+        None => return,
+    };
+    // The lock is held while sleeping so callers are let through one interval apart.
+    let mut next_request = NEXT_REQUEST.lock().unwrap();
+    if let Some(at) = *next_request {
+        let now = Instant::now();
+        if at > now {
+            std::thread::sleep(at - now);
+        }
+    }
+    *next_request = Some(Instant::now() + interval);
+}
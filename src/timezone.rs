@@ -0,0 +1,57 @@
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
+use rusqlite::functions::FunctionFlags;
+
+use std::sync::OnceLock;
+
+use crate::SimpleResult;
+
+/// The zone times are shown in, set once from `--timezone`. Unset shows them as stored, in UTC.
+static ZONE: OnceLock<(String, TimeZone)> = OnceLock::new();
+
+pub fn set(name: &str) -> SimpleResult<()> {
+    let zone = TimeZone::get(name).map_err(|e| format!("Unknown time zone {}: {}", name, e))?;
+    ZONE.set((name.to_string(), zone))
+        .map_err(|_| "Time zone was already set".into())
+}
+
+/// The name of the zone times are shown in.
+pub fn name() -> &'static str {
+    ZONE.get().map_or("UTC", |(name, _)| name.as_str())
+}
+
+/// A stored RFC 3339 timestamp in the zone, with its offset and in Discord's format.
+/// Timestamps that don't parse are returned as they are.
+pub fn local(timestamp: &str) -> String {
+    let zone = match ZONE.get() {
+        Some((_, zone)) => zone,
+        None => return timestamp.to_string(),
+    };
+    match timestamp.parse::<Timestamp>() {
+        Ok(parsed) => parsed
+            .to_zoned(zone.clone())
+            .strftime("%Y-%m-%dT%H:%M:%S%.6f%:z")
+            .to_string(),
+        Err(_) => timestamp.to_string(),
+    }
+}
+
+/// Unix seconds moved by the zone's offset at that moment, so SQLite's date functions given
+/// `local(unix)` read local dates and hours.
+fn shift(unix: i64) -> i64 {
+    match (ZONE.get(), Timestamp::from_second(unix)) {
+        (Some((_, zone)), Ok(timestamp)) => unix + zone.to_offset(timestamp).seconds() as i64,
+        _ => unix,
+    }
+}
+
+/// Makes `local(unix)` available to the SQL run on `conn`.
+pub fn register(conn: &rusqlite::Connection) -> SimpleResult<()> {
+    conn.create_scalar_function(
+        "local",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(ctx.get::<Option<i64>>(0)?.map(shift)),
+    )?;
+    Ok(())
+}
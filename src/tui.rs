@@ -0,0 +1,338 @@
+use std::io::IsTerminal;
+
+use crate::{progress, SimpleResult};
+
+/// Replaces the log on the terminal with a dashboard of the scrape, moving the log into
+/// a pane of it, until `stop` is called.
+pub fn start() -> SimpleResult<()> {
+    if !cfg!(all(feature = "tui", unix)) {
+        return Err("The dashboard needs a build with the `tui` feature, on Linux or macOS".into());
+    }
+    if !std::io::stdout().is_terminal() {
+        return Err("The dashboard needs standard output to be a terminal".into());
+    }
+    progress::enable();
+    dashboard::start()
+}
+
+/// Closes the dashboard if one is open, so what's printed next goes to the terminal again.
+pub fn stop() -> SimpleResult<()> {
+    dashboard::stop()
+}
+
+#[cfg(all(feature = "tui", unix))]
+mod dashboard {
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::crossterm::execute;
+    use ratatui::crossterm::terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+    };
+    use ratatui::layout::{Constraint, Layout, Rect};
+    use ratatui::style::{Color, Style, Stylize};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+    use ratatui::{Frame, Terminal};
+
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::fd::{FromRawFd, RawFd};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::JoinHandle;
+    use std::time::{Duration, Instant};
+
+    use crate::progress::{self, ChannelProgress, Status};
+    use crate::{console, cutoff, interrupt, summary, SimpleResult};
+
+    /// Log lines kept for the log pane.
+    const LOG_LINES: usize = 500;
+    /// Warnings kept for the warnings pane.
+    const WARNINGS: usize = 5;
+    /// Throughput is averaged over this long.
+    const RATE_WINDOW: Duration = Duration::from_secs(10);
+    const TICK: Duration = Duration::from_millis(250);
+    const BAR_WIDTH: usize = 30;
+
+    static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static WARNINGS_SEEN: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static RUNNING: Mutex<Option<Running>> = Mutex::new(None);
+
+    struct Running {
+        stop: Arc<AtomicBool>,
+        /// Errors as text, boxed errors can't leave the thread.
+        draw: JoinHandle<Result<(), String>>,
+        read: JoinHandle<()>,
+        /// The terminal standard output pointed at before the log was moved into a pipe.
+        terminal: RawFd,
+    }
+
+    pub fn start() -> SimpleResult<()> {
+        // The panes color warnings themselves.
+        console::set_color(false);
+        std::io::stdout().flush()?;
+        // Everything printed goes into a pipe read into the log pane, while the dashboard
+        // draws on the terminal standard output was.
+        let mut pipe = [0; 2];
+        let terminal = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if terminal < 0 || unsafe { libc::pipe(pipe.as_mut_ptr()) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        unsafe {
+            libc::dup2(pipe[1], libc::STDOUT_FILENO);
+            libc::close(pipe[1]);
+        }
+        let read = std::thread::spawn(move || {
+            let lines = BufReader::new(unsafe { File::from_raw_fd(pipe[0]) }).lines();
+            for line in lines.map_while(Result::ok) {
+                if line.starts_with("[WARN]") {
+                    push(&WARNINGS_SEEN, line.clone(), WARNINGS);
+                }
+                push(&LOG, line, LOG_LINES);
+            }
+        });
+
+        let out = unsafe { File::from_raw_fd(libc::dup(terminal)) };
+        let stop = Arc::new(AtomicBool::new(false));
+        let draw = std::thread::spawn({
+            let stop = stop.clone();
+            move || run(out, &stop).map_err(|e| e.to_string())
+        });
+
+        // A panic would otherwise leave the terminal unusable.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let mut out = unsafe { File::from_raw_fd(libc::dup(terminal)) };
+            let _ = restore(&mut out);
+            hook(info);
+        }));
+
+        *RUNNING.lock().unwrap() = Some(Running {
+            stop,
+            draw,
+            read,
+            terminal,
+        });
+        Ok(())
+    }
+
+    pub fn stop() -> SimpleResult<()> {
+        let running = match RUNNING.lock().unwrap().take() {
+            Some(running) => running,
+            None => return Ok(()),
+        };
+        running.stop.store(true, Ordering::Relaxed);
+        let drawn = running.draw.join().unwrap();
+        std::io::stdout().flush()?;
+        unsafe {
+            libc::dup2(running.terminal, libc::STDOUT_FILENO);
+            libc::close(running.terminal);
+        }
+        // Nothing writes into the pipe any more, so the reader sees it end.
+        let _ = running.read.join();
+        Ok(drawn?)
+    }
+
+    fn push(lines: &Mutex<VecDeque<String>>, line: String, keep: usize) {
+        let mut lines = lines.lock().unwrap();
+        if lines.len() == keep {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn restore(out: &mut File) -> SimpleResult<()> {
+        disable_raw_mode()?;
+        execute!(out, LeaveAlternateScreen)?;
+        Ok(())
+    }
+
+    fn run(mut out: File, stop: &AtomicBool) -> SimpleResult<()> {
+        enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(out.try_clone()?))?;
+        let result = draw_until(&mut terminal, &mut out, stop);
+        restore(&mut out)?;
+        result
+    }
+
+    fn draw_until(
+        terminal: &mut Terminal<CrosstermBackend<File>>,
+        out: &mut File,
+        stop: &AtomicBool,
+    ) -> SimpleResult<()> {
+        let started = Instant::now();
+        // Messages fetched and requests sent at each tick within the rate window.
+        let mut samples: VecDeque<(Instant, u64, u64)> = VecDeque::new();
+        while !stop.load(Ordering::Relaxed) {
+            let channels = progress::channels();
+            let now = Instant::now();
+            samples.push_back((
+                now,
+                channels.iter().map(|c| c.messages).sum(),
+                summary::requests(),
+            ));
+            while now - samples[0].0 > RATE_WINDOW {
+                samples.pop_front();
+            }
+            let (first, last) = (samples[0], samples[samples.len() - 1]);
+            let elapsed = (last.0 - first.0).as_secs_f64().max(TICK.as_secs_f64());
+            let rates = (
+                (last.1 - first.1) as f64 / elapsed,
+                (last.2 - first.2) as f64 / elapsed,
+            );
+
+            terminal.draw(|frame| draw(frame, &channels, rates, started.elapsed()))?;
+
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c)
+                    {
+                        if interrupt::requested() {
+                            restore(out)?;
+                            std::process::exit(130);
+                        }
+                        interrupt::request();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(
+        frame: &mut Frame,
+        channels: &[ChannelProgress],
+        (messages_per_second, requests_per_second): (f64, f64),
+        elapsed: Duration,
+    ) {
+        let [header, table, warnings, log, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(channels.len().min(12) as u16 + 3),
+            Constraint::Length(WARNINGS as u16 + 2),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let done = channels
+            .iter()
+            .filter(|c| matches!(c.status, Status::Done | Status::Failed))
+            .count();
+        let seconds = elapsed.as_secs();
+        frame.render_widget(
+            Paragraph::new(format!(
+                " {}/{} channels done | {:.1} messages/s | {:.1} requests/s | rate limited {} times, waited {:.1}s | {:02}:{:02}:{:02}",
+                done,
+                channels.len(),
+                messages_per_second,
+                requests_per_second,
+                summary::rate_limited(),
+                summary::rate_limit_wait().as_secs_f64(),
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            ))
+            .bold(),
+            header,
+        );
+
+        draw_channels(frame, table, channels);
+
+        let warnings_seen = WARNINGS_SEEN.lock().unwrap();
+        let lines: Vec<Line> = warnings_seen
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines)
+                .style(Style::new().fg(Color::Yellow))
+                .block(Block::new().borders(Borders::ALL).title(" Warnings ")),
+            warnings,
+        );
+
+        let log_lines = LOG.lock().unwrap();
+        let shown = log.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = log_lines
+            .iter()
+            .skip(log_lines.len().saturating_sub(shown))
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::new().borders(Borders::ALL).title(" Log ")),
+            log,
+        );
+
+        let hint = if interrupt::requested() {
+            " Stopping after the pages in flight, q or Ctrl-C again to exit now"
+        } else {
+            " q or Ctrl-C to stop"
+        };
+        frame.render_widget(Paragraph::new(hint).dim(), footer);
+    }
+
+    fn draw_channels(frame: &mut Frame, area: Rect, channels: &[ChannelProgress]) {
+        let now = cutoff::now_unix();
+        // Running channels first, so the busy ones stay in view.
+        let mut channels: Vec<&ChannelProgress> = channels.iter().collect();
+        channels.sort_by_key(|c| match c.status {
+            Status::Running => 0,
+            Status::Queued => 1,
+            Status::Failed => 2,
+            Status::Done => 3,
+        });
+        let rows = channels.iter().map(|channel| {
+            let fraction = channel.fraction(now);
+            let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+            let (status, color) = match channel.status {
+                Status::Queued => ("queued", Color::DarkGray),
+                Status::Running => ("running", Color::Cyan),
+                Status::Done => ("done", Color::Green),
+                Status::Failed => ("failed", Color::Red),
+            };
+            Row::new(vec![
+                channel
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| channel.id.to_string()),
+                status.to_string(),
+                channel.messages.to_string(),
+                format!(
+                    "{}{} {:>3.0}%",
+                    "█".repeat(filled),
+                    "░".repeat(BAR_WIDTH - filled),
+                    fraction * 100.0
+                ),
+            ])
+            .style(Style::new().fg(color))
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(24),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Min(BAR_WIDTH as u16 + 5),
+            ],
+        )
+        .header(Row::new(["Channel", "Status", "Messages", "Progress"]).bold())
+        .block(Block::new().borders(Borders::ALL).title(" Channels "));
+        frame.render_widget(table, area);
+    }
+}
+
+#[cfg(not(all(feature = "tui", unix)))]
+mod dashboard {
+    use crate::SimpleResult;
+
+    pub fn start() -> SimpleResult<()> {
+        unreachable!("the dashboard isn't started without the `tui` feature")
+    }
+
+    pub fn stop() -> SimpleResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use clap::Args;
+use hmac::{Hmac, Mac};
+use reqwest::blocking::Response;
+use sha2::{Digest, Sha256};
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::{cutoff, retry, SimpleResult};
+
+/// Files up to this size are sent in one request, larger ones in parts of it.
+const PART_SIZE: usize = 64 * 1024 * 1024;
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Debug, Args)]
+pub struct UploadArgs {
+    /// After a successful scrape or export, upload the database or exported files to this S3
+    /// location, e.g. s3://bucket/prefix, defaults to DISCORD_UPLOAD
+    #[clap(long, global = true, value_name = "URL")]
+    upload: Option<String>,
+
+    /// Upload to this S3-compatible service instead of AWS, e.g. http://localhost:9000 for
+    /// MinIO, defaults to AWS_ENDPOINT_URL
+    #[clap(long, global = true, value_name = "URL")]
+    s3_endpoint: Option<String>,
+}
+
+impl UploadArgs {
+    /// The uploader the flags and environment describe, or `None` when nothing is to be
+    /// uploaded. Credentials are read from the usual AWS_* variables.
+    pub fn uploader(&self) -> SimpleResult<Option<Uploader>> {
+        let url = match self
+            .upload
+            .clone()
+            .or_else(|| env::var("DISCORD_UPLOAD").ok())
+        {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let (bucket, prefix) = url
+            .strip_prefix("s3://")
+            .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+            .filter(|(bucket, _)| !bucket.is_empty())
+            .ok_or_else(|| format!("--upload must look like s3://bucket/prefix, got {}", url))?;
+
+        let variable = |name: &str| {
+            env::var(name).map_err(|_| format!("Uploading to {} needs {} to be set", url, name))
+        };
+        let access_key = variable("AWS_ACCESS_KEY_ID")?;
+        let secret_key = variable("AWS_SECRET_ACCESS_KEY")?;
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let endpoint = self
+            .s3_endpoint
+            .clone()
+            .or_else(|| env::var("AWS_ENDPOINT_URL_S3").ok())
+            .or_else(|| env::var("AWS_ENDPOINT_URL").ok());
+        // MinIO and most S3-compatible services take the bucket in the path, AWS in the host.
+        let base = match endpoint {
+            Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+        };
+        let base = reqwest::Url::parse(&base)
+            .map_err(|e| format!("Invalid S3 endpoint {}: {}", base, e))?;
+
+        Ok(Some(Uploader {
+            // Large parts take as long as they take.
+            client: reqwest::blocking::Client::builder().timeout(None).build()?,
+            base,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            region,
+            access_key,
+            secret_key,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        }))
+    }
+}
+
+pub struct Uploader {
+    client: reqwest::blocking::Client,
+    /// URL of the bucket.
+    base: reqwest::Url,
+    bucket: String,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Uploader {
+    /// Uploads a file, or each file in a directory, under the prefix by the same name.
+    pub fn upload(&self, path: &str) -> SimpleResult<()> {
+        let path = Path::new(path);
+        if !path.is_dir() {
+            return self.upload_file(path, &file_name(path));
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        for file in files {
+            self.upload_file(&file, &format!("{}/{}", file_name(path), file_name(&file)))?;
+        }
+        Ok(())
+    }
+
+    fn upload_file(&self, path: &Path, name: &str) -> SimpleResult<()> {
+        let key = if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", self.prefix, name)
+        };
+        let size = std::fs::metadata(path)?.len();
+        info!(
+            "Uploading {} ({} bytes) to s3://{}/{}",
+            path.display(),
+            size,
+            self.bucket,
+            key
+        );
+
+        let mut file = File::open(path)?;
+        if size <= PART_SIZE as u64 {
+            let mut body = Vec::new();
+            file.read_to_end(&mut body)?;
+            self.send("PUT", &key, &[], body)?;
+            return Ok(());
+        }
+
+        let created = self
+            .send("POST", &key, &[("uploads", "")], Vec::new())?
+            .text()?;
+        let upload_id = xml_value(&created, "UploadId")
+            .ok_or_else(|| format!("S3 started no multipart upload of {}", key))?;
+        let result = self.upload_parts(&mut file, &key, &upload_id);
+        if result.is_err() {
+            // Otherwise the parts sent so far are kept, and billed, until a lifecycle rule
+            // clears them.
+            let _ = self.send("DELETE", &key, &[("uploadId", &upload_id)], Vec::new());
+        }
+        result
+    }
+
+    fn upload_parts(&self, file: &mut File, key: &str, upload_id: &str) -> SimpleResult<()> {
+        let mut etags = Vec::new();
+        loop {
+            let mut part = Vec::with_capacity(PART_SIZE);
+            file.by_ref()
+                .take(PART_SIZE as u64)
+                .read_to_end(&mut part)?;
+            if part.is_empty() {
+                break;
+            }
+            let number = (etags.len() + 1).to_string();
+            let res = self.send(
+                "PUT",
+                key,
+                &[("partNumber", &number), ("uploadId", upload_id)],
+                part,
+            )?;
+            let etag = res
+                .headers()
+                .get("ETag")
+                .and_then(|etag| etag.to_str().ok())
+                .ok_or_else(|| format!("S3 returned no ETag for part {} of {}", number, key))?;
+            etags.push(etag.to_string());
+        }
+
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let completed = self
+            .send("POST", key, &[("uploadId", upload_id)], body.into_bytes())?
+            .text()?;
+        // Completing can still fail after S3 answered 200.
+        if completed.contains("<Error>") {
+            let err_msg = format!(
+                "Completing the upload of {} failed: {}",
+                key,
+                xml_value(&completed, "Message").unwrap_or(completed.clone())
+            );
+            return Err(err_msg.into());
+        }
+        Ok(())
+    }
+
+    /// Sends a request signed with AWS Signature Version 4, retried like requests to
+    /// Discord when it fails on the way or with a server error.
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> SimpleResult<Response> {
+        let mut url = self.base.clone();
+        url.set_path(&format!(
+            "{}/{}",
+            url.path().trim_end_matches('/'),
+            uri_encode(key, false)
+        ));
+        let mut query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+            .collect();
+        query.sort();
+        let query = query.join("&");
+        url.set_query(Some(&query).filter(|q| !q.is_empty()).map(|q| q.as_str()));
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let payload_hash = hex(&Sha256::digest(&body));
+        let method = reqwest::Method::from_bytes(method.as_bytes())?;
+
+        let mut attempt = 0;
+        loop {
+            let [year, month, day, hour, minute, second] =
+                cutoff::utc_from_unix(cutoff::now_unix());
+            let date = format!("{:04}{:02}{:02}", year, month, day);
+            let timestamp = format!("{}T{:02}{:02}{:02}Z", date, hour, minute, second);
+
+            // In the order of their names, as the signature wants them.
+            let mut headers = vec![
+                ("host", host.clone()),
+                ("x-amz-content-sha256", payload_hash.clone()),
+                ("x-amz-date", timestamp.clone()),
+            ];
+            if let Some(token) = &self.session_token {
+                headers.push(("x-amz-security-token", token.clone()));
+            }
+            let canonical_headers: String = headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+                .collect();
+            let signed_headers = headers
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(";");
+            let canonical_request = format!(
+                "{}\n{}\n{}\n{}\n{}\n{}",
+                method,
+                url.path(),
+                query,
+                canonical_headers,
+                signed_headers,
+                payload_hash
+            );
+            let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                timestamp,
+                scope,
+                hex(&Sha256::digest(canonical_request.as_bytes()))
+            );
+            let mut signing_key = hmac(
+                format!("AWS4{}", self.secret_key).as_bytes(),
+                date.as_bytes(),
+            );
+            for part in [self.region.as_str(), "s3", "aws4_request"] {
+                signing_key = hmac(&signing_key, part.as_bytes());
+            }
+            let authorization = format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key,
+                scope,
+                signed_headers,
+                hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+            );
+
+            let mut req = self
+                .client
+                .request(method.clone(), url.clone())
+                .header("Authorization", authorization)
+                .body(body.clone());
+            // The host header is added by reqwest.
+            for (name, value) in &headers[1..] {
+                req = req.header(*name, value);
+            }
+            let err_msg = match req.send() {
+                Ok(res) if res.status().is_success() => return Ok(res),
+                Ok(res) => {
+                    let status = res.status();
+                    let text = res.text().unwrap_or_default();
+                    let err_msg = format!(
+                        "Uploading to {} failed: {}",
+                        url,
+                        xml_value(&text, "Message").unwrap_or_else(|| status.to_string())
+                    );
+                    if !status.is_server_error() {
+                        return Err(err_msg.into());
+                    }
+                    err_msg
+                }
+                Err(e) => format!("Uploading to {} failed: {}", url, e),
+            };
+            match retry::backoff(attempt) {
+                Some(delay) => {
+                    warn!("{}. Retrying in {:.1}s.", err_msg, delay.as_secs_f64());
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                None => return Err(err_msg.into()),
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Percent-encodes all but unreserved characters, as the signature wants, and `/` too
+/// when `encode_slash` is set.
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The text of the first `<tag>` element of an S3 response.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].to_string())
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,59 @@
+use regex::Regex;
+
+use crate::enrich::Enricher;
+use crate::{Message, SimpleResult};
+
+/// Stores the links in each message in `message_url`, along with their domain.
+pub struct UrlExtractor {
+    pattern: Regex,
+}
+
+impl UrlExtractor {
+    pub fn new() -> Self {
+        UrlExtractor {
+            pattern: Regex::new(r"https?://[^\s<>]+").unwrap(),
+        }
+    }
+}
+
+impl Default for UrlExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enricher for UrlExtractor {
+    fn name(&self) -> &str {
+        "URL extraction"
+    }
+
+    fn enrich(&self, tx: &rusqlite::Transaction, messages: &[Message]) -> SimpleResult<()> {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR IGNORE INTO message_url (message_id, url, domain) VALUES (?,?,?)",
+        )?;
+        for message in messages {
+            for found in self.pattern.find_iter(&message.content) {
+                // Punctuation right after a link usually ends the sentence around it.
+                let url = found.as_str().trim_end_matches([
+                    '.', ',', ';', ':', '!', '?', ')', '>', '"', '\'', '*', '_', '|', '~',
+                ]);
+                stmt.execute(rusqlite::params![message.id, url, domain(url)])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The lowercased host of `url`, without credentials or port.
+fn domain(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    host.to_lowercase()
+}
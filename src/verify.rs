@@ -0,0 +1,107 @@
+use crate::exit::{self, Failure};
+use crate::SimpleResult;
+
+/// A consistency check and the query returning the IDs of the rows that fail it.
+const CHECKS: &[(&str, &str)] = &[
+    (
+        "Messages whose channel is missing",
+        "SELECT id FROM message WHERE channel_id IS NULL
+            OR channel_id NOT IN (SELECT id FROM channel)",
+    ),
+    (
+        "Messages whose author is missing",
+        "SELECT id FROM message WHERE author_id IS NULL
+            OR author_id NOT IN (SELECT id FROM user)",
+    ),
+    (
+        "Messages with a malformed timestamp",
+        "SELECT id FROM message
+         WHERE timestamp NOT GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]T[0-9][0-9]:[0-9][0-9]:[0-9][0-9]*'
+            OR julianday(timestamp) IS NULL",
+    ),
+    (
+        "Messages whose timestamp disagrees with their ID",
+        "SELECT id FROM message
+         WHERE julianday(timestamp) IS NOT NULL
+            AND abs(CAST(strftime('%s', timestamp) AS INTEGER) - created_at_unix) > 1",
+    ),
+    (
+        "Duplicate message IDs",
+        "SELECT id FROM message GROUP BY id HAVING COUNT(*) > 1",
+    ),
+    (
+        "Duplicate user IDs",
+        "SELECT id FROM user GROUP BY id HAVING COUNT(*) > 1",
+    ),
+    (
+        "Duplicate channel IDs",
+        "SELECT id FROM channel GROUP BY id HAVING COUNT(*) > 1",
+    ),
+];
+
+/// How many failing IDs are listed per check.
+const EXAMPLES: usize = 5;
+
+/// Prints a report of every check and fails if any of them found a problem.
+pub fn run(conn: &rusqlite::Connection) -> SimpleResult<()> {
+    let mut problems = 0;
+
+    let integrity: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if integrity == ["ok"] {
+        println!("[ OK ] SQLite integrity check");
+    } else {
+        problems += integrity.len();
+        println!("[FAIL] SQLite integrity check:");
+        for line in integrity {
+            println!("       {}", line);
+        }
+    }
+
+    let broken_references: i64 =
+        conn.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })?;
+    report_check(
+        "Broken foreign key references",
+        broken_references as usize,
+        &[],
+    );
+    problems += broken_references as usize;
+
+    for (name, sql) in CHECKS {
+        let ids: Vec<i64> = conn
+            .prepare(sql)?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        report_check(name, ids.len(), &ids);
+        problems += ids.len();
+    }
+
+    if problems > 0 {
+        let err_msg = format!("Verification found {} problems", problems);
+        return Err(Failure::new(exit::DATABASE, err_msg).into());
+    }
+
+    info!("No problems found");
+    Ok(())
+}
+
+fn report_check(name: &str, failures: usize, ids: &[i64]) {
+    if failures == 0 {
+        println!("[ OK ] {}", name);
+        return;
+    }
+
+    println!("[FAIL] {}: {}", name, failures);
+    if !ids.is_empty() {
+        let examples: Vec<String> = ids.iter().take(EXAMPLES).map(|id| id.to_string()).collect();
+        println!(
+            "       e.g. {}{}",
+            examples.join(", "),
+            if ids.len() > EXAMPLES { ", ..." } else { "" }
+        );
+    }
+}
@@ -0,0 +1,137 @@
+// Browses the archive through the JSON endpoints of `serve`. Message content is only ever
+// set as text, never as HTML, so archived messages can't inject markup into the page.
+const channelList = document.getElementById("channels");
+const title = document.getElementById("title");
+const messageList = document.getElementById("messages");
+const olderButton = document.getElementById("older");
+const main = document.querySelector("main");
+const PAGE_SIZE = 100;
+
+// The open channel and the oldest message shown from it, for loading older pages.
+let current = null;
+
+async function get(path) {
+  const response = await fetch(path);
+  const body = await response.json();
+  if (!response.ok) {
+    throw new Error(body.error);
+  }
+  return body;
+}
+
+function element(tag, className, text) {
+  const node = document.createElement(tag);
+  if (className) node.className = className;
+  if (text !== undefined) node.textContent = text;
+  return node;
+}
+
+function renderMessage(message, withChannel) {
+  const node = element("div", "message");
+  node.id = "message-" + message.id;
+  node.append(element("span", "author", message.author));
+  let meta = message.timestamp.replace("T", " ").slice(0, 19);
+  if (withChannel) meta += " in #" + message.channel_name;
+  if (message.reply_to) meta += " (reply)";
+  if (message.origin_channel_id) {
+    meta += (message.forwarded ? " (forwarded from " : " (crossposted from ") + message.origin_channel_id + ")";
+  }
+  if (message.voice_duration_secs !== null) {
+    meta += " (voice message, " + Math.round(message.voice_duration_secs) + "s)";
+  }
+  if (message.deleted) {
+    meta += " (deleted)";
+  }
+  node.append(element("span", "meta", meta));
+  node.append(element("div", "content", message.content));
+  return node;
+}
+
+async function loadChannels() {
+  const channels = await get("/channels");
+  let guild;
+  for (const channel of channels) {
+    const guildName = channel.guild_name || (channel.guild_id ? channel.guild_id : "Direct Messages");
+    if (guildName !== guild) {
+      guild = guildName;
+      channelList.append(element("li", "guild", guild));
+    }
+    const link = element("a", null, "#" + (channel.name || channel.id));
+    link.href = "#channel-" + channel.id;
+    link.dataset.id = channel.id;
+    link.append(element("span", "count", channel.message_count));
+    const item = element("li");
+    item.append(link);
+    channelList.append(item);
+  }
+}
+
+async function openChannel(id) {
+  for (const link of channelList.querySelectorAll("a")) {
+    link.classList.toggle("active", link.dataset.id === id);
+  }
+  const link = channelList.querySelector(`a[data-id="${id}"]`);
+  title.textContent = link ? link.firstChild.textContent : "#" + id;
+  messageList.replaceChildren();
+  current = { id, oldest: null };
+  await loadOlder();
+  main.scrollTop = main.scrollHeight;
+}
+
+async function loadOlder() {
+  let path = `/channels/${current.id}/messages?limit=${PAGE_SIZE}`;
+  if (current.oldest) path += "&before=" + current.oldest;
+  const messages = await get(path);
+  // Pages arrive newest first, the page reads oldest first. Keep the messages already
+  // shown where they were instead of jumping to the top.
+  const fromBottom = main.scrollHeight - main.scrollTop;
+  for (const message of messages) {
+    messageList.prepend(renderMessage(message, false));
+  }
+  main.scrollTop = main.scrollHeight - fromBottom;
+  if (messages.length > 0) {
+    current.oldest = messages[messages.length - 1].id;
+  } else if (!messageList.hasChildNodes()) {
+    messageList.append(element("p", "empty", "No messages"));
+  }
+  olderButton.hidden = messages.length < PAGE_SIZE;
+}
+
+async function search(q) {
+  current = null;
+  olderButton.hidden = true;
+  for (const link of channelList.querySelectorAll("a")) {
+    link.classList.remove("active");
+  }
+  title.textContent = `Search results for "${q}"`;
+  messageList.replaceChildren();
+  const messages = await get("/search?limit=100&q=" + encodeURIComponent(q));
+  for (const message of messages) {
+    messageList.append(renderMessage(message, true));
+  }
+  if (messages.length === 0) {
+    messageList.append(element("p", "empty", "Nothing found"));
+  }
+}
+
+function showError(error) {
+  messageList.replaceChildren(element("p", "empty", error.message));
+}
+
+function route() {
+  const match = location.hash.match(/^#channel-(\d+)$/);
+  if (match) openChannel(match[1]).catch(showError);
+}
+
+document.getElementById("search").addEventListener("submit", (event) => {
+  event.preventDefault();
+  const q = event.target.q.value.trim();
+  if (q) {
+    history.replaceState(null, "", location.pathname);
+    search(q).catch(showError);
+  }
+});
+olderButton.addEventListener("click", () => loadOlder().catch(showError));
+window.addEventListener("hashchange", route);
+
+loadChannels().then(route).catch(showError);
@@ -0,0 +1,104 @@
+use discord_scraper::anonymize::Anonymizer;
+use discord_scraper::redact::RedactArgs;
+use discord_scraper::snowflake::Snowflake;
+use discord_scraper::User;
+use regex::Regex;
+
+use std::path::PathBuf;
+
+fn salt_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("anonymize-{}-{}.salt", std::process::id(), name))
+}
+
+fn user(id: i64, username: &str) -> User {
+    User {
+        id: Snowflake(id),
+        username: username.to_string(),
+        discriminator: Some("0".to_string()),
+        global_name: Some("Ada".to_string()),
+    }
+}
+
+#[test]
+fn salts_map_users_to_stable_pseudonyms() {
+    let path = salt_path("stable");
+    let first = Anonymizer::load(path.to_str().unwrap()).unwrap();
+    // The salt created on first use is read back by later runs.
+    let again = Anonymizer::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let other_path = salt_path("other");
+    let other = Anonymizer::load(other_path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&other_path).unwrap();
+
+    let pseudonym = first.user(user(10, "ada"));
+    assert_eq!(pseudonym, again.user(user(10, "ada")));
+    assert_ne!(pseudonym.id, Snowflake(10));
+    assert!(pseudonym.id.0 > 0);
+    assert!(pseudonym.username.starts_with("user-"));
+    assert_eq!(
+        (pseudonym.discriminator, pseudonym.global_name),
+        (None, None)
+    );
+    assert_ne!(first.user(user(20, "grace")).id, pseudonym.id);
+    assert_ne!(other.user(user(10, "ada")).id, pseudonym.id);
+}
+
+#[test]
+fn rejects_invalid_salts() {
+    for (name, salt) in [
+        ("empty", " \n"),
+        ("odd", "abc"),
+        ("sign", "+1"),
+        ("unicode", "é0"),
+    ] {
+        let path = salt_path(name);
+        std::fs::write(&path, salt).unwrap();
+        let loaded = Anonymizer::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err(), "{:?} was accepted", salt);
+    }
+}
+
+#[test]
+fn strips_user_mentions() {
+    let path = salt_path("mentions");
+    let anonymizer = Anonymizer::load(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        anonymizer.content("<@10> and <@!20>, see <#30> and <@&40>"),
+        "@user and @user, see <#30> and <@&40>"
+    );
+}
+
+#[test]
+fn redacts_built_in_and_custom_patterns() {
+    let redactor = RedactArgs {
+        redact: true,
+        redact_patterns: vec![Regex::new(r"(?i)order #(\d+)").unwrap()],
+    }
+    .redactor()
+    .unwrap();
+    assert_eq!(
+        redactor
+            .redact("Mail ada@example.com or call 555-123-4567 about Order #42, ship to 12 Main Street"),
+        "Mail [REDACTED EMAIL] or call [REDACTED PHONE] about [REDACTED], ship to [REDACTED ADDRESS]"
+    );
+
+    let none = RedactArgs {
+        redact: false,
+        redact_patterns: Vec::new(),
+    };
+    assert!(none.redactor().is_none());
+    let custom = RedactArgs {
+        redact: false,
+        redact_patterns: vec![Regex::new(r"secret (\w+)").unwrap()],
+    }
+    .redactor()
+    .unwrap();
+    // Only the given patterns apply without --redact.
+    assert_eq!(
+        custom.redact("a secret plan for ada@example.com"),
+        "a [REDACTED] for ada@example.com"
+    );
+}
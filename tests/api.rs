@@ -0,0 +1,568 @@
+use discord_scraper::api::{ApiClient, ApiResponse, ReplayClient};
+use discord_scraper::client::{self, TokenType};
+use discord_scraper::cutoff::Cutoff;
+use discord_scraper::snowflake::Snowflake;
+use discord_scraper::summary::ChannelSummary;
+use discord_scraper::{
+    db, exit, get_channel_messages, read, refresh, retry, send_request, BASE_URL,
+};
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::sync::Once;
+
+/// Tokens and the retry policy are set once for the whole process, and rate limits are
+/// shared by every test, so each test uses channels of its own.
+fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        client::set_tokens(TokenType::User, vec!["token".to_string()]).unwrap();
+        retry::set_policy(2, 0.0, 0.0).unwrap();
+        retry::set_max_rate_limit_wait(60.0).unwrap();
+    });
+}
+
+fn message(id: i64, channel_id: i64) -> serde_json::Value {
+    json!({
+        "id": id.to_string(),
+        "channel_id": channel_id.to_string(),
+        "author": {"id": "10", "username": "ada", "discriminator": "0"},
+        "content": format!("Message {}", id),
+        "timestamp": "2024-01-01T12:00:00.000000+00:00",
+    })
+}
+
+fn response(status: u16, headers: &[(&str, &str)], body: &str) -> ApiResponse {
+    ApiResponse {
+        status,
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>(),
+        body: body.to_string(),
+    }
+}
+
+fn database(channel_id: i64) -> rusqlite::Connection {
+    let conn = db::connect_db(":memory:").unwrap();
+    conn.execute(
+        "INSERT INTO channel (id, guild_id, name) VALUES (?, NULL, 'general')",
+        [channel_id.to_string()],
+    )
+    .unwrap();
+    conn
+}
+
+fn scrape(
+    conn: &mut rusqlite::Connection,
+    client: &dyn ApiClient,
+    channel_id: i64,
+) -> ChannelSummary {
+    let channel_id = Snowflake(channel_id);
+    let mut summary = ChannelSummary::new(Some(channel_id), None);
+    get_channel_messages(conn, client, channel_id, None, None, &mut summary).unwrap();
+    summary
+}
+
+fn stored_ids(conn: &rusqlite::Connection) -> Vec<i64> {
+    let mut stmt = conn
+        .prepare("SELECT CAST(id AS INTEGER) FROM message ORDER BY CAST(id AS INTEGER)")
+        .unwrap();
+    let ids = stmt.query_map([], |row| row.get(0)).unwrap();
+    ids.map(Result::unwrap).collect()
+}
+
+#[test]
+fn scrapes_replayed_channel() {
+    setup();
+    let client = ReplayClient::from_file("tests/fixtures/channel_1.json").unwrap();
+    let mut conn = database(1);
+
+    let summary = scrape(&mut conn, &client, 1);
+
+    assert_eq!(summary.new, 3);
+    assert_eq!(stored_ids(&conn), vec![11, 12, 13]);
+    assert_eq!(
+        client.requests(),
+        vec![
+            "/channels/1/messages?limit=100",
+            "/channels/1/messages?limit=100&before=11",
+        ]
+    );
+}
+
+#[test]
+fn pages_until_first_message() {
+    setup();
+    let client = ReplayClient::new();
+    let page = |ids: std::ops::RangeInclusive<i64>| {
+        json!(ids.rev().map(|id| message(id, 2)).collect::<Vec<_>>())
+    };
+    client.push_json("/channels/2/messages?limit=100", 200, page(151..=250));
+    client.push_json(
+        "/channels/2/messages?limit=100&before=151",
+        200,
+        page(101..=150),
+    );
+    client.push_json("/channels/2/messages?limit=100&before=101", 200, json!([]));
+    let mut conn = database(2);
+
+    let summary = scrape(&mut conn, &client, 2);
+
+    assert_eq!(summary.new, 150);
+    assert_eq!(stored_ids(&conn), (101..=250).collect::<Vec<_>>());
+    assert_eq!(client.requests().len(), 3);
+
+    // A later run only asks for what's newer than the newest stored message.
+    client.push_json("/channels/2/messages?limit=100", 200, page(250..=251));
+    let summary = scrape(&mut conn, &client, 2);
+
+    assert_eq!(summary.new, 1);
+    assert_eq!(client.requests().len(), 4);
+}
+
+#[test]
+fn refreshes_edits_reactions_and_deletions() {
+    setup();
+    let client = ReplayClient::new();
+    client.push_json(
+        "/channels/8/messages?limit=100",
+        200,
+        json!([message(83, 8), message(82, 8), message(81, 8)]),
+    );
+    client.push_json("/channels/8/messages?limit=100&before=81", 200, json!([]));
+    let mut conn = database(8);
+    scrape(&mut conn, &client, 8);
+
+    // Since then 83 was edited and reacted to, and 82 deleted.
+    let mut edited = message(83, 8);
+    edited["content"] = json!("Message 83, edited");
+    edited["edited_timestamp"] = json!("2024-01-01T12:05:00.000000+00:00");
+    edited["reactions"] = json!([{
+        "count": 2,
+        "count_details": {"burst": 1, "normal": 1},
+        "burst_colors": ["#ffcc4d"],
+        "emoji": {"id": null, "name": "👍"},
+    }]);
+    client.push_json(
+        "/channels/8/messages?limit=100",
+        200,
+        json!([edited, message(81, 8)]),
+    );
+    client.push_json("/channels/8/messages?limit=100&before=81", 200, json!([]));
+    let mut summary = ChannelSummary::new(Some(Snowflake(8)), None);
+    refresh::run(
+        &mut conn,
+        &client,
+        Snowflake(8),
+        Cutoff(0),
+        None,
+        None,
+        &mut summary,
+    )
+    .unwrap();
+
+    assert_eq!(summary.updated, 2);
+    let rows: Vec<(i64, String, bool)> = conn
+        .prepare("SELECT id, content, deleted FROM message ORDER BY id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (81, "Message 81".to_string(), false),
+            (82, "Message 82".to_string(), true),
+            (83, "Message 83, edited".to_string(), false),
+        ]
+    );
+    let reactions: (String, i64, i64, String) = conn
+        .query_row(
+            "SELECT emoji, count, burst_count, burst_colors FROM message_reaction
+             WHERE message_id = 83",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        reactions,
+        ("👍".to_string(), 2, 1, r##"["#ffcc4d"]"##.to_string())
+    );
+    let revision: (i64, String) = conn
+        .query_row(
+            "SELECT message_id, content FROM message_revision",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(revision, (83, "Message 83".to_string()));
+}
+
+#[test]
+fn keeps_usernames_current() {
+    setup();
+    let client = ReplayClient::new();
+    client.push_json(
+        "/channels/9/messages?limit=100",
+        200,
+        json!([message(91, 9)]),
+    );
+    client.push_json("/channels/9/messages?limit=100&before=91", 200, json!([]));
+    let mut conn = database(9);
+    scrape(&mut conn, &client, 9);
+
+    // Messages come with their author as they are now.
+    let page: Vec<_> = [92, 91]
+        .into_iter()
+        .map(|id| {
+            let mut message = message(id, 9);
+            message["author"]["username"] = json!("ada_l");
+            message["author"]["global_name"] = json!("Ada");
+            message
+        })
+        .collect();
+    client.push_json("/channels/9/messages?limit=100", 200, json!(page));
+    scrape(&mut conn, &client, 9);
+
+    let user: (String, Option<String>, Option<String>) = conn
+        .query_row(
+            "SELECT username, discriminator, global_name FROM user WHERE id = 10",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(user, ("ada_l".to_string(), None, Some("Ada".to_string())));
+    let history: Vec<String> = conn
+        .prepare("SELECT username FROM user_history WHERE user_id = 10")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(history, vec!["ada"]);
+}
+
+#[test]
+fn stores_crosspost_and_forward_origins() {
+    setup();
+    let client = ReplayClient::new();
+    let mut crosspost = message(112, 11);
+    crosspost["flags"] = json!(2);
+    crosspost["message_reference"] = json!({"message_id": "5", "channel_id": "6", "guild_id": "7"});
+    // Channel follow notices reference the followed channel without being crossposts.
+    let mut follow = message(111, 11);
+    follow["type"] = json!(12);
+    follow["message_reference"] = json!({"channel_id": "6", "guild_id": "7"});
+    // Forwards carry the content of what they forward in a snapshot, and have none themselves.
+    let mut forward = message(113, 11);
+    forward["content"] = json!("");
+    forward["flags"] = json!(1 << 14);
+    forward["message_reference"] =
+        json!({"type": 1, "message_id": "8", "channel_id": "9", "guild_id": "7"});
+    forward["message_snapshots"] = json!([{"message": {"content": "Forwarded"}}]);
+    client.push_json(
+        "/channels/11/messages?limit=100",
+        200,
+        json!([forward, crosspost, follow]),
+    );
+    client.push_json("/channels/11/messages?limit=100&before=111", 200, json!([]));
+    let mut conn = database(11);
+    scrape(&mut conn, &client, 11);
+
+    let origin = |id: i64| -> (i64, Option<i64>, Option<i64>, Option<i64>) {
+        conn.query_row(
+            "SELECT flags, origin_guild_id, origin_channel_id, origin_message_id
+             FROM message WHERE id = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap()
+    };
+    assert_eq!(origin(112), (2, Some(7), Some(6), Some(5)));
+    assert_eq!(origin(111), (0, None, None, None));
+    assert_eq!(origin(113), (1 << 14, Some(7), Some(9), Some(8)));
+    let content: String = conn
+        .query_row("SELECT content FROM message WHERE id = 113", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(content, "Forwarded");
+}
+
+#[test]
+fn stores_voice_message_recordings() {
+    setup();
+    let client = ReplayClient::new();
+    let mut voice = message(152, 15);
+    voice["content"] = json!("");
+    voice["flags"] = json!(1 << 13);
+    voice["attachments"] = json!([{"id": "9", "filename": "voice-message.ogg", "duration_secs": 14.2, "waveform": "AAoU"}]);
+    // Only voice messages have their attachment's recording stored.
+    let mut file = message(151, 15);
+    file["attachments"] = json!([{"id": "8", "filename": "clip.ogg", "duration_secs": 3.0}]);
+    client.push_json("/channels/15/messages?limit=100", 200, json!([voice, file]));
+    client.push_json("/channels/15/messages?limit=100&before=151", 200, json!([]));
+    let mut conn = database(15);
+    scrape(&mut conn, &client, 15);
+
+    let recording = |id: i64| -> (Option<f64>, Option<String>) {
+        conn.query_row(
+            "SELECT voice_duration_secs, voice_waveform FROM message WHERE id = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    };
+    assert_eq!(recording(152), (Some(14.2), Some("AAoU".to_string())));
+    assert_eq!(recording(151), (None, None));
+    let stored = read::messages_by_author(&conn, Snowflake(10)).unwrap();
+    let durations: Vec<Option<f64>> = stored.iter().map(|m| m.voice_duration_secs).collect();
+    assert_eq!(durations, vec![None, Some(14.2)]);
+}
+
+#[test]
+fn leaves_out_forgotten_users() {
+    setup();
+    let client = ReplayClient::new();
+    let mut reply = message(122, 12);
+    reply["author"] = json!({"id": "20", "username": "grace", "discriminator": "0"});
+    reply["content"] = json!("<@10> and <@!10>, see above");
+    client.push_json(
+        "/channels/12/messages?limit=100",
+        200,
+        json!([reply, message(121, 12)]),
+    );
+    client.push_json("/channels/12/messages?limit=100&before=121", 200, json!([]));
+    let mut conn = database(12);
+    conn.execute(
+        "INSERT INTO forgotten_user (user_id, messages, anonymized) VALUES (10, 0, 0)",
+        [],
+    )
+    .unwrap();
+    scrape(&mut conn, &client, 12);
+
+    assert_eq!(stored_ids(&conn), vec![122]);
+    let content: String = conn
+        .query_row("SELECT content FROM message WHERE id = 122", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(content, "@Deleted User and @Deleted User, see above");
+}
+
+#[test]
+fn refresh_leaves_out_forgotten_users() {
+    setup();
+    let client = ReplayClient::new();
+    let mut reply = message(162, 16);
+    reply["author"] = json!({"id": "20", "username": "grace", "discriminator": "0"});
+    reply["content"] = json!("<@10> see above");
+    client.push_json(
+        "/channels/16/messages?limit=100",
+        200,
+        json!([reply.clone(), message(161, 16)]),
+    );
+    client.push_json("/channels/16/messages?limit=100&before=161", 200, json!([]));
+    let mut conn = database(16);
+    scrape(&mut conn, &client, 16);
+    conn.execute(
+        "INSERT INTO forgotten_user (user_id, messages, anonymized) VALUES (10, 1, 0)",
+        [],
+    )
+    .unwrap();
+    conn.execute_batch(
+        "DELETE FROM message WHERE author_id = 10;
+        UPDATE message SET content = '@Deleted User see above' WHERE id = 162;",
+    )
+    .unwrap();
+
+    // The reply was edited since, still mentioning the forgotten user.
+    reply["content"] = json!("<@10> see above, again");
+    reply["edited_timestamp"] = json!("2024-01-01T12:05:00.000000+00:00");
+    client.push_json(
+        "/channels/16/messages?limit=100",
+        200,
+        json!([reply, message(161, 16)]),
+    );
+    client.push_json("/channels/16/messages?limit=100&before=161", 200, json!([]));
+    let mut summary = ChannelSummary::new(Some(Snowflake(16)), None);
+    refresh::run(
+        &mut conn,
+        &client,
+        Snowflake(16),
+        Cutoff(0),
+        None,
+        None,
+        &mut summary,
+    )
+    .unwrap();
+
+    assert_eq!(stored_ids(&conn), vec![162]);
+    let contents: Vec<String> = conn
+        .prepare(
+            "SELECT content FROM message
+             UNION ALL SELECT content FROM message_revision",
+        )
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        contents,
+        vec!["@Deleted User see above, again", "@Deleted User see above"]
+    );
+}
+
+#[test]
+fn reads_stored_messages() {
+    setup();
+    let client = ReplayClient::new();
+    let mut other = message(133, 13);
+    other["author"] = json!({"id": "20", "username": "grace", "discriminator": "0"});
+    client.push_json(
+        "/channels/13/messages?limit=100",
+        200,
+        json!([other, message(132, 13), message(131, 13)]),
+    );
+    client.push_json("/channels/13/messages?limit=100&before=131", 200, json!([]));
+    let mut conn = database(13);
+    scrape(&mut conn, &client, 13);
+
+    let ids = |messages: Vec<read::StoredMessage>| -> Vec<i64> {
+        messages.iter().map(|m| m.id.0).collect()
+    };
+    let all = read::messages_in_range(&conn, Cutoff(0), Cutoff(i64::MAX), Some(Snowflake(13)));
+    assert_eq!(ids(all.unwrap()), vec![131, 132, 133]);
+    let none = read::messages_in_range(&conn, Cutoff(0), Cutoff(1), None);
+    assert!(ids(none.unwrap()).is_empty());
+    let by_author = read::messages_by_author(&conn, Snowflake(20));
+    assert_eq!(ids(by_author.unwrap()), vec![133]);
+
+    let summary = read::channel_summary(&conn, Snowflake(13))
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.message_count, 3);
+    assert_eq!(summary.last_message_id, Some(Snowflake(133)));
+    assert!(read::channel_summary(&conn, Snowflake(14))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn parses_cutoffs() {
+    let before = discord_scraper::cutoff::now_unix();
+    let week: Cutoff = "1w".parse().unwrap();
+    assert!((before - 7 * 86400..=before - 7 * 86400 + 1).contains(&week.0));
+    assert_eq!("2021-06-30".parse::<Cutoff>().unwrap().0, 1625011200);
+    for invalid in ["999999999999999d", "12x", "10000-01-01", "2021-13-01"] {
+        assert!(
+            invalid.parse::<Cutoff>().is_err(),
+            "{} was accepted",
+            invalid
+        );
+    }
+    // Times past what IDs can hold compare as the highest ID rather than overflowing.
+    let last = Snowflake::first_at_unix("9999-12-31".parse::<Cutoff>().unwrap().0);
+    assert_eq!(last, Snowflake(i64::MAX >> 22 << 22));
+}
+
+#[test]
+fn retries_rate_limited_requests() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/3/messages?limit=100";
+    client.push(
+        path,
+        response(
+            429,
+            &[("retry-after", "0.05"), ("x-ratelimit-scope", "user")],
+            r#"{"message": "You are being rate limited.", "retry_after": 0.05, "global": false}"#,
+        ),
+    );
+    client.push(path, response(200, &[], "[]"));
+
+    let body = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap();
+
+    assert_eq!(body, "[]");
+    assert_eq!(client.requests(), vec![path, path]);
+}
+
+#[test]
+fn gives_up_on_long_rate_limits() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/10/messages?limit=100";
+    client.push(path, response(429, &[("retry-after", "3600")], ""));
+
+    let err = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap_err();
+
+    assert_eq!(exit::code(&*err), exit::RATE_LIMITED);
+    assert_eq!(client.requests().len(), 1);
+}
+
+#[test]
+fn rate_limit_without_retry_after_fails() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/4/messages?limit=100";
+    client.push(path, response(429, &[], ""));
+
+    let err = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap_err();
+
+    assert_eq!(err.to_string(), "Rate limited without a Retry-After header");
+}
+
+#[test]
+fn decodes_discord_errors() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/5/messages?limit=100";
+    client.push_json(
+        path,
+        404,
+        json!({"message": "Unknown Channel", "code": 10003}),
+    );
+
+    let err = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap_err();
+
+    assert!(err.to_string().ends_with(": Unknown Channel"), "{}", err);
+    assert_eq!(exit::code(&*err), exit::CHANNEL);
+    // Client errors aren't retried.
+    assert_eq!(client.requests().len(), 1);
+}
+
+#[test]
+fn retries_server_errors() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/6/messages?limit=100";
+    client.push(path, response(502, &[], "<html>Bad Gateway</html>"));
+    client.push(path, response(200, &[], "[]"));
+
+    let body = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap();
+
+    assert_eq!(body, "[]");
+    assert_eq!(client.requests().len(), 2);
+}
+
+#[test]
+fn gives_up_after_retries() {
+    setup();
+    let client = ReplayClient::new();
+    let path = "/channels/7/messages?limit=100";
+    for _ in 0..3 {
+        client.push(path, response(503, &[], "upstream connect error"));
+    }
+
+    let err = send_request(&client, &format!("{}{}", BASE_URL, path)).unwrap_err();
+
+    assert!(
+        err.to_string().ends_with("503 Service Unavailable"),
+        "{}",
+        err
+    );
+    assert_eq!(client.requests().len(), 3);
+}
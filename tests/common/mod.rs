@@ -0,0 +1,55 @@
+//! Helpers for tests that run the binary on databases of their own.
+
+use discord_scraper::db;
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A database of its own for each test, removed with its WAL files once the test is done.
+pub struct TempDb(pub PathBuf);
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("scraper-{}-{}.db", std::process::id(), name));
+        TempDb(path)
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    /// Creates the database with a channel, ada (10) and grace (20), and `messages`.
+    pub fn create(&self, messages: &[(i64, i64, &str)]) -> rusqlite::Connection {
+        let conn = db::connect_db(&self.0).unwrap();
+        conn.execute_batch(
+            "INSERT INTO channel (id, guild_id, name) VALUES (1, NULL, 'general');
+             INSERT INTO user (id, username, discriminator) VALUES (10, 'ada', '0'), (20, 'grace', '0');",
+        )
+        .unwrap();
+        for (id, author_id, content) in messages {
+            conn.execute(
+                "INSERT INTO message (id, channel_id, author_id, content, timestamp, created_at_unix)
+                 VALUES (?, 1, ?, ?, '2024-01-01T12:00:00.000000+00:00', 1704110400)",
+                rusqlite::params![id, author_id, content],
+            )
+            .unwrap();
+        }
+        conn
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path(), suffix));
+        }
+    }
+}
+
+/// Runs the scraper's binary with `args`.
+pub fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_discord-scraper"))
+        .args(args)
+        .output()
+        .unwrap()
+}
@@ -0,0 +1,96 @@
+use discord_scraper::api::ReplayClient;
+use discord_scraper::client::{self, TokenType};
+use discord_scraper::enrich::{self, Enricher};
+use discord_scraper::snowflake::Snowflake;
+use discord_scraper::summary::ChannelSummary;
+use discord_scraper::urls::UrlExtractor;
+use discord_scraper::{db, get_channel_messages, Message, SimpleResult};
+use serde_json::json;
+
+/// Counts the characters of each message into a table of its own.
+struct Length;
+
+impl Enricher for Length {
+    fn name(&self) -> &str {
+        "Length"
+    }
+
+    fn setup(&self, conn: &rusqlite::Connection) -> SimpleResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS message_length (message_id INTEGER PRIMARY KEY, length INTEGER)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn enrich(&self, tx: &rusqlite::Transaction, messages: &[Message]) -> SimpleResult<()> {
+        for message in messages {
+            tx.execute(
+                "INSERT OR REPLACE INTO message_length (message_id, length) VALUES (?,?)",
+                rusqlite::params![message.id, message.content.chars().count()],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn enrichers_run_on_stored_pages() {
+    client::set_tokens(TokenType::User, vec!["token".to_string()]).unwrap();
+    enrich::register(UrlExtractor::new());
+    enrich::register(Length);
+
+    let client = ReplayClient::new();
+    let message = |id: &str, content: &str| {
+        json!({
+            "id": id,
+            "channel_id": "1",
+            "author": {"id": "10", "username": "ada", "discriminator": "0"},
+            "content": content,
+            "timestamp": "2024-01-01T12:00:00.000000+00:00",
+        })
+    };
+    client.push_json(
+        "/channels/1/messages?limit=100",
+        200,
+        json!([
+            message("12", "Notes are at https://Docs.example.com/notes."),
+            message("11", "hi"),
+        ]),
+    );
+    client.push_json("/channels/1/messages?limit=100&before=11", 200, json!([]));
+
+    let mut conn = db::connect_db(":memory:").unwrap();
+    conn.execute(
+        "INSERT INTO channel (id, guild_id, name) VALUES (1, NULL, 'general')",
+        [],
+    )
+    .unwrap();
+    let mut summary = ChannelSummary::new(Some(Snowflake(1)), None);
+    get_channel_messages(&mut conn, &client, Snowflake(1), None, None, &mut summary).unwrap();
+
+    let urls: Vec<(i64, String, String)> = conn
+        .prepare("SELECT message_id, url, domain FROM message_url")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        urls,
+        vec![(
+            12,
+            "https://Docs.example.com/notes".to_string(),
+            "docs.example.com".to_string()
+        )]
+    );
+
+    let lengths: Vec<(i64, i64)> = conn
+        .prepare("SELECT message_id, length FROM message_length ORDER BY message_id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lengths, vec![(11, 2), (12, 44)]);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::TempDb;
+use discord_scraper::db;
+
+#[test]
+fn forgets_mentions_outside_messages() {
+    let archive = TempDb::new("forget");
+    let conn = archive.create(&[(100, 20, "Reported <@10>")]);
+    conn.execute_batch(
+        "INSERT INTO channel (id, guild_id, name) VALUES (2, NULL, 'Direct Message with ada#0');
+         INSERT INTO case_record (id, name) VALUES (1, 'Raid');
+         INSERT INTO case_message (case_id, message_id, note) VALUES (1, 100, 'Same as <@!10> last week');
+         INSERT INTO message_field (message_id, name, value)
+         VALUES (100, 'summary', 'Complains about <@10>'), (100, 'score', 10);",
+    )
+    .unwrap();
+    drop(conn);
+
+    let output = common::run(&["forget-user", "10", "-d", archive.path()]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let conn = db::open_read_only(archive.path()).unwrap();
+    let text = |sql: &str| -> String { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+    assert_eq!(
+        text("SELECT content FROM message WHERE id = 100"),
+        "Reported @Deleted User"
+    );
+    assert_eq!(
+        text("SELECT note FROM case_message"),
+        "Same as @Deleted User last week"
+    );
+    assert_eq!(
+        text("SELECT value FROM message_field WHERE name = 'summary'"),
+        "Complains about @Deleted User"
+    );
+    let score: i64 = conn
+        .query_row(
+            "SELECT value FROM message_field WHERE name = 'score'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(score, 10);
+    assert_eq!(
+        text("SELECT name FROM channel WHERE id = 2"),
+        "Direct Message with Deleted User"
+    );
+}
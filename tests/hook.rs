@@ -0,0 +1,59 @@
+use discord_scraper::api::ReplayClient;
+use discord_scraper::client::{self, TokenType};
+use discord_scraper::redact::RedactArgs;
+use discord_scraper::snowflake::Snowflake;
+use discord_scraper::summary::ChannelSummary;
+use discord_scraper::{db, get_channel_messages, hook};
+use serde_json::json;
+
+#[test]
+fn hooks_get_forwards_redacted() {
+    client::set_tokens(TokenType::User, vec!["token".to_string()]).unwrap();
+    let output = std::env::temp_dir().join(format!("hook-{}.json", std::process::id()));
+    hook::set_command(format!("cat > '{}'", output.display())).unwrap();
+
+    let client = ReplayClient::new();
+    client.push_json(
+        "/channels/1/messages?limit=100",
+        200,
+        json!([{
+            "id": "11",
+            "channel_id": "1",
+            "author": {"id": "10", "username": "ada", "discriminator": "0"},
+            "content": "",
+            "timestamp": "2024-01-01T12:00:00.000000+00:00",
+            "flags": 1 << 14,
+            "message_reference": {"type": 1, "message_id": "8", "channel_id": "9", "guild_id": "7"},
+            "message_snapshots": [{"message": {"content": "Mail ada@example.com"}}],
+        }]),
+    );
+    client.push_json("/channels/1/messages?limit=100&before=11", 200, json!([]));
+
+    let mut conn = db::connect_db(":memory:").unwrap();
+    conn.execute(
+        "INSERT INTO channel (id, guild_id, name) VALUES (1, NULL, 'general')",
+        [],
+    )
+    .unwrap();
+    let redactor = RedactArgs {
+        redact: true,
+        redact_patterns: Vec::new(),
+    }
+    .redactor();
+    let mut summary = ChannelSummary::new(Some(Snowflake(1)), None);
+    get_channel_messages(
+        &mut conn,
+        &client,
+        Snowflake(1),
+        None,
+        redactor.as_ref(),
+        &mut summary,
+    )
+    .unwrap();
+
+    let batch = std::fs::read_to_string(&output).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    assert!(!batch.contains("ada@example.com"), "{}", batch);
+    let batch: serde_json::Value = serde_json::from_str(&batch).unwrap();
+    assert_eq!(batch[0]["content"], "Mail [REDACTED EMAIL]");
+}
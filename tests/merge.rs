@@ -0,0 +1,91 @@
+mod common;
+
+use common::TempDb;
+use discord_scraper::db;
+
+use std::process::Output;
+
+fn merge(source: &TempDb, destination: &TempDb) -> Output {
+    common::run(&["merge", source.path(), "-d", destination.path()])
+}
+
+fn contents(conn: &rusqlite::Connection) -> Vec<(i64, i64, String)> {
+    conn.prepare("SELECT id, author_id, content FROM message ORDER BY id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn merges_new_rows_and_keeps_forgotten_users_erased() {
+    let source = TempDb::new("source");
+    let destination = TempDb::new("destination");
+    let src = source.create(&[
+        (100, 10, "Shared"),
+        (101, 10, "Edited later"),
+        (102, 20, "Only in the source, thanks <@10>"),
+        (103, 10, "Only in the source, by ada"),
+    ]);
+    src.execute(
+        "INSERT INTO flagged_user (user_id, reason, detail) VALUES (20, 'burst', '9 messages')",
+        [],
+    )
+    .unwrap();
+    drop(src);
+    // ada was forgotten in the destination after the source was copied off it.
+    let dest = destination.create(&[(100, 10, "Shared"), (101, 10, "Edited")]);
+    dest.execute(
+        "INSERT INTO forgotten_user (user_id, messages, anonymized) VALUES (10, 2, 0)",
+        [],
+    )
+    .unwrap();
+    drop(dest);
+
+    let output = merge(&source, &destination);
+    assert!(output.status.success(), "{:?}", output);
+    let log = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+    assert!(
+        log.contains("Messages with different content: 1 (keeping existing values), e.g. 101"),
+        "{}",
+        log
+    );
+
+    let conn = db::open_read_only(destination.path()).unwrap();
+    assert_eq!(
+        contents(&conn),
+        vec![(
+            102,
+            20,
+            "Only in the source, thanks @Deleted User".to_string()
+        )]
+    );
+    let users: Vec<i64> = conn
+        .prepare("SELECT id FROM user ORDER BY id")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(users, vec![20]);
+    let flags: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM flagged_user WHERE user_id = 20",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(flags, 1);
+}
+
+#[test]
+fn refuses_missing_sources() {
+    let source = TempDb::new("missing");
+    let destination = TempDb::new("kept");
+    drop(destination.create(&[(100, 10, "Kept")]));
+
+    let output = merge(&source, &destination);
+    assert!(!output.status.success());
+    assert!(!source.0.exists());
+}